@@ -13,7 +13,7 @@ use std::os::windows::process::CommandExt;
 use std::{ffi::OsStr, process::Command as StdCommand, sync::Arc, time::Duration};
 
 use super::{ClashCoreType, CommandEvent, CoreType, TerminatedPayload, utils::spawn_pipe_reader};
-use crate::os::ChildExt;
+use crate::os::{ChildExt, KillOutcome, ProcessMatcher};
 use crate::runtime::block_on;
 
 // const DETACHED_PROCESS: u32 = 0x00000008;
@@ -193,13 +193,12 @@ impl CoreInstance {
     }
 
     #[instrument(skip(self))]
-    async fn kill_instance_by_pid_file(&self) -> Result<(), std::io::Error> {
+    async fn kill_instance_by_pid_file(&self) -> Result<KillOutcome, std::io::Error> {
         tracing::debug!("kill instance by pid file: {:?}", self.pid_path);
-        crate::os::kill_by_pid_file(
-            &self.pid_path,
-            Some(&CoreType::get_supported_cores_executables()),
-        )
-        .await
+        let matcher = ProcessMatcher::new()
+            .executable(&self.binary_path)
+            .names(CoreType::get_supported_cores_executables());
+        crate::os::kill_by_pid_file_validated(&self.pid_path, &matcher).await
     }
 
     #[instrument(skip(self))]
@@ -219,8 +218,18 @@ impl CoreInstance {
             }
         }
         // kill instance by pid file if exists
-        if let Err(err) = self.kill_instance_by_pid_file().await {
-            tracing::error!("Failed to kill instance by pid file: {:?}", err);
+        match self.kill_instance_by_pid_file().await {
+            Ok(KillOutcome::Killed(killed)) => {
+                tracing::info!("killed leftover instance: {:?}", killed);
+            }
+            Ok(KillOutcome::Mismatched { executable }) => {
+                tracing::warn!(
+                    "pid file names an unrelated process {:?}, left it running",
+                    executable
+                );
+            }
+            Ok(_) => {}
+            Err(err) => tracing::error!("Failed to kill instance by pid file: {:?}", err),
         }
 
         let args = match self.core_type {
@@ -10,15 +10,93 @@ pub enum AtomicFsError {
     Io(#[from] std::io::Error),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// A single holder, excluding every other holder.
+    Exclusive,
+    /// Any number of holders, excluding an exclusive one.
+    Shared,
+}
+
+/// How a [`DirLock`] is taken. A purpose makes the holder publish a
+/// [`LockMetadata`] sidecar next to the lock file while it holds the lock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirLockOptions {
+    mode: LockMode,
+    purpose: Option<String>,
+}
+
+impl DirLockOptions {
+    pub fn exclusive() -> Self {
+        Self {
+            mode: LockMode::Exclusive,
+            purpose: None,
+        }
+    }
+
+    pub fn shared() -> Self {
+        Self {
+            mode: LockMode::Shared,
+            purpose: None,
+        }
+    }
+
+    pub fn purpose(mut self, purpose: impl Into<String>) -> Self {
+        self.purpose = Some(purpose.into());
+        self
+    }
+}
+
+/// Who holds a [`DirLock`], as published in its sidecar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockMetadata {
+    pub pid: u32,
+    pub mode: LockMode,
+    pub purpose: String,
+    pub acquired_at: std::time::SystemTime,
+    /// The holder's `ProcessIdentity::start_token`, which tells it apart from
+    /// a later process with the same pid. Only recorded with the `process`
+    /// feature.
+    pub start_token: Option<u64>,
+}
+
 #[derive(Debug)]
 pub struct DirLock {
     #[cfg(unix)]
     _file: nix::fcntl::Flock<std::fs::File>,
     #[cfg(not(unix))]
     _file: std::fs::File,
+    sidecar: Option<(PathBuf, LockMetadata)>,
 }
 
+impl DirLock {
+    /// The metadata this holder published, if it was given a purpose.
+    pub fn metadata(&self) -> Option<&LockMetadata> {
+        self.sidecar.as_ref().map(|(_, metadata)| metadata)
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        // Removed before the lock file closes, so no successor's sidecar is
+        // at stake.
+        if let Some((path, _)) = &self.sidecar {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Takes an exclusive lock without waiting.
 pub fn acquire_dir_lock(path: impl AsRef<Path>) -> Result<DirLock, AtomicFsError> {
+    acquire_dir_lock_with(path, &DirLockOptions::exclusive())
+}
+
+/// Takes a lock without waiting; fails with [`AtomicFsError::Contended`]
+/// while a conflicting holder has it.
+pub fn acquire_dir_lock_with(
+    path: impl AsRef<Path>,
+    options: &DirLockOptions,
+) -> Result<DirLock, AtomicFsError> {
     let path = path.as_ref();
     match std::fs::symlink_metadata(path) {
         Ok(metadata)
@@ -33,42 +111,290 @@ pub fn acquire_dir_lock(path: impl AsRef<Path>) -> Result<DirLock, AtomicFsError
         Err(error) => return Err(error.into()),
     }
 
+    let file = open_lock_file(path, options.mode).map_err(|error| {
+        if dir_lock_is_contended(&error) {
+            AtomicFsError::Contended(path.to_owned())
+        } else {
+            error.into()
+        }
+    })?;
+
+    #[cfg(unix)]
+    let file = {
+        let arg = match options.mode {
+            LockMode::Exclusive => nix::fcntl::FlockArg::LockExclusiveNonblock,
+            LockMode::Shared => nix::fcntl::FlockArg::LockSharedNonblock,
+        };
+        nix::fcntl::Flock::lock(file, arg).map_err(|(_, errno)| {
+            let error: std::io::Error = errno.into();
+            if dir_lock_is_contended(&error) {
+                AtomicFsError::Contended(path.to_owned())
+            } else {
+                error.into()
+            }
+        })?
+    };
+
+    let mut lock = DirLock {
+        _file: file,
+        sidecar: None,
+    };
+    if options.mode == LockMode::Exclusive {
+        // No other holder exists, so every sidecar left behind is stale, as
+        // is any half-written one.
+        for (sidecar, _) in sidecar_files(path)? {
+            let _ = std::fs::remove_file(sidecar);
+        }
+    }
+    if let Some(purpose) = &options.purpose {
+        let metadata = LockMetadata {
+            pid: std::process::id(),
+            mode: options.mode,
+            purpose: purpose.clone(),
+            acquired_at: std::time::SystemTime::now(),
+            start_token: own_start_token(),
+        };
+        let sidecar = sidecar_path(path, options.mode);
+        write_sidecar(&sidecar, &metadata)?;
+        lock.sidecar = Some((sidecar, metadata));
+    }
+    Ok(lock)
+}
+
+/// Retries [`acquire_dir_lock_with`] until it succeeds or `timeout` passes,
+/// then fails with [`AtomicFsError::Contended`].
+pub async fn acquire_with_timeout(
+    path: impl AsRef<Path>,
+    options: &DirLockOptions,
+    timeout: std::time::Duration,
+) -> Result<DirLock, AtomicFsError> {
+    const POLL: std::time::Duration = std::time::Duration::from_millis(25);
+
+    let path = path.as_ref();
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        match acquire_dir_lock_with(path, options) {
+            Err(AtomicFsError::Contended(_)) if tokio::time::Instant::now() < deadline => {
+                tokio::time::sleep(POLL).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The published holders of the lock at `path` whose process is still
+/// running. Holders that took the lock without a purpose are not listed.
+pub fn lock_holders(path: impl AsRef<Path>) -> std::io::Result<Vec<LockMetadata>> {
+    Ok(lock_sidecars(path.as_ref())?
+        .into_iter()
+        .filter_map(|(_, metadata)| metadata)
+        .filter(holder_is_running)
+        .collect())
+}
+
+#[cfg(feature = "process")]
+fn own_start_token() -> Option<u64> {
+    crate::process::ProcessIdentity::of(std::process::id())
+        .ok()
+        .flatten()
+        .map(|identity| identity.start_token())
+}
+
+#[cfg(not(feature = "process"))]
+fn own_start_token() -> Option<u64> {
+    None
+}
+
+/// Whether the process that published `metadata` still runs. A recorded
+/// start token must match, so a process that reused the pid does not count;
+/// if the process cannot be inspected, only its pid is checked.
+fn holder_is_running(metadata: &LockMetadata) -> bool {
+    #[cfg(feature = "process")]
+    if let Some(token) = metadata.start_token
+        && let Ok(identity) = crate::process::ProcessIdentity::of(metadata.pid)
+    {
+        return identity.is_some_and(|identity| identity.start_token() == token);
+    }
+    pid_is_running(metadata.pid)
+}
+
+#[cfg(unix)]
+fn pid_is_running(pid: u32) -> bool {
+    match crate::os::PidHandle::open(pid) {
+        Ok(handle) => handle.is_alive().unwrap_or(true),
+        Err(error) => error.kind() != std::io::ErrorKind::NotFound,
+    }
+}
+
+#[cfg(not(unix))]
+fn pid_is_running(pid: u32) -> bool {
+    use sysinfo::{Pid, ProcessRefreshKind, RefreshKind, System};
+
+    let kind = RefreshKind::nothing().with_processes(ProcessRefreshKind::nothing());
+    let mut system = System::new_with_specifics(kind);
+    system.refresh_specifics(kind);
+    system.process(Pid::from_u32(pid)).is_some()
+}
+
+fn open_lock_file(path: &Path, mode: LockMode) -> std::io::Result<std::fs::File> {
     let mut options = std::fs::OpenOptions::new();
     options.create(true).read(true).write(true);
     #[cfg(unix)]
     {
         use std::os::unix::fs::OpenOptionsExt;
         options.mode(0o600);
+        let _ = mode;
     }
     #[cfg(windows)]
     {
         use std::os::windows::fs::OpenOptionsExt;
+        const FILE_SHARE_READ: u32 = 0x1;
+        if mode == LockMode::Shared {
+            // Read-only handles that share reading coexist with each other,
+            // and conflict with the writable, unshared exclusive handle.
+            if let Err(error) = std::fs::symlink_metadata(path)
+                && error.kind() == std::io::ErrorKind::NotFound
+            {
+                drop(options.share_mode(0).open(path)?);
+            }
+            return std::fs::OpenOptions::new()
+                .read(true)
+                .share_mode(FILE_SHARE_READ)
+                .open(path);
+        }
         options.share_mode(0);
     }
-    let file = options.open(path).map_err(|error| {
-        if dir_lock_is_contended(&error) {
-            AtomicFsError::Contended(path.to_owned())
-        } else {
-            error.into()
+    #[cfg(not(any(unix, windows)))]
+    let _ = mode;
+    options.open(path)
+}
+
+fn sidecar_path(lock: &Path, mode: LockMode) -> PathBuf {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut name = lock.as_os_str().to_owned();
+    match mode {
+        LockMode::Exclusive => name.push(".owner"),
+        LockMode::Shared => name.push(format!(
+            ".reader-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        )),
+    }
+    PathBuf::from(name)
+}
+
+/// Every sidecar of `lock`, with its metadata if it parses.
+fn lock_sidecars(lock: &Path) -> std::io::Result<Vec<(PathBuf, Option<LockMetadata>)>> {
+    Ok(sidecar_files(lock)?
+        .into_iter()
+        .filter(|(_, temp)| !temp)
+        .map(|(path, _)| {
+            let metadata = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|raw| parse_sidecar(&raw));
+            (path, metadata)
+        })
+        .collect())
+}
+
+/// The sidecars of `lock` and the staging files they are written through,
+/// the latter flagged.
+fn sidecar_files(lock: &Path) -> std::io::Result<Vec<(PathBuf, bool)>> {
+    let Some(name) = lock.file_name().and_then(|name| name.to_str()) else {
+        return Ok(Vec::new());
+    };
+    let dir = match lock.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut sidecars = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(suffix) = file_name
+            .to_str()
+            .and_then(|entry| entry.strip_prefix(name))
+        else {
+            continue;
+        };
+        if suffix == ".owner" || suffix.starts_with(".owner.tmp") || suffix.starts_with(".reader-")
+        {
+            sidecars.push((entry.path(), suffix.contains(".tmp")));
         }
-    })?;
+    }
+    Ok(sidecars)
+}
+
+/// Publishes the sidecar through a staging file of its own. The staging file
+/// is created exclusively and without following symlinks, so nothing planted
+/// at its name is written through.
+fn write_sidecar(path: &Path, metadata: &LockMetadata) -> std::io::Result<()> {
+    use std::{
+        io::Write,
+        sync::atomic::{AtomicU64, Ordering},
+    };
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
 
+    let acquired_at = metadata
+        .acquired_at
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let mode = match metadata.mode {
+        LockMode::Exclusive => "exclusive",
+        LockMode::Shared => "shared",
+    };
+    let purpose = metadata.purpose.replace(['\r', '\n'], " ");
+    let mut raw = format!(
+        "pid={}\nmode={mode}\nacquired-at={acquired_at}\npurpose={purpose}\n",
+        metadata.pid
+    );
+    if let Some(token) = metadata.start_token {
+        raw.push_str(&format!("start-token={token}\n"));
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(
+        ".tmp-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp = PathBuf::from(temp);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
     #[cfg(unix)]
     {
-        let file = nix::fcntl::Flock::lock(file, nix::fcntl::FlockArg::LockExclusiveNonblock)
-            .map_err(|(_, errno)| {
-                let error: std::io::Error = errno.into();
-                if dir_lock_is_contended(&error) {
-                    AtomicFsError::Contended(path.to_owned())
-                } else {
-                    error.into()
-                }
-            })?;
-        Ok(DirLock { _file: file })
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600).custom_flags(libc::O_NOFOLLOW);
+    }
+    let mut file = options.open(&temp)?;
+    let result = file
+        .write_all(raw.as_bytes())
+        .and_then(|()| std::fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
     }
+    result
+}
 
-    #[cfg(not(unix))]
-    Ok(DirLock { _file: file })
+fn parse_sidecar(raw: &str) -> Option<LockMetadata> {
+    let field = |key: &str| {
+        raw.lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+    };
+    Some(LockMetadata {
+        pid: field("pid")?.parse().ok()?,
+        mode: match field("mode")? {
+            "exclusive" => LockMode::Exclusive,
+            "shared" => LockMode::Shared,
+            _ => return None,
+        },
+        purpose: field("purpose")?.to_owned(),
+        acquired_at: std::time::UNIX_EPOCH
+            + std::time::Duration::from_millis(field("acquired-at")?.parse().ok()?),
+        start_token: field("start-token").and_then(|token| token.parse().ok()),
+    })
 }
 
 #[cfg(unix)]
@@ -297,6 +623,131 @@ pub fn verify_windows_directory_acl(path: impl AsRef<Path>) -> Result<(), Atomic
     Ok(())
 }
 
+/// Restricts the directory at `path` to mode 0700, then checks it as
+/// [`verify_directory`] does. The directory must already belong to the
+/// effective user; ownership is never changed.
+#[cfg(unix)]
+pub fn harden_directory(
+    path: impl AsRef<Path>,
+    trust_root: impl AsRef<Path>,
+) -> Result<(), AtomicFsError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = path.as_ref();
+    let directory = open_owned_directory(path)?;
+    directory.set_permissions(std::fs::Permissions::from_mode(0o700))?;
+    drop(directory);
+    verify_directory(path, trust_root)
+}
+
+/// Checks that `path` is a directory (not a symlink) owned by the effective
+/// user with no group or other permissions, and that every ancestor strictly
+/// below `trust_root` is owned by root or the effective user and is either
+/// not group or world writable or has the sticky bit, as `/tmp` does. A
+/// shared parent without the sticky bit would let another user rename the
+/// directory away and plant a replacement.
+///
+/// `path` is taken as given, not canonicalized: it must lie lexically below
+/// `trust_root`, and it is walked one component at a time from there without
+/// following symlinks, so a symlinked component below the trust root is
+/// rejected and each check applies to the directory actually opened.
+#[cfg(unix)]
+pub fn verify_directory(
+    path: impl AsRef<Path>,
+    trust_root: impl AsRef<Path>,
+) -> Result<(), AtomicFsError> {
+    use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+
+    let path = std::path::absolute(path)?;
+    let trust_root = std::path::absolute(trust_root)?;
+    let relative = match path.strip_prefix(&trust_root) {
+        Ok(relative) if relative.components().next().is_some() => relative,
+        _ => return Err(AtomicFsError::UnsafePath(path)),
+    };
+    let euid = nix::unistd::geteuid().as_raw();
+    let mut directory = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY)
+        .open(&trust_root)?;
+    let mut current = trust_root.clone();
+    let mut components = relative.components().peekable();
+    while let Some(component) = components.next() {
+        let std::path::Component::Normal(name) = component else {
+            return Err(AtomicFsError::UnsafePath(path));
+        };
+        current.push(name);
+        directory = open_child_directory(&directory, name, &current)?;
+        let metadata = directory.metadata()?;
+        let unsafe_mode = if components.peek().is_none() {
+            metadata.uid() != euid || metadata.mode() & 0o077 != 0
+        } else {
+            let shared = metadata.mode() & 0o022 != 0;
+            let sticky = metadata.mode() & 0o1000 != 0;
+            (metadata.uid() != 0 && metadata.uid() != euid) || (shared && !sticky)
+        };
+        if unsafe_mode {
+            return Err(AtomicFsError::UnsafePath(current));
+        }
+    }
+    Ok(())
+}
+
+/// Opens the directory `name` inside `parent` without following a symlink;
+/// `path` names it in errors.
+#[cfg(unix)]
+fn open_child_directory(
+    parent: &std::fs::File,
+    name: &std::ffi::OsStr,
+    path: &Path,
+) -> Result<std::fs::File, AtomicFsError> {
+    use std::os::{
+        fd::{AsRawFd, FromRawFd},
+        unix::ffi::OsStrExt,
+    };
+
+    let name = std::ffi::CString::new(name.as_bytes())
+        .map_err(|_| AtomicFsError::UnsafePath(path.to_owned()))?;
+    // SAFETY: openat receives a live directory descriptor and a NUL-terminated
+    // name that outlive the call.
+    let fd = unsafe {
+        libc::openat(
+            parent.as_raw_fd(),
+            name.as_ptr(),
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+        )
+    };
+    if fd == -1 {
+        let error = std::io::Error::last_os_error();
+        return Err(match error.raw_os_error() {
+            Some(libc::ELOOP | libc::ENOTDIR) => AtomicFsError::UnsafePath(path.to_owned()),
+            _ => error.into(),
+        });
+    }
+    // SAFETY: openat returned a new descriptor that nothing else owns.
+    Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+/// Opens `path` without following a final symlink and checks that it is a
+/// directory owned by the effective user.
+#[cfg(unix)]
+fn open_owned_directory(path: &Path) -> Result<std::fs::File, AtomicFsError> {
+    use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+
+    let directory = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY | libc::O_NOFOLLOW)
+        .open(path)
+        .map_err(|error| match error.raw_os_error() {
+            Some(libc::ELOOP | libc::ENOTDIR) => AtomicFsError::UnsafePath(path.to_owned()),
+            _ => error.into(),
+        })?;
+    let metadata = directory.metadata()?;
+    if !metadata.is_dir() || metadata.uid() != nix::unistd::geteuid().as_raw() {
+        return Err(AtomicFsError::UnsafePath(path.to_owned()));
+    }
+    Ok(directory)
+}
+
 #[cfg(windows)]
 fn windows_io_error(error: windows::core::Error) -> std::io::Error {
     let code = error.code().0 as u32;
@@ -461,6 +912,186 @@ pub async fn sync_dir(_dir: impl AsRef<Path>) -> std::io::Result<()> {
     Ok(())
 }
 
+/// How [`write_atomic`] and [`AtomicFile`] publish their target.
+#[derive(Debug, Clone, Copy)]
+pub struct AtomicWriteOptions {
+    mode: Option<u32>,
+    create_new: bool,
+}
+
+impl Default for AtomicWriteOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtomicWriteOptions {
+    /// Replaces an existing regular file, with default permissions.
+    pub fn new() -> Self {
+        Self {
+            mode: None,
+            create_new: false,
+        }
+    }
+
+    /// Unix permission bits of the written file; ignored elsewhere.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Fails with [`std::io::ErrorKind::AlreadyExists`] instead of replacing
+    /// an existing target, including one that appears while writing.
+    pub fn create_new(mut self, create_new: bool) -> Self {
+        self.create_new = create_new;
+        self
+    }
+}
+
+/// Writes `bytes` to `path` through an [`AtomicFile`]: readers see either the
+/// old contents or all of `bytes`.
+pub async fn write_atomic(
+    path: impl AsRef<Path>,
+    bytes: impl AsRef<[u8]>,
+    options: AtomicWriteOptions,
+) -> Result<(), AtomicFsError> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut file = AtomicFile::create(path, options).await?;
+    file.write_all(bytes.as_ref()).await?;
+    file.commit().await
+}
+
+/// A file written to a sibling staging file and published on
+/// [`AtomicFile::commit`]. The staging file, named
+/// `{target}.tmp-{pid}-{counter}`, is removed if the writer is dropped first.
+#[derive(Debug)]
+pub struct AtomicFile {
+    target: PathBuf,
+    staging: PathBuf,
+    file: Option<tokio::fs::File>,
+    create_new: bool,
+}
+
+impl AtomicFile {
+    pub async fn create(
+        path: impl AsRef<Path>,
+        options: AtomicWriteOptions,
+    ) -> Result<Self, AtomicFsError> {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let target = path.as_ref().to_owned();
+        validate_write_target(&target, options.create_new).await?;
+        let mut staging = target.clone().into_os_string();
+        staging.push(format!(
+            ".tmp-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let staging = PathBuf::from(staging);
+        let mut open = tokio::fs::OpenOptions::new();
+        open.create_new(true).write(true);
+        #[cfg(unix)]
+        if let Some(mode) = options.mode {
+            open.mode(mode);
+        }
+        let file = open.open(&staging).await?;
+        #[cfg(unix)]
+        if let Some(mode) = options.mode {
+            // The umask may have narrowed the mode given at creation.
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(mode))
+                .await?;
+        }
+        Ok(Self {
+            target,
+            staging,
+            file: Some(file),
+            create_new: options.create_new,
+        })
+    }
+
+    /// The path the file is published at.
+    pub fn path(&self) -> &Path {
+        &self.target
+    }
+
+    /// Syncs the data, publishes it at the target and syncs the parent
+    /// directory.
+    pub async fn commit(mut self) -> Result<(), AtomicFsError> {
+        use tokio::io::AsyncWriteExt;
+
+        let Some(mut file) = self.file.take() else {
+            return Err(std::io::Error::other("atomic file already committed").into());
+        };
+        file.flush().await?;
+        file.sync_all().await?;
+        drop(file);
+        validate_write_target(&self.target, self.create_new).await?;
+        if self.create_new {
+            atomic_move_new(&self.staging, &self.target).await?;
+        } else {
+            atomic_replace(&self.staging, &self.target).await?;
+        }
+        let parent = match self.target.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        sync_dir(parent).await?;
+        Ok(())
+    }
+}
+
+impl tokio::io::AsyncWrite for AtomicFile {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        std::pin::Pin::new(self.file_mut()?).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(self.file_mut()?).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(self.file_mut()?).poll_shutdown(cx)
+    }
+}
+
+impl AtomicFile {
+    fn file_mut(&mut self) -> std::io::Result<&mut tokio::fs::File> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| std::io::Error::other("atomic file already committed"))
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // After a successful commit the staging name no longer exists.
+        let _ = std::fs::remove_file(&self.staging);
+    }
+}
+
+async fn validate_write_target(path: &Path, create_new: bool) -> Result<(), AtomicFsError> {
+    if create_new {
+        return validate_absent_regular_target(path).await;
+    }
+    match validate_existing_regular_target(path).await {
+        Err(AtomicFsError::Io(error)) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -501,6 +1132,47 @@ mod tests {
         assert!(!source.exists());
     }
 
+    #[tokio::test]
+    async fn write_atomic_replaces_and_respects_create_new() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("config.yaml");
+        write_atomic(&target, b"old", AtomicWriteOptions::new().create_new(true))
+            .await
+            .unwrap();
+        let error = write_atomic(&target, b"new", AtomicWriteOptions::new().create_new(true))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            AtomicFsError::Io(error) if error.kind() == std::io::ErrorKind::AlreadyExists
+        ));
+        write_atomic(&target, b"new", AtomicWriteOptions::new().mode(0o600))
+            .await
+            .unwrap();
+        assert_eq!(tokio::fs::read(&target).await.unwrap(), b"new");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&target).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn dropped_atomic_file_leaves_no_staging_file() {
+        use tokio::io::AsyncWriteExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("state");
+        let mut file = AtomicFile::create(&target, AtomicWriteOptions::new())
+            .await
+            .unwrap();
+        file.write_all(b"partial").await.unwrap();
+        drop(file);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
     #[tokio::test]
     async fn sync_dir_succeeds_for_real_directory() {
         let dir = tempfile::tempdir().unwrap();
@@ -532,6 +1204,222 @@ mod tests {
         acquire_dir_lock(path).unwrap();
     }
 
+    #[test]
+    fn shared_locks_coexist_and_exclude_a_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lock");
+        let first = acquire_dir_lock_with(&path, &DirLockOptions::shared()).unwrap();
+        let second = acquire_dir_lock_with(&path, &DirLockOptions::shared()).unwrap();
+
+        assert!(matches!(
+            acquire_dir_lock(&path).unwrap_err(),
+            AtomicFsError::Contended(_)
+        ));
+
+        drop((first, second));
+        let writer = acquire_dir_lock(&path).unwrap();
+        assert!(matches!(
+            acquire_dir_lock_with(&path, &DirLockOptions::shared()).unwrap_err(),
+            AtomicFsError::Contended(_)
+        ));
+        drop(writer);
+    }
+
+    #[test]
+    fn lock_metadata_is_published_while_held() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lock");
+        let reader =
+            acquire_dir_lock_with(&path, &DirLockOptions::shared().purpose("list")).unwrap();
+        let metadata = reader.metadata().unwrap().clone();
+        assert_eq!(metadata.pid, std::process::id());
+        assert_eq!(metadata.mode, LockMode::Shared);
+        assert_eq!(metadata.purpose, "list");
+
+        let holders = lock_holders(&path).unwrap();
+        assert_eq!(holders.len(), 1);
+        assert_eq!(holders[0].purpose, "list");
+        assert_eq!(holders[0].mode, LockMode::Shared);
+
+        drop(reader);
+        assert!(lock_holders(&path).unwrap().is_empty());
+        let writer = acquire_dir_lock(&path).unwrap();
+        assert!(writer.metadata().is_none());
+        assert!(lock_holders(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn exclusive_holder_removes_stale_sidecars() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lock");
+        let stale = dir.path().join("lock.reader-4294967295-0");
+        std::fs::write(&stale, "pid=4294967295\nmode=shared\n").unwrap();
+
+        let _writer =
+            acquire_dir_lock_with(&path, &DirLockOptions::exclusive().purpose("sweep")).unwrap();
+        assert!(!stale.exists());
+        let holders = lock_holders(&path).unwrap();
+        assert_eq!(holders.len(), 1);
+        assert_eq!(holders[0].mode, LockMode::Exclusive);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sidecar_staging_files_are_never_written_through() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lock");
+        let victim = dir.path().join("victim");
+        std::fs::write(&victim, "keep").unwrap();
+        let planted = [
+            dir.path().join("lock.owner.tmp"),
+            dir.path().join("lock.reader-1-0.tmp"),
+        ];
+        for link in &planted {
+            std::os::unix::fs::symlink(&victim, link).unwrap();
+        }
+
+        let writer =
+            acquire_dir_lock_with(&path, &DirLockOptions::exclusive().purpose("sweep")).unwrap();
+        assert!(planted.iter().all(|link| link.symlink_metadata().is_err()));
+        drop(writer);
+        let _reader =
+            acquire_dir_lock_with(&path, &DirLockOptions::shared().purpose("list")).unwrap();
+        assert_eq!(std::fs::read_to_string(&victim).unwrap(), "keep");
+        assert!(sidecar_files(&path).unwrap().iter().all(|(_, temp)| !temp));
+    }
+
+    #[test]
+    fn holders_whose_pid_was_reused_are_not_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lock");
+        let _reader =
+            acquire_dir_lock_with(&path, &DirLockOptions::shared().purpose("list")).unwrap();
+        let mut reused = lock_holders(&path).unwrap().remove(0);
+        assert!(holder_is_running(&reused));
+
+        if let Some(token) = reused.start_token {
+            reused.start_token = Some(token.wrapping_add(1));
+            assert!(!holder_is_running(&reused));
+        }
+    }
+
+    #[tokio::test]
+    async fn acquire_with_timeout_waits_for_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lock");
+        let writer = acquire_dir_lock(&path).unwrap();
+
+        assert!(matches!(
+            acquire_with_timeout(
+                &path,
+                &DirLockOptions::shared(),
+                std::time::Duration::from_millis(100)
+            )
+            .await
+            .unwrap_err(),
+            AtomicFsError::Contended(_)
+        ));
+
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            drop(writer);
+        });
+        acquire_with_timeout(
+            &path,
+            &DirLockOptions::shared(),
+            std::time::Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+        release.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn harden_directory_restricts_mode_and_verifies() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("runtime");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(matches!(
+            verify_directory(&dir, root.path()).unwrap_err(),
+            AtomicFsError::UnsafePath(path) if path == dir
+        ));
+
+        harden_directory(&dir, root.path()).unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        verify_directory(&dir, root.path()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_directory_rejects_symlinks_and_paths_outside_the_trust_root() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("runtime");
+        std::fs::create_dir(&dir).unwrap();
+        harden_directory(&dir, root.path()).unwrap();
+        let link = root.path().join("link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+
+        assert!(matches!(
+            verify_directory(&link, root.path()).unwrap_err(),
+            AtomicFsError::UnsafePath(_)
+        ));
+        assert!(matches!(
+            verify_directory(&dir, &dir).unwrap_err(),
+            AtomicFsError::UnsafePath(_)
+        ));
+        let other = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            verify_directory(&dir, other.path()).unwrap_err(),
+            AtomicFsError::UnsafePath(_)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_directory_rejects_symlinked_ancestors() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("real").join("runtime");
+        std::fs::create_dir_all(&dir).unwrap();
+        harden_directory(&dir, root.path()).unwrap();
+        let alias = root.path().join("alias");
+        std::os::unix::fs::symlink(root.path().join("real"), &alias).unwrap();
+
+        assert!(matches!(
+            verify_directory(alias.join("runtime"), root.path()).unwrap_err(),
+            AtomicFsError::UnsafePath(path) if path == alias
+        ));
+        assert!(matches!(
+            verify_directory(root.path().join("real/../real/runtime"), root.path()).unwrap_err(),
+            AtomicFsError::UnsafePath(_)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_directory_requires_sticky_shared_ancestors() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let shared = root.path().join("shared");
+        let dir = shared.join("runtime");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o755)).unwrap();
+        harden_directory(&dir, root.path()).unwrap();
+        std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o777)).unwrap();
+
+        assert!(matches!(
+            verify_directory(&dir, root.path()).unwrap_err(),
+            AtomicFsError::UnsafePath(path) if path.ends_with("shared")
+        ));
+        std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o1777)).unwrap();
+        verify_directory(&dir, root.path()).unwrap();
+    }
+
     #[cfg(windows)]
     #[test]
     fn windows_directory_acl_round_trips() {
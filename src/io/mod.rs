@@ -6,6 +6,10 @@
 
 #[cfg(feature = "atomic_fs")]
 pub mod atomic_fs;
+#[cfg(feature = "store")]
+pub mod store;
+#[cfg(feature = "watch")]
+pub mod watch;
 
 use std::convert::Infallible;
 use std::io::BufRead;
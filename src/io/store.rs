@@ -0,0 +1,520 @@
+//! Durable, versioned storage of one serde value in a YAML, JSON or TOML file.
+//!
+//! Every save goes through [`atomic_fs::write_atomic`], so the file holds
+//! either the old or the new document after a crash. The replaced document
+//! is kept as a `{file}.bak-{unix millis}` sibling, of which the newest
+//! [`Store::backups`] survive. Documents carry a top-level version field;
+//! [`Store::load`] runs the registered migrations up to [`Store::version`]
+//! and, when the file does not load, falls back to the newest backup that
+//! does and restores it.
+
+use std::{
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use super::atomic_fs::{self, AtomicFsError, AtomicWriteOptions};
+
+const DEFAULT_BACKUPS: usize = 3;
+const DEFAULT_VERSION_KEY: &str = "version";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl Format {
+    /// Picks the format from the file extension.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "yaml" | "yml" => Some(Self::Yaml),
+            "json" => Some(Self::Json),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    fn decode(self, raw: &str) -> Result<Value, String> {
+        match self {
+            Self::Yaml => serde_yaml::from_str(raw).map_err(|error| error.to_string()),
+            Self::Json => serde_json::from_str(raw).map_err(|error| error.to_string()),
+            Self::Toml => toml::from_str(raw).map_err(|error| error.to_string()),
+        }
+    }
+
+    fn encode(self, value: &mut Value) -> Result<String, String> {
+        match self {
+            Self::Yaml => serde_yaml::to_string(value).map_err(|error| error.to_string()),
+            Self::Json => serde_json::to_string_pretty(value).map_err(|error| error.to_string()),
+            Self::Toml => {
+                strip_nulls(value);
+                toml::to_string_pretty(value).map_err(|error| error.to_string())
+            }
+        }
+    }
+}
+
+/// TOML has no null, so `None` fields are left out of the table, which is
+/// how serde reads them back anyway.
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, field| !field.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("failed to parse {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+    #[error("failed to encode the document: {0}")]
+    Encode(String),
+    #[error("the value has a top-level `{0}` field, which holds the document version")]
+    VersionKeyCollision(String),
+    #[error("the value is not a map, so it cannot hold the `{0}` version field")]
+    UnversionableValue(String),
+    #[error("document version {found} is newer than the supported {supported}")]
+    UnsupportedVersion { found: u32, supported: u32 },
+    #[error("migration from version {from} failed: {message}")]
+    Migration { from: u32, message: String },
+    #[error(transparent)]
+    AtomicFs(#[from] AtomicFsError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Upgrades a document by one version, in its generic form.
+pub type Migration = Box<dyn Fn(&mut Value) -> Result<(), String> + Send + Sync>;
+
+/// A value read by [`Store::load`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loaded<T> {
+    pub value: T,
+    /// The version the document had on disk, if it was migrated.
+    pub migrated_from: Option<u32>,
+    /// The backup the document was restored from, if the file did not load.
+    pub recovered_from: Option<PathBuf>,
+}
+
+pub struct Store<T> {
+    path: PathBuf,
+    format: Format,
+    backups: usize,
+    version: u32,
+    version_key: String,
+    migrations: Vec<(u32, Migration)>,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> std::fmt::Debug for Store<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Store")
+            .field("path", &self.path)
+            .field("format", &self.format)
+            .field("backups", &self.backups)
+            .field("version", &self.version)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Store<T> {
+    /// A store at version 0, keeping three backups.
+    pub fn new(path: impl Into<PathBuf>, format: Format) -> Self {
+        Self {
+            path: path.into(),
+            format,
+            backups: DEFAULT_BACKUPS,
+            version: 0,
+            version_key: DEFAULT_VERSION_KEY.into(),
+            migrations: Vec::new(),
+            _value: PhantomData,
+        }
+    }
+
+    /// How many replaced documents to keep; 0 disables backups.
+    pub fn backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// The version written on save and migrated to on load. Above 0 only
+    /// values that serialize as a map can be saved.
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// The top-level field holding the version; `version` by default. Saving
+    /// a value that has a field of the same name fails with
+    /// [`StoreError::VersionKeyCollision`].
+    pub fn version_key(mut self, key: impl Into<String>) -> Self {
+        self.version_key = key.into();
+        self
+    }
+
+    /// Registers the upgrade from version `from` to `from + 1`. A document
+    /// without the version field counts as version 0.
+    pub fn migration<F>(mut self, from: u32, migration: F) -> Self
+    where
+        F: Fn(&mut Value) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.migrations.push((from, Box::new(migration)));
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads the document, or `None` if the file does not exist. A migrated
+    /// or recovered document is saved back before it is returned.
+    pub async fn load(&self) -> Result<Option<Loaded<T>>, StoreError> {
+        let error = match self.load_file(&self.path).await {
+            Ok(None) => return Ok(None),
+            Ok(Some((value, migrated_from))) => {
+                if migrated_from.is_some() {
+                    self.save(&value).await?;
+                }
+                return Ok(Some(Loaded {
+                    value,
+                    migrated_from,
+                    recovered_from: None,
+                }));
+            }
+            Err(error @ StoreError::UnsupportedVersion { .. }) => return Err(error),
+            Err(error) => error,
+        };
+        tracing::warn!("{}: {error}; trying backups", self.path.display());
+        for backup in self.list_backups().await?.into_iter().rev() {
+            match self.load_file(&backup).await {
+                Ok(Some((value, migrated_from))) => {
+                    tracing::warn!(
+                        "restoring {} from {}",
+                        self.path.display(),
+                        backup.display()
+                    );
+                    self.write(&value).await?;
+                    return Ok(Some(Loaded {
+                        value,
+                        migrated_from,
+                        recovered_from: Some(backup),
+                    }));
+                }
+                Ok(None) => {}
+                Err(error) => tracing::warn!("skipping backup {}: {error}", backup.display()),
+            }
+        }
+        Err(error)
+    }
+
+    /// Backs up the current document if it still loads, then replaces it.
+    pub async fn save(&self, value: &T) -> Result<(), StoreError> {
+        if self.backups > 0
+            && let Some(current) = self.read_valid().await
+        {
+            self.write_backup(current).await?;
+            self.prune_backups().await?;
+        }
+        self.write(value).await
+    }
+
+    /// The backups, oldest first.
+    pub async fn list_backups(&self) -> Result<Vec<PathBuf>, StoreError> {
+        let (dir, prefix) = self.backup_prefix();
+        let mut backups = Vec::new();
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let stamp = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(|stamp| stamp.parse::<u128>().ok());
+            if let Some(stamp) = stamp {
+                backups.push((stamp, entry.path()));
+            }
+        }
+        backups.sort();
+        Ok(backups.into_iter().map(|(_, path)| path).collect())
+    }
+
+    async fn write(&self, value: &T) -> Result<(), StoreError> {
+        let mut document =
+            serde_json::to_value(value).map_err(|error| StoreError::Encode(error.to_string()))?;
+        match &mut document {
+            Value::Object(map) => {
+                if map.contains_key(&self.version_key) {
+                    return Err(StoreError::VersionKeyCollision(self.version_key.clone()));
+                }
+                map.insert(self.version_key.clone(), self.version.into());
+            }
+            // Loading reads a missing version field as version 0.
+            _ if self.version == 0 => {}
+            _ => return Err(StoreError::UnversionableValue(self.version_key.clone())),
+        }
+        let raw = self
+            .format
+            .encode(&mut document)
+            .map_err(StoreError::Encode)?;
+        atomic_fs::write_atomic(&self.path, raw, AtomicWriteOptions::new()).await?;
+        Ok(())
+    }
+
+    /// Parses, migrates and deserializes the document at `path`.
+    async fn load_file(&self, path: &Path) -> Result<Option<(T, Option<u32>)>, StoreError> {
+        let raw = match tokio::fs::read_to_string(path).await {
+            Ok(raw) => raw,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        let parse_error = |message: String| StoreError::Parse {
+            path: path.to_owned(),
+            message,
+        };
+        let mut document = self.format.decode(&raw).map_err(parse_error)?;
+        let found = match document.get(&self.version_key) {
+            None => 0,
+            Some(version) => version
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| parse_error(format!("invalid `{}` field", self.version_key)))?,
+        };
+        if found > self.version {
+            return Err(StoreError::UnsupportedVersion {
+                found,
+                supported: self.version,
+            });
+        }
+        for from in found..self.version {
+            let (_, migration) = self
+                .migrations
+                .iter()
+                .find(|(version, _)| *version == from)
+                .ok_or_else(|| StoreError::Migration {
+                    from,
+                    message: "no migration registered".into(),
+                })?;
+            migration(&mut document).map_err(|message| StoreError::Migration { from, message })?;
+        }
+        if let Value::Object(map) = &mut document {
+            map.remove(&self.version_key);
+        }
+        let value =
+            serde_json::from_value(document).map_err(|error| parse_error(error.to_string()))?;
+        Ok(Some((value, (found < self.version).then_some(found))))
+    }
+
+    /// The current file, if it loads; a corrupt file is not worth a backup.
+    async fn read_valid(&self) -> Option<String> {
+        let raw = tokio::fs::read_to_string(&self.path).await.ok()?;
+        matches!(self.load_file(&self.path).await, Ok(Some(_))).then_some(raw)
+    }
+
+    fn backup_prefix(&self) -> (PathBuf, String) {
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+            _ => PathBuf::from("."),
+        };
+        let name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        (dir, format!("{name}.bak-"))
+    }
+
+    /// Writes `current` to the first free backup stamp. The create-new write
+    /// itself claims the stamp, so a concurrent save moves on to the next one.
+    async fn write_backup(&self, current: String) -> Result<(), StoreError> {
+        let mut stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let (dir, prefix) = self.backup_prefix();
+        loop {
+            let path = dir.join(format!("{prefix}{stamp}"));
+            match atomic_fs::write_atomic(
+                &path,
+                &current,
+                AtomicWriteOptions::new().create_new(true),
+            )
+            .await
+            {
+                Err(AtomicFsError::Io(error))
+                    if error.kind() == std::io::ErrorKind::AlreadyExists =>
+                {
+                    stamp += 1;
+                }
+                result => return Ok(result?),
+            }
+        }
+    }
+
+    async fn prune_backups(&self) -> Result<(), StoreError> {
+        let backups = self.list_backups().await?;
+        let excess = backups.len().saturating_sub(self.backups);
+        for backup in &backups[..excess] {
+            atomic_fs::remove_regular_file(backup).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Settings {
+        port: u16,
+        name: String,
+    }
+
+    fn settings(port: u16) -> Settings {
+        Settings {
+            port,
+            name: "nyanpasu".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn every_format_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["settings.yaml", "settings.json", "settings.toml"] {
+            let path = dir.path().join(file);
+            let store = Store::<Settings>::new(&path, Format::from_path(&path).unwrap()).version(2);
+            assert!(store.load().await.unwrap().is_none());
+            store.save(&settings(7890)).await.unwrap();
+            let loaded = store.load().await.unwrap().unwrap();
+            assert_eq!(loaded.value, settings(7890));
+            assert_eq!(loaded.migrated_from, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn toml_leaves_out_unset_options() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Profile {
+            name: String,
+            proxy: Option<String>,
+            nested: Vec<Option<Settings>>,
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::<Profile>::new(dir.path().join("profile.toml"), Format::Toml);
+        let profile = Profile {
+            name: "default".into(),
+            proxy: None,
+            nested: vec![Some(settings(1))],
+        };
+        store.save(&profile).await.unwrap();
+        assert_eq!(store.load().await.unwrap().unwrap().value, profile);
+    }
+
+    #[tokio::test]
+    async fn values_may_not_shadow_the_version_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::<Settings>::new(dir.path().join("settings.json"), Format::Json)
+            .version_key("port");
+        assert!(matches!(
+            store.save(&settings(1)).await.unwrap_err(),
+            StoreError::VersionKeyCollision(key) if key == "port"
+        ));
+        assert!(!store.path().exists());
+    }
+
+    #[tokio::test]
+    async fn only_maps_carry_a_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ports.json");
+        let unversioned = Store::<Vec<u16>>::new(&path, Format::Json);
+        unversioned.save(&vec![1, 2]).await.unwrap();
+        assert_eq!(unversioned.load().await.unwrap().unwrap().value, [1, 2]);
+
+        let versioned = Store::<Vec<u16>>::new(&path, Format::Json).version(1);
+        assert!(matches!(
+            versioned.save(&vec![3]).await.unwrap_err(),
+            StoreError::UnversionableValue(key) if key == "version"
+        ));
+        assert_eq!(unversioned.load().await.unwrap().unwrap().value, [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn concurrent_saves_claim_distinct_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::<Settings>::new(dir.path().join("settings.json"), Format::Json)
+            .backups(usize::MAX);
+        store.save(&settings(0)).await.unwrap();
+        let (first, second, third) = tokio::join!(
+            store.save(&settings(1)),
+            store.save(&settings(2)),
+            store.save(&settings(3)),
+        );
+        first.unwrap();
+        second.unwrap();
+        third.unwrap();
+        assert_eq!(store.list_backups().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn backups_are_pruned_and_used_for_recovery() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        let store = Store::<Settings>::new(&path, Format::Json).backups(2);
+        for port in 1..=4 {
+            store.save(&settings(port)).await.unwrap();
+        }
+        assert_eq!(store.list_backups().await.unwrap().len(), 2);
+
+        // A torn write, as left by a plain `fs::write` on power loss.
+        std::fs::write(&path, r#"{"port": 4, "na"#).unwrap();
+        let loaded = store.load().await.unwrap().unwrap();
+        assert_eq!(loaded.value, settings(3));
+        assert!(loaded.recovered_from.is_some());
+        assert_eq!(
+            store.load().await.unwrap().unwrap().recovered_from,
+            None,
+            "the main file was not restored"
+        );
+    }
+
+    #[tokio::test]
+    async fn migrations_run_in_order_and_are_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.yaml");
+        std::fs::write(&path, "mixed-port: 7890\n").unwrap();
+        let store = Store::<Settings>::new(&path, Format::Yaml)
+            .version(2)
+            .migration(0, |doc| {
+                let port = doc["mixed-port"].take();
+                doc["port"] = port;
+                Ok(())
+            })
+            .migration(1, |doc| {
+                doc["name"] = "nyanpasu".into();
+                Ok(())
+            });
+        let loaded = store.load().await.unwrap().unwrap();
+        assert_eq!(loaded.value, settings(7890));
+        assert_eq!(loaded.migrated_from, Some(0));
+        assert_eq!(store.load().await.unwrap().unwrap().migrated_from, None);
+
+        let newer = Store::<Settings>::new(&path, Format::Yaml).version(1);
+        assert!(matches!(
+            newer.load().await.unwrap_err(),
+            StoreError::UnsupportedVersion {
+                found: 2,
+                supported: 1
+            }
+        ));
+    }
+}
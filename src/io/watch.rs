@@ -0,0 +1,614 @@
+//! Debounced change notifications for files and directories.
+//!
+//! A [`Watcher`] watches a set of files (such as a profile YAML) and
+//! directories (such as a runtime directory, non-recursively). On Linux it
+//! uses inotify; elsewhere, when inotify is unavailable or a watch is lost,
+//! it polls. Raw events for a path are coalesced until the path has been
+//! quiet for the debounce period, or for at most the maximum latency after
+//! its first event, then compared with the path's state before the burst. An atomic replace (a staging file renamed over the target) thus
+//! yields a single [`FsChangeKind::Modified`], and a staging file that comes
+//! and goes within the burst yields nothing.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use tokio::{sync::mpsc, task::JoinHandle, time::Instant};
+
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(200);
+const DEFAULT_MAX_LATENCY: Duration = Duration::from_secs(2);
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const CHANNEL_CAPACITY: usize = 64;
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FsChangeKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FsChange {
+    pub path: PathBuf,
+    pub kind: FsChangeKind,
+}
+
+/// How a [`Watcher`] debounces and polls.
+#[derive(Debug, Clone, Copy)]
+pub struct WatchOptions {
+    debounce: Duration,
+    max_latency: Duration,
+    poll_interval: Duration,
+    force_polling: bool,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WatchOptions {
+    pub fn new() -> Self {
+        Self {
+            debounce: DEFAULT_DEBOUNCE,
+            max_latency: DEFAULT_MAX_LATENCY,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            force_polling: false,
+        }
+    }
+
+    /// How long a path must stay quiet before its change is reported.
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// How long after its first event a path that never goes quiet, such as
+    /// a log being appended to, is reported anyway.
+    pub fn max_latency(mut self, max_latency: Duration) -> Self {
+        self.max_latency = max_latency;
+        self
+    }
+
+    /// How often the polling backend rescans.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Polls even where inotify is available.
+    pub fn force_polling(mut self, force_polling: bool) -> Self {
+        self.force_polling = force_polling;
+        self
+    }
+}
+
+/// Reports [`FsChange`]s from a background task, which stops when the
+/// watcher is dropped.
+#[derive(Debug)]
+pub struct Watcher {
+    changes: mpsc::Receiver<FsChange>,
+    task: JoinHandle<()>,
+}
+
+impl Watcher {
+    /// Watches `paths`. An existing directory is watched for its direct
+    /// entries other than subdirectories; any other path is watched as a
+    /// file, which may not exist yet. Must be called within a Tokio runtime.
+    pub fn new(paths: impl IntoIterator<Item = impl Into<PathBuf>>, options: WatchOptions) -> Self {
+        let targets = Targets::new(paths);
+        // Armed first: a change between the snapshot and the watch would
+        // otherwise be neither in the baseline nor reported.
+        let backend = Backend::new(&targets, &options);
+        let state = targets.snapshot();
+        let (sender, changes) = mpsc::channel(CHANNEL_CAPACITY);
+        let task = tokio::spawn(run(targets, state, backend, options, sender));
+        Self { changes, task }
+    }
+
+    /// The next debounced change; `None` once the background task stopped.
+    pub async fn next(&mut self) -> Option<FsChange> {
+        self.changes.recv().await
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn run(
+    targets: Targets,
+    mut state: HashMap<PathBuf, Fingerprint>,
+    mut backend: Backend,
+    options: WatchOptions,
+    sender: mpsc::Sender<FsChange>,
+) {
+    let mut pending = HashMap::<PathBuf, Pending>::new();
+    loop {
+        let flush_at = pending.values().map(|burst| burst.due(&options)).min();
+        tokio::select! {
+            event = backend.wait() => match event {
+                Ok(Event::Paths(paths)) => {
+                    let now = Instant::now();
+                    for path in paths {
+                        pending
+                            .entry(path)
+                            .and_modify(|burst| burst.last = now)
+                            .or_insert(Pending { first: now, last: now });
+                    }
+                }
+                Ok(Event::Rescan) => rescan(&targets, &state, &mut pending),
+                Err(error) => {
+                    tracing::warn!("filesystem watch failed, polling instead: {error}");
+                    backend = Backend::polling(&options);
+                    rescan(&targets, &state, &mut pending);
+                }
+            },
+            _ = sleep_until(flush_at), if flush_at.is_some() => {
+                let now = Instant::now();
+                let mut due = pending
+                    .iter()
+                    .filter(|(_, burst)| burst.due(&options) <= now)
+                    .map(|(path, _)| path.clone())
+                    .collect::<Vec<_>>();
+                due.sort();
+                for path in due {
+                    pending.remove(&path);
+                    let Some(change) = settle(&mut state, path) else {
+                        continue;
+                    };
+                    if sender.send(change).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            _ = sender.closed() => return,
+        }
+    }
+}
+
+/// When a pending path's burst of events started and when it last saw one.
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    first: Instant,
+    last: Instant,
+}
+
+impl Pending {
+    fn due(&self, options: &WatchOptions) -> Instant {
+        (self.last + options.debounce).min(self.first + options.max_latency)
+    }
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Marks every path whose state differs from the last reported one. A path
+/// already pending keeps its timestamps, so repeated polls cannot postpone it
+/// at all.
+fn rescan(
+    targets: &Targets,
+    state: &HashMap<PathBuf, Fingerprint>,
+    pending: &mut HashMap<PathBuf, Pending>,
+) {
+    let now = Instant::now();
+    let current = targets.snapshot();
+    let changed = current
+        .iter()
+        .filter(|(path, fingerprint)| state.get(*path) != Some(*fingerprint))
+        .map(|(path, _)| path)
+        .chain(state.keys().filter(|path| !current.contains_key(*path)));
+    for path in changed {
+        pending.entry(path.clone()).or_insert(Pending {
+            first: now,
+            last: now,
+        });
+    }
+}
+
+/// Compares `path` with its last reported state and records the new one.
+fn settle(state: &mut HashMap<PathBuf, Fingerprint>, path: PathBuf) -> Option<FsChange> {
+    let after = Fingerprint::of(&path);
+    let kind = match (state.get(&path), &after) {
+        (None, Some(_)) => FsChangeKind::Created,
+        (Some(before), Some(after)) if before != after => FsChangeKind::Modified,
+        (Some(_), None) => FsChangeKind::Removed,
+        _ => return None,
+    };
+    match after {
+        Some(after) => state.insert(path.clone(), after),
+        None => state.remove(&path),
+    };
+    Some(FsChange { path, kind })
+}
+
+/// Enough metadata to tell a rewritten or replaced file from an untouched
+/// one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Fingerprint {
+    len: u64,
+    modified: Option<SystemTime>,
+    #[cfg(unix)]
+    inode: (u64, u64),
+    #[cfg(unix)]
+    changed: (i64, i64),
+}
+
+impl Fingerprint {
+    /// `None` for a missing path and for a directory.
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::symlink_metadata(path).ok()?;
+        if metadata.is_dir() {
+            return None;
+        }
+        #[cfg(unix)]
+        use std::os::unix::fs::MetadataExt;
+        Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            #[cfg(unix)]
+            inode: (metadata.dev(), metadata.ino()),
+            #[cfg(unix)]
+            changed: (metadata.ctime(), metadata.ctime_nsec()),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Targets {
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl Targets {
+    fn new(paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        let mut targets = Self {
+            files: HashSet::new(),
+            dirs: HashSet::new(),
+        };
+        for path in paths {
+            let path = path.into();
+            if path.is_dir() {
+                targets.dirs.insert(path);
+            } else {
+                targets.files.insert(path);
+            }
+        }
+        targets
+    }
+
+    fn snapshot(&self) -> HashMap<PathBuf, Fingerprint> {
+        let entries = self
+            .dirs
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| Some(entry.ok()?.path()));
+        self.files
+            .iter()
+            .cloned()
+            .chain(entries)
+            .filter_map(|path| Some((path.clone(), Fingerprint::of(&path)?)))
+            .collect()
+    }
+}
+
+enum Event {
+    Paths(Vec<PathBuf>),
+    Rescan,
+}
+
+enum Backend {
+    #[cfg(target_os = "linux")]
+    Inotify(inotify::InotifyBackend),
+    Polling(tokio::time::Interval),
+}
+
+impl Backend {
+    fn new(targets: &Targets, options: &WatchOptions) -> Self {
+        #[cfg(target_os = "linux")]
+        {
+            if !options.force_polling {
+                match inotify::InotifyBackend::new(targets) {
+                    Ok(backend) => return Self::Inotify(backend),
+                    Err(error) => tracing::warn!("inotify unavailable, polling instead: {error}"),
+                }
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (targets, options.force_polling);
+        Self::polling(options)
+    }
+
+    fn polling(options: &WatchOptions) -> Self {
+        let mut interval = tokio::time::interval(options.poll_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        Self::Polling(interval)
+    }
+
+    async fn wait(&mut self) -> std::io::Result<Event> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Self::Inotify(backend) => backend.wait().await,
+            Self::Polling(interval) => {
+                interval.tick().await;
+                Ok(Event::Rescan)
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::{
+        collections::{HashMap, HashSet},
+        ffi::OsString,
+        os::fd::{AsFd, AsRawFd, RawFd},
+        path::{Path, PathBuf},
+    };
+
+    use nix::{
+        errno::Errno,
+        sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor},
+    };
+    use tokio::io::unix::AsyncFd;
+
+    use super::{Event, Targets};
+
+    /// A watched directory: every entry, or only the named ones.
+    struct Watch {
+        dir: PathBuf,
+        all: bool,
+        names: HashSet<OsString>,
+    }
+
+    struct Fd(Inotify);
+
+    impl AsRawFd for Fd {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0.as_fd().as_raw_fd()
+        }
+    }
+
+    pub(super) struct InotifyBackend {
+        fd: AsyncFd<Fd>,
+        watches: HashMap<WatchDescriptor, Watch>,
+    }
+
+    impl InotifyBackend {
+        /// Files are watched through their parent directory, so a rename over
+        /// the file is seen even though it replaces the watched inode.
+        pub(super) fn new(targets: &Targets) -> std::io::Result<Self> {
+            let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+            let mask = AddWatchFlags::IN_CREATE
+                | AddWatchFlags::IN_MODIFY
+                | AddWatchFlags::IN_CLOSE_WRITE
+                | AddWatchFlags::IN_ATTRIB
+                | AddWatchFlags::IN_DELETE
+                | AddWatchFlags::IN_MOVED_FROM
+                | AddWatchFlags::IN_MOVED_TO
+                | AddWatchFlags::IN_DELETE_SELF
+                | AddWatchFlags::IN_MOVE_SELF
+                | AddWatchFlags::IN_ONLYDIR;
+            let mut watches = HashMap::new();
+            for dir in &targets.dirs {
+                add_watch(&inotify, &mut watches, dir, mask)?.all = true;
+            }
+            for file in &targets.files {
+                let (Some(parent), Some(name)) = (file.parent(), file.file_name()) else {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("cannot watch {}", file.display()),
+                    ));
+                };
+                add_watch(&inotify, &mut watches, parent, mask)?
+                    .names
+                    .insert(name.to_owned());
+            }
+            Ok(Self {
+                fd: AsyncFd::new(Fd(inotify))?,
+                watches,
+            })
+        }
+
+        /// Waits for the next batch of relevant events. A lost watch (its
+        /// directory was removed or moved) is an error, and the caller falls
+        /// back to polling.
+        pub(super) async fn wait(&mut self) -> std::io::Result<Event> {
+            loop {
+                let mut guard = self.fd.readable().await?;
+                let events = match guard.get_inner().0.read_events() {
+                    Ok(events) => events,
+                    Err(Errno::EAGAIN) => {
+                        guard.clear_ready();
+                        continue;
+                    }
+                    Err(errno) => return Err(errno.into()),
+                };
+                let mut paths = Vec::new();
+                for event in events {
+                    if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+                        return Ok(Event::Rescan);
+                    }
+                    if event.mask.intersects(
+                        AddWatchFlags::IN_IGNORED
+                            | AddWatchFlags::IN_DELETE_SELF
+                            | AddWatchFlags::IN_MOVE_SELF,
+                    ) {
+                        return Err(std::io::Error::other("a watched directory went away"));
+                    }
+                    let (Some(watch), Some(name)) = (self.watches.get(&event.wd), event.name)
+                    else {
+                        continue;
+                    };
+                    if watch.all || watch.names.contains(&name) {
+                        paths.push(watch.dir.join(name));
+                    }
+                }
+                if !paths.is_empty() {
+                    return Ok(Event::Paths(paths));
+                }
+            }
+        }
+    }
+
+    fn add_watch<'a>(
+        inotify: &Inotify,
+        watches: &'a mut HashMap<WatchDescriptor, Watch>,
+        dir: &Path,
+        mask: AddWatchFlags,
+    ) -> std::io::Result<&'a mut Watch> {
+        let target = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let descriptor = inotify.add_watch(target, mask)?;
+        Ok(watches.entry(descriptor).or_insert_with(|| Watch {
+            dir: dir.to_owned(),
+            all: false,
+            names: HashSet::new(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn options() -> WatchOptions {
+        WatchOptions::new()
+            .debounce(Duration::from_millis(100))
+            .poll_interval(Duration::from_millis(50))
+    }
+
+    async fn next(watcher: &mut Watcher) -> FsChange {
+        tokio::time::timeout(TIMEOUT, watcher.next())
+            .await
+            .expect("no change reported")
+            .unwrap()
+    }
+
+    async fn assert_quiet(watcher: &mut Watcher) {
+        let change = tokio::time::timeout(Duration::from_millis(400), watcher.next()).await;
+        assert!(change.is_err(), "unexpected change: {change:?}");
+    }
+
+    async fn rename_over_is_one_modification(options: WatchOptions) {
+        let dir = tempfile::tempdir().unwrap();
+        let profile = dir.path().join("profile.yaml");
+        std::fs::write(&profile, "a: 1\n").unwrap();
+        let mut watcher = Watcher::new([&profile], options);
+
+        let staging = dir.path().join("profile.yaml.tmp-1-0");
+        std::fs::write(&staging, "a: 2\nb: 3\n").unwrap();
+        std::fs::rename(&staging, &profile).unwrap();
+
+        assert_eq!(
+            next(&mut watcher).await,
+            FsChange {
+                path: profile.clone(),
+                kind: FsChangeKind::Modified,
+            }
+        );
+        assert_quiet(&mut watcher).await;
+
+        std::fs::remove_file(&profile).unwrap();
+        assert_eq!(next(&mut watcher).await.kind, FsChangeKind::Removed);
+    }
+
+    async fn directory_entries_are_reported(options: WatchOptions) {
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("core-1.pid");
+        std::fs::write(&pid_file, "pid=1\n").unwrap();
+        let mut watcher = Watcher::new([dir.path()], options);
+
+        let config = dir.path().join("config-2.yaml");
+        std::fs::write(&config, "").unwrap();
+        assert_eq!(
+            next(&mut watcher).await,
+            FsChange {
+                path: config,
+                kind: FsChangeKind::Created,
+            }
+        );
+
+        std::fs::remove_file(&pid_file).unwrap();
+        assert_eq!(
+            next(&mut watcher).await,
+            FsChange {
+                path: pid_file,
+                kind: FsChangeKind::Removed,
+            }
+        );
+
+        // A staging file that is created and removed within the debounce
+        // period leaves no trace.
+        let staging = dir.path().join("core-3.pid.tmp-1-0");
+        std::fs::write(&staging, "").unwrap();
+        std::fs::remove_file(&staging).unwrap();
+        assert_quiet(&mut watcher).await;
+    }
+
+    #[tokio::test]
+    async fn a_file_that_never_goes_quiet_is_reported_within_the_max_latency() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("core.log");
+        std::fs::write(&log, "").unwrap();
+        let mut watcher = Watcher::new([&log], options().max_latency(Duration::from_millis(300)));
+
+        let writer = tokio::spawn({
+            let log = log.clone();
+            async move {
+                use std::io::Write;
+
+                let mut file = std::fs::OpenOptions::new().append(true).open(log).unwrap();
+                loop {
+                    file.write_all(b"line\n").unwrap();
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+            }
+        });
+        let change = tokio::time::timeout(Duration::from_secs(2), watcher.next()).await;
+        writer.abort();
+        assert_eq!(
+            change.expect("postponed while the file kept changing"),
+            Some(FsChange {
+                path: log,
+                kind: FsChangeKind::Modified,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn rename_over_is_one_modification_with_the_native_backend() {
+        rename_over_is_one_modification(options()).await;
+    }
+
+    #[tokio::test]
+    async fn rename_over_is_one_modification_when_polling() {
+        rename_over_is_one_modification(options().force_polling(true)).await;
+    }
+
+    #[tokio::test]
+    async fn directory_entries_are_reported_with_the_native_backend() {
+        directory_entries_are_reported(options()).await;
+    }
+
+    #[tokio::test]
+    async fn directory_entries_are_reported_when_polling() {
+        directory_entries_are_reported(options().force_polling(true)).await;
+    }
+}
@@ -0,0 +1,245 @@
+//! Linux capabilities for privileged cores.
+//!
+//! [`current`] reports the calling thread's capability sets, so callers can
+//! check for exactly what TUN mode needs instead of requiring root. Only the
+//! capabilities the cores use are modelled by [`Capability`]; [`CapSet`]
+//! still carries every bit the kernel reports. The `pub(crate)` helpers run
+//! between fork and exec, so they allocate nothing and only make syscalls.
+
+use std::{fmt, io};
+
+/// A Linux capability that can be handed to a spawned core.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Bind ports below 1024.
+    NetBindService,
+    /// Configure interfaces, routes and firewall rules (TUN mode).
+    NetAdmin,
+    /// Open raw and packet sockets.
+    NetRaw,
+}
+
+impl Capability {
+    pub const ALL: [Self; 3] = [Self::NetBindService, Self::NetAdmin, Self::NetRaw];
+
+    /// The kernel's name, e.g. `CAP_NET_ADMIN`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::NetBindService => "CAP_NET_BIND_SERVICE",
+            Self::NetAdmin => "CAP_NET_ADMIN",
+            Self::NetRaw => "CAP_NET_RAW",
+        }
+    }
+
+    /// The kernel's capability number (`CAP_*` in `linux/capability.h`).
+    pub fn number(self) -> u32 {
+        match self {
+            Self::NetBindService => 10,
+            Self::NetAdmin => 12,
+            Self::NetRaw => 13,
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A set of capabilities as the kernel's 64-bit mask.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CapSet(u64);
+
+impl CapSet {
+    pub fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(self) -> u64 {
+        self.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, cap: Capability) -> bool {
+        self.0 & (1 << cap.number()) != 0
+    }
+}
+
+/// The calling thread's capability sets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CapabilityState {
+    pub effective: CapSet,
+    pub permitted: CapSet,
+    pub inheritable: CapSet,
+    pub ambient: CapSet,
+    pub bounding: CapSet,
+}
+
+impl CapabilityState {
+    /// Capabilities from `caps` that cannot be handed to a child as ambient
+    /// capabilities: they must be both permitted and in the bounding set.
+    pub fn missing_for_ambient(&self, caps: &[Capability]) -> Vec<Capability> {
+        caps.iter()
+            .copied()
+            .filter(|&cap| !self.permitted.contains(cap) || !self.bounding.contains(cap))
+            .collect()
+    }
+}
+
+/// Reads the calling thread's capability sets. Capabilities are per thread,
+/// but the manager never changes them after startup, so any thread's view is
+/// the process's.
+pub fn current() -> io::Result<CapabilityState> {
+    let mut header = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapUserData::default(); 2];
+    // SAFETY: capget writes two data structs, matching the version 3 layout.
+    if unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let join = |low: u32, high: u32| CapSet((u64::from(high) << 32) | u64::from(low));
+    Ok(CapabilityState {
+        effective: join(data[0].effective, data[1].effective),
+        permitted: join(data[0].permitted, data[1].permitted),
+        inheritable: join(data[0].inheritable, data[1].inheritable),
+        ambient: probe_each(|cap| {
+            // SAFETY: PR_CAP_AMBIENT_IS_SET takes plain integer arguments.
+            unsafe { libc::prctl(libc::PR_CAP_AMBIENT, IS_SET, cap, ZERO, ZERO) }
+        })?,
+        // SAFETY: PR_CAPBSET_READ takes a plain integer argument.
+        bounding: probe_each(|cap| unsafe {
+            libc::prctl(libc::PR_CAPBSET_READ, cap, ZERO, ZERO, ZERO)
+        })?,
+    })
+}
+
+/// Builds a set from a per-capability prctl query, stopping at the first
+/// number the kernel does not know (`EINVAL`).
+fn probe_each(query: impl Fn(libc::c_ulong) -> libc::c_int) -> io::Result<CapSet> {
+    let mut bits = 0;
+    for cap in 0..64 {
+        match query(cap) {
+            1 => bits |= 1 << cap,
+            0 => {}
+            _ => {
+                let error = io::Error::last_os_error();
+                if error.raw_os_error() == Some(libc::EINVAL) {
+                    break;
+                }
+                return Err(error);
+            }
+        }
+    }
+    Ok(CapSet(bits))
+}
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+// prctl is variadic and the kernel reads every argument as `unsigned long`,
+// so integer arguments are passed at full width to avoid garbage upper bits.
+const ZERO: libc::c_ulong = 0;
+const IS_SET: libc::c_ulong = libc::PR_CAP_AMBIENT_IS_SET as libc::c_ulong;
+
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Keeps the permitted set across a later `setuid` away from root.
+pub(crate) fn keep_caps_across_setuid() -> io::Result<()> {
+    let keep: libc::c_ulong = 1;
+    // SAFETY: PR_SET_KEEPCAPS takes a plain integer argument.
+    if unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, keep, ZERO, ZERO, ZERO) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Reduces the calling thread to exactly `caps` and raises them into the
+/// ambient set, so they survive `execve` into an unprivileged binary.
+pub(crate) fn raise_ambient(caps: &[Capability]) -> io::Result<()> {
+    let mut data = [CapUserData::default(); 2];
+    for cap in caps {
+        let number = cap.number();
+        let slot = &mut data[(number / 32) as usize];
+        let bit = 1 << (number % 32);
+        // Ambient raising requires the capability in both the permitted and
+        // the inheritable set.
+        slot.effective |= bit;
+        slot.permitted |= bit;
+        slot.inheritable |= bit;
+    }
+    let mut header = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    // SAFETY: capset reads one header and two data structs, matching the
+    // version 3 layout declared above.
+    if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    for cap in caps {
+        // SAFETY: PR_CAP_AMBIENT_RAISE takes plain integer arguments.
+        let rc = unsafe {
+            libc::prctl(
+                libc::PR_CAP_AMBIENT,
+                libc::PR_CAP_AMBIENT_RAISE as libc::c_ulong,
+                libc::c_ulong::from(cap.number()),
+                ZERO,
+                ZERO,
+            )
+        };
+        if rc == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_state_is_consistent() {
+        let state = current().unwrap();
+        // The kernel keeps ambient within permitted and inheritable, and every
+        // kernel since ambient capabilities has a non-empty bounding set.
+        assert_eq!(
+            state.ambient.bits() & !(state.permitted.bits() & state.inheritable.bits()),
+            0
+        );
+        assert_eq!(state.effective.bits() & !state.permitted.bits(), 0);
+        assert!(!state.bounding.is_empty());
+    }
+
+    #[test]
+    fn missing_for_ambient_requires_permitted_and_bounding() {
+        let state = CapabilityState {
+            permitted: CapSet::from_bits((1 << 10) | (1 << 12)),
+            bounding: CapSet::from_bits(1 << 10),
+            ..CapabilityState::default()
+        };
+        assert_eq!(
+            state.missing_for_ambient(&Capability::ALL),
+            [Capability::NetAdmin, Capability::NetRaw]
+        );
+        assert_eq!(Capability::NetAdmin.to_string(), "CAP_NET_ADMIN");
+    }
+}
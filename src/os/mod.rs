@@ -3,15 +3,25 @@
 
 //! Operating-system and process helpers.
 
+#[cfg(target_os = "linux")]
+pub mod caps;
 mod child;
 pub mod elevated;
 mod os_impl;
+#[cfg(unix)]
+mod pid_handle;
+mod validated_kill;
 pub use child::*;
 pub use elevated::*;
 pub use os_impl::*;
+#[cfg(unix)]
+pub use pid_handle::PidHandle;
 use std::fmt::Debug;
 use sysinfo::{Pid, ProcessRefreshKind, RefreshKind, System};
 use tracing_attributes::instrument;
+pub use validated_kill::{
+    KillOutcome, KilledProcess, ProcessMatcher, kill_by_pid_file_validated, kill_pid_validated,
+};
 
 use std::{ffi::OsStr, fmt::Display, io::Error as IoError, path::Path};
 use tokio::{fs::OpenOptions, io::AsyncWriteExt};
@@ -43,6 +53,10 @@ where
     Some(pid)
 }
 
+/// Looks `pid` up by number, so the answer may describe a process that reused
+/// it. On Unix, [`PidHandle`] stays bound to one process where the kernel
+/// supports it.
+#[deprecated(note = "the pid may have been reused; use `PidHandle` or `ProcessIdentity`")]
 #[instrument]
 pub fn pid_exists<Name: AsRef<str> + Debug>(pid: u32, validator: Option<&[Name]>) -> bool {
     let kind = RefreshKind::nothing()
@@ -65,6 +79,11 @@ pub fn pid_exists<Name: AsRef<str> + Debug>(pid: u32, validator: Option<&[Name]>
         })
 }
 
+/// Kills `pid` and its tree if its executable path contains one of
+/// `validator`, without protection against pid reuse. Prefer
+/// [`kill_pid_validated`].
+#[deprecated(note = "the pid may have been reused; use `kill_pid_validated` or `PidHandle`")]
+#[allow(deprecated)]
 #[instrument]
 pub async fn kill_pid<Name: AsRef<str> + Debug>(
     pid: u32,
@@ -85,6 +104,10 @@ pub async fn kill_pid<Name: AsRef<str> + Debug>(
     Ok(())
 }
 
+/// Prefer [`kill_by_pid_file_validated`], which checks the process identity
+/// and reports what was killed.
+#[deprecated(note = "the pid may have been reused; use `kill_by_pid_file_validated`")]
+#[allow(deprecated)]
 #[instrument]
 pub async fn kill_by_pid_file<T, Name: AsRef<str> + Debug>(
     path: T,
@@ -0,0 +1,228 @@
+//! A handle to an arbitrary process by pid.
+//!
+//! On Linux 5.3+ the handle holds a pidfd, which keeps referring to the same
+//! process even after its pid is reused, can be polled for exit and signals
+//! exactly that process. Elsewhere, and on older kernels, it falls back to
+//! the bare pid; [`PidHandle::is_pidfd`] tells the two apart, and the fallback
+//! keeps the usual pid-reuse window between a check and a signal.
+
+use std::{io, time::Duration};
+
+use nix::sys::signal::Signal;
+
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+const FALLBACK_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+pub struct PidHandle {
+    pid: u32,
+    #[cfg(target_os = "linux")]
+    pidfd: Option<OwnedFd>,
+}
+
+impl PidHandle {
+    /// Opens `pid`. Fails with [`io::ErrorKind::NotFound`] if no such process
+    /// exists.
+    pub fn open(pid: u32) -> io::Result<Self> {
+        #[cfg(target_os = "linux")]
+        match open_pidfd(pid) {
+            Ok(pidfd) => {
+                return Ok(Self {
+                    pid,
+                    pidfd: Some(pidfd),
+                });
+            }
+            Err(error) if error.raw_os_error() == Some(libc::ENOSYS) => {}
+            Err(error) if error.raw_os_error() == Some(libc::ESRCH) => return Err(exited(pid)),
+            Err(error) => return Err(error),
+        }
+        let handle = Self {
+            pid,
+            #[cfg(target_os = "linux")]
+            pidfd: None,
+        };
+        if !handle.is_alive()? {
+            return Err(exited(pid));
+        }
+        Ok(handle)
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Whether the handle is bound to the process rather than to its pid.
+    pub fn is_pidfd(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        if self.pidfd.is_some() {
+            return true;
+        }
+        false
+    }
+
+    /// Whether the process is still running. A zombie counts as exited.
+    pub fn is_alive(&self) -> io::Result<bool> {
+        #[cfg(target_os = "linux")]
+        if let Some(pidfd) = &self.pidfd {
+            let mut poll = libc::pollfd {
+                fd: pidfd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: poll reads and writes one pollfd that lives on the stack.
+            return match unsafe { libc::poll(&mut poll, 1, 0) } {
+                -1 => Err(io::Error::last_os_error()),
+                ready => Ok(ready == 0),
+            };
+        }
+        // SAFETY: signal 0 only checks that the pid exists and is signalable.
+        if unsafe { libc::kill(self.pid as libc::pid_t, 0) } == 0 {
+            return Ok(!is_zombie(self.pid));
+        }
+        let error = io::Error::last_os_error();
+        match error.raw_os_error() {
+            Some(libc::ESRCH) => Ok(false),
+            // The process exists but belongs to another user.
+            Some(libc::EPERM) => Ok(!is_zombie(self.pid)),
+            _ => Err(error),
+        }
+    }
+
+    /// Sends `signal`. Fails with [`io::ErrorKind::NotFound`] once the process
+    /// has exited.
+    pub fn send_signal(&self, signal: Signal) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        let result = match &self.pidfd {
+            // SAFETY: the pidfd is live and the optional siginfo is null.
+            Some(pidfd) => unsafe {
+                libc::syscall(
+                    libc::SYS_pidfd_send_signal,
+                    pidfd.as_raw_fd(),
+                    signal as libc::c_int,
+                    std::ptr::null::<libc::siginfo_t>(),
+                    0_u32,
+                )
+            },
+            // SAFETY: a plain kill on a numeric pid.
+            None => unsafe { libc::kill(self.pid as libc::pid_t, signal as libc::c_int) }.into(),
+        };
+        // SAFETY: a plain kill on a numeric pid.
+        #[cfg(not(target_os = "linux"))]
+        let result = unsafe { libc::kill(self.pid as libc::pid_t, signal as libc::c_int) };
+        if result == -1 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() == Some(libc::ESRCH) {
+                return Err(exited(self.pid));
+            }
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// Resolves once the process has exited. The process is not reaped: a
+    /// child of the caller stays a zombie until it is waited for, and counts
+    /// as exited here.
+    pub async fn wait(&self) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        if let Some(pidfd) = &self.pidfd {
+            // A duplicate, so concurrent waits register distinct descriptors.
+            let fd = tokio::io::unix::AsyncFd::with_interest(
+                pidfd.try_clone()?,
+                tokio::io::Interest::READABLE,
+            )?;
+            let _ready = fd.readable().await?;
+            return Ok(());
+        }
+        while self.is_alive()? {
+            tokio::time::sleep(FALLBACK_POLL).await;
+        }
+        Ok(())
+    }
+
+    /// The identity of the process behind this handle, or `None` once it has
+    /// exited. With a pidfd, the answer cannot belong to a process that
+    /// reused the pid: it is only returned if the process is still alive
+    /// after the lookup.
+    #[cfg(feature = "process")]
+    pub fn identity(&self) -> io::Result<Option<crate::process::ProcessIdentity>> {
+        let identity = crate::process::ProcessIdentity::of(self.pid)?;
+        if !self.is_alive()? {
+            return Ok(None);
+        }
+        Ok(identity)
+    }
+
+    /// Whether the process behind this handle is `expected`.
+    #[cfg(feature = "process")]
+    pub fn verify(&self, expected: &crate::process::ProcessIdentity) -> io::Result<bool> {
+        Ok(self
+            .identity()?
+            .is_some_and(|identity| identity.matches(expected)))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_pidfd(pid: u32) -> io::Result<OwnedFd> {
+    // SAFETY: pidfd_open receives a numeric pid and zero flags.
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0_u32) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: pidfd_open returned a new descriptor that nothing else owns; it
+    // is opened close-on-exec.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as std::os::fd::RawFd) })
+}
+
+/// Zombies still accept signals, so the fallback checks the process state.
+#[cfg(target_os = "linux")]
+fn is_zombie(pid: u32) -> bool {
+    std::fs::read_to_string(format!("/proc/{pid}/stat")).is_ok_and(|stat| {
+        stat.rsplit_once(") ")
+            .is_some_and(|(_, rest)| rest.starts_with('Z'))
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_zombie(_pid: u32) -> bool {
+    false
+}
+
+fn exited(pid: u32) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("process {pid} has exited"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn wait_resolves_when_the_process_exits() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let handle = PidHandle::open(child.id()).unwrap();
+        assert!(handle.is_alive().unwrap());
+        handle.send_signal(Signal::SIGKILL).unwrap();
+        tokio::time::timeout(Duration::from_secs(10), handle.wait())
+            .await
+            .expect("wait never resolved")
+            .unwrap();
+        assert!(!handle.is_alive().unwrap());
+        child.wait().unwrap();
+        assert_eq!(
+            handle.send_signal(Signal::SIGTERM).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn own_process_is_alive() {
+        let handle = PidHandle::open(std::process::id()).unwrap();
+        assert!(handle.is_alive().unwrap());
+        #[cfg(target_os = "linux")]
+        assert!(handle.is_pidfd());
+    }
+}
@@ -0,0 +1,424 @@
+//! Kills that check who they are about to hit.
+//!
+//! [`kill_pid_validated`] kills a process and its descendants only if the
+//! process matches a [`ProcessMatcher`]: an executable path (compared after
+//! canonicalization), an executable file name (compared exactly, or with
+//! Unicode case folding on Windows) and an optional start-time bound. A
+//! matcher must name at least one executable or file name. Every
+//! target is checked again right before it is signalled; on Unix the check
+//! happens after a [`super::PidHandle`] pins the process, so with a pidfd the
+//! signal cannot reach a process that reused the pid.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use sysinfo::{Pid, ProcessRefreshKind, RefreshKind, System, UpdateKind};
+use tracing_attributes::instrument;
+
+const KILL_WAIT: Duration = Duration::from_secs(5);
+/// sysinfo reports start times in whole seconds derived from a rounded boot
+/// time, so a process started just before the bound can read a little later.
+const START_TIME_SLACK: u64 = 2;
+#[cfg(not(unix))]
+const POLL: Duration = Duration::from_millis(50);
+
+/// What a validated kill accepts: processes whose executable is readable and
+/// matches one of the paths or names. A matcher with neither accepts nothing,
+/// and [`kill_pid_validated`] refuses it.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessMatcher {
+    executables: Vec<PathBuf>,
+    names: Vec<OsString>,
+    started_by: Option<SystemTime>,
+}
+
+impl ProcessMatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts the executable at `path`.
+    pub fn executable(mut self, path: impl AsRef<Path>) -> Self {
+        self.executables.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// Accepts an executable file name, such as `mihomo.exe`.
+    pub fn name(mut self, name: impl Into<OsString>) -> Self {
+        self.names.push(name.into());
+        self
+    }
+
+    pub fn names<I, N>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = N>,
+        N: Into<OsString>,
+    {
+        self.names.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Rejects processes started after `time`, e.g. after the pid file that
+    /// names them was written, allowing a couple of seconds for the coarse
+    /// start times the system reports.
+    pub fn started_by(mut self, time: SystemTime) -> Self {
+        self.started_by = Some(time);
+        self
+    }
+
+    fn is_empty(&self) -> bool {
+        self.executables.is_empty() && self.names.is_empty()
+    }
+
+    fn matches(&self, process: &Snapshot) -> bool {
+        if let Some(bound) = self.started_by {
+            let bound = bound
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            if process.start_time > bound.saturating_add(START_TIME_SLACK) {
+                return false;
+            }
+        }
+        let Some(exe) = &process.executable else {
+            return false;
+        };
+        let actual = canonical(exe);
+        if self
+            .executables
+            .iter()
+            .any(|expected| actual == canonical(expected))
+        {
+            return true;
+        }
+        exe_file_name(exe).is_some_and(|name| self.names.iter().any(|n| names_equal(&name, n)))
+    }
+}
+
+/// The result of a validated kill.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KillOutcome {
+    /// No process holds the pid.
+    NotFound,
+    /// The pid belongs to a process the matcher rejected; nothing was
+    /// signalled.
+    Mismatched { executable: Option<PathBuf> },
+    /// The processes that were killed, descendants first.
+    Killed(Vec<KilledProcess>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KilledProcess {
+    pub pid: u32,
+    pub executable: Option<PathBuf>,
+    /// Seconds since the Unix epoch.
+    pub start_time: u64,
+}
+
+/// Kills `pid` and its descendants if `pid` matches `matcher`. Descendants
+/// are not matched themselves; they only have to keep the start time they
+/// had when the tree was captured.
+///
+/// Fails with [`std::io::ErrorKind::InvalidInput`] if `matcher` names no
+/// executable or file name.
+#[instrument(skip(matcher))]
+pub async fn kill_pid_validated(
+    pid: u32,
+    matcher: &ProcessMatcher,
+) -> std::io::Result<KillOutcome> {
+    if matcher.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "process matcher names no executable",
+        ));
+    }
+    let processes = snapshot();
+    let Some(root) = processes.get(&pid) else {
+        return Ok(KillOutcome::NotFound);
+    };
+    if !matcher.matches(root) {
+        return Ok(KillOutcome::Mismatched {
+            executable: root.executable.clone(),
+        });
+    }
+    let mut targets = descendants(&processes, pid);
+    targets.reverse();
+    targets.push(pid);
+    let targets = targets
+        .into_iter()
+        .filter_map(|pid| processes.get(&pid).cloned())
+        .collect::<Vec<_>>();
+    let killed = kill_targets(targets).await?;
+    for process in &killed {
+        tracing::info!("killed validated process: {process:?}");
+    }
+    Ok(KillOutcome::Killed(killed))
+}
+
+/// Reads a numeric pid file, kills its process through [`kill_pid_validated`]
+/// and removes the file. Unless `matcher` already bounds the start time, a
+/// process started after the file was last written is rejected.
+#[instrument(skip(matcher))]
+pub async fn kill_by_pid_file_validated(
+    path: impl AsRef<Path> + std::fmt::Debug,
+    matcher: &ProcessMatcher,
+) -> std::io::Result<KillOutcome> {
+    let path = path.as_ref();
+    let Some(pid) = super::get_pid_from_file(path).await else {
+        tracing::debug!("pid file not found or parsing error, skip");
+        return Ok(KillOutcome::NotFound);
+    };
+    let mut matcher = matcher.clone();
+    if matcher.started_by.is_none() {
+        let metadata = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata,
+            // Removed since it was read, by whoever already handled its process.
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(KillOutcome::NotFound);
+            }
+            Err(error) => return Err(error),
+        };
+        if let Ok(modified) = metadata.modified() {
+            matcher.started_by = Some(modified);
+        }
+    }
+    let outcome = kill_pid_validated(pid, &matcher).await?;
+    match tokio::fs::remove_file(path).await {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+        _ => Ok(outcome),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    pid: u32,
+    parent: Option<u32>,
+    executable: Option<PathBuf>,
+    start_time: u64,
+}
+
+impl Snapshot {
+    fn killed(self) -> KilledProcess {
+        KilledProcess {
+            pid: self.pid,
+            executable: self.executable,
+            start_time: self.start_time,
+        }
+    }
+}
+
+fn snapshot() -> BTreeMap<u32, Snapshot> {
+    let kind = RefreshKind::nothing()
+        .with_processes(ProcessRefreshKind::nothing().with_exe(UpdateKind::Always));
+    let mut system = System::new_with_specifics(kind);
+    system.refresh_specifics(kind);
+    system
+        .processes()
+        .iter()
+        .map(|(pid, process)| {
+            let pid = pid.as_u32();
+            (
+                pid,
+                Snapshot {
+                    pid,
+                    parent: process.parent().map(Pid::as_u32),
+                    executable: process.exe().map(Path::to_owned),
+                    start_time: process.start_time(),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Breadth-first, so reversing the list kills children before parents.
+fn descendants(processes: &BTreeMap<u32, Snapshot>, root: u32) -> Vec<u32> {
+    let mut found = Vec::new();
+    let mut next = 0;
+    let mut parent = root;
+    loop {
+        for process in processes.values() {
+            if process.parent == Some(parent)
+                && process.pid != root
+                && !found.contains(&process.pid)
+            {
+                found.push(process.pid);
+            }
+        }
+        let Some(&pid) = found.get(next) else {
+            return found;
+        };
+        parent = pid;
+        next += 1;
+    }
+}
+
+/// Whether `expected` still runs under its pid, judged by its start time.
+fn still_running(current: &BTreeMap<u32, Snapshot>, expected: &Snapshot) -> bool {
+    current
+        .get(&expected.pid)
+        .is_some_and(|process| process.start_time == expected.start_time)
+}
+
+#[cfg(unix)]
+async fn kill_targets(targets: Vec<Snapshot>) -> std::io::Result<Vec<KilledProcess>> {
+    use nix::sys::signal::Signal;
+
+    let mut pinned = Vec::new();
+    for target in targets {
+        match super::PidHandle::open(target.pid) {
+            Ok(handle) => pinned.push((handle, target)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+    }
+    let current = snapshot();
+    let mut killed = Vec::new();
+    for (handle, target) in pinned {
+        if !still_running(&current, &target) {
+            continue;
+        }
+        match handle.send_signal(Signal::SIGKILL) {
+            Ok(()) => killed.push((handle, target)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+    }
+    for (handle, _) in &killed {
+        tokio::time::timeout(KILL_WAIT, handle.wait())
+            .await
+            .map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("pid {} survived SIGKILL", handle.pid()),
+                )
+            })??;
+    }
+    Ok(killed
+        .into_iter()
+        .map(|(_, target)| target.killed())
+        .collect())
+}
+
+/// Without a process handle, each target is checked again right before it
+/// is terminated, leaving the usual pid-reuse window.
+#[cfg(not(unix))]
+async fn kill_targets(targets: Vec<Snapshot>) -> std::io::Result<Vec<KilledProcess>> {
+    let kind = RefreshKind::nothing().with_processes(ProcessRefreshKind::nothing());
+    let mut system = System::new_with_specifics(kind);
+    let mut killed = Vec::new();
+    for target in targets {
+        system.refresh_specifics(kind);
+        let Some(process) = system.process(Pid::from_u32(target.pid)) else {
+            continue;
+        };
+        if process.start_time() != target.start_time {
+            continue;
+        }
+        if process.kill() {
+            killed.push(target);
+        }
+    }
+    let deadline = tokio::time::Instant::now() + KILL_WAIT;
+    loop {
+        let current = snapshot();
+        let Some(survivor) = killed.iter().find(|target| still_running(&current, target)) else {
+            break;
+        };
+        if tokio::time::Instant::now() >= deadline {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("pid {} survived termination", survivor.pid),
+            ));
+        }
+        tokio::time::sleep(POLL).await;
+    }
+    Ok(killed.into_iter().map(Snapshot::killed).collect())
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+/// Linux marks the executable of a process whose binary was replaced, e.g.
+/// by a core upgrade, with ` (deleted)`.
+fn exe_file_name(exe: &Path) -> Option<OsString> {
+    let name = exe.file_name()?;
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        if let Some(stripped) = name.as_bytes().strip_suffix(b" (deleted)") {
+            return Some(std::ffi::OsStr::from_bytes(stripped).to_owned());
+        }
+    }
+    Some(name.to_owned())
+}
+
+#[cfg(windows)]
+fn names_equal(actual: &std::ffi::OsStr, expected: &std::ffi::OsStr) -> bool {
+    match (actual.to_str(), expected.to_str()) {
+        (Some(actual), Some(expected)) => actual.to_lowercase() == expected.to_lowercase(),
+        _ => actual == expected,
+    }
+}
+
+#[cfg(not(windows))]
+fn names_equal(actual: &std::ffi::OsStr, expected: &std::ffi::OsStr) -> bool {
+    actual == expected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(exe: &str, start_time: u64) -> Snapshot {
+        Snapshot {
+            pid: 1,
+            parent: None,
+            executable: Some(PathBuf::from(exe)),
+            start_time,
+        }
+    }
+
+    #[test]
+    fn names_match_whole_file_names_only() {
+        let matcher = ProcessMatcher::new().name("mihomo");
+        assert!(matcher.matches(&process("/opt/core/mihomo", 1)));
+        assert!(!matcher.matches(&process("/opt/core/mihomo-alpha", 1)));
+        assert!(!matcher.matches(&process("/opt/mihomo/verge", 1)));
+        assert!(
+            ProcessMatcher::new()
+                .name("ミホモ")
+                .matches(&process("/opt/ミホモ", 1))
+        );
+        #[cfg(target_os = "linux")]
+        assert!(matcher.matches(&process("/opt/core/mihomo (deleted)", 1)));
+        let unreadable = Snapshot {
+            executable: None,
+            ..process("", 1)
+        };
+        assert!(!matcher.matches(&unreadable));
+    }
+
+    #[test]
+    fn start_bound_rejects_later_processes() {
+        let bound = SystemTime::UNIX_EPOCH + Duration::from_secs(100);
+        let matcher = ProcessMatcher::new().name("mihomo").started_by(bound);
+        assert!(matcher.matches(&process("/mihomo", 100)));
+        assert!(matcher.matches(&process("/mihomo", 100 + START_TIME_SLACK)));
+        assert!(!matcher.matches(&process("/mihomo", 101 + START_TIME_SLACK)));
+    }
+
+    #[tokio::test]
+    async fn empty_matchers_are_refused() {
+        assert!(!ProcessMatcher::new().matches(&process("/mihomo", 1)));
+        let error = kill_pid_validated(std::process::id(), &ProcessMatcher::new())
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::VecDeque;
+
+use super::{
+    command::{Command, OutputOverflow},
+    error::{ProcessError, ProcessOutput},
+    event::{ExitReason, ProcessEvent},
+};
+
+/// Per-stream byte budgets for [`Command::output_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimits {
+    pub max_stdout: usize,
+    pub max_stderr: usize,
+    /// Keep only the newest bytes instead of splitting the budget between the
+    /// head and the tail of the stream.
+    pub tail_only: bool,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        Self {
+            max_stdout: 1024 * 1024,
+            max_stderr: 1024 * 1024,
+            tail_only: false,
+        }
+    }
+}
+
+/// One captured stream. Without truncation the whole stream is in `head`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapturedStream {
+    pub head: String,
+    pub tail: String,
+    /// Bytes elided between `head` and `tail`.
+    pub omitted: usize,
+}
+
+impl CapturedStream {
+    pub fn is_truncated(&self) -> bool {
+        self.omitted > 0
+    }
+}
+
+struct StreamCapture {
+    head: String,
+    head_limit: usize,
+    tail: VecDeque<String>,
+    tail_bytes: usize,
+    tail_limit: usize,
+    omitted: usize,
+    /// An incomplete UTF-8 sequence at the end of the last raw chunk.
+    partial_char: Vec<u8>,
+}
+
+impl StreamCapture {
+    fn new(max: usize, tail_only: bool) -> Self {
+        let head_limit = if tail_only { 0 } else { max.div_ceil(2) };
+        Self {
+            head: String::new(),
+            head_limit,
+            tail: VecDeque::new(),
+            tail_bytes: 0,
+            tail_limit: max - head_limit,
+            omitted: 0,
+            partial_char: Vec::new(),
+        }
+    }
+
+    fn push_line(&mut self, line: &str) {
+        let mut text = String::with_capacity(line.len() + 1);
+        text.push_str(line);
+        text.push('\n');
+        self.push_text(text);
+    }
+
+    /// Decodes a raw chunk lossily. A character split across chunks is held
+    /// back until its remaining bytes arrive.
+    fn push_chunk(&mut self, chunk: &[u8]) {
+        self.partial_char.extend_from_slice(chunk);
+        let complete = self.partial_char.len() - incomplete_char_len(&self.partial_char);
+        let rest = self.partial_char.split_off(complete);
+        let bytes = std::mem::replace(&mut self.partial_char, rest);
+        self.push_text(String::from_utf8_lossy(&bytes).into_owned());
+    }
+
+    fn push_text(&mut self, mut text: String) {
+        let room = self.head_limit - self.head.len();
+        if room > 0 {
+            let split = floor_char_boundary(&text, room);
+            self.head.push_str(&text[..split]);
+            text.drain(..split);
+        }
+        if text.is_empty() {
+            return;
+        }
+
+        self.tail_bytes += text.len();
+        self.tail.push_back(text);
+        while self.tail_bytes > self.tail_limit {
+            let excess = self.tail_bytes - self.tail_limit;
+            let front = self.tail.front_mut().expect("tail has bytes");
+            if front.len() <= excess {
+                let dropped = self.tail.pop_front().expect("tail has bytes");
+                self.tail_bytes -= dropped.len();
+                self.omitted += dropped.len();
+            } else {
+                let cut = ceil_char_boundary(front, excess);
+                front.drain(..cut);
+                self.tail_bytes -= cut;
+                self.omitted += cut;
+            }
+        }
+    }
+
+    fn finish(mut self) -> CapturedStream {
+        if !self.partial_char.is_empty() {
+            let bytes = std::mem::take(&mut self.partial_char);
+            self.push_text(String::from_utf8_lossy(&bytes).into_owned());
+        }
+        let tail: String = self.tail.into_iter().collect();
+        if self.omitted == 0 {
+            CapturedStream {
+                head: self.head + &tail,
+                tail: String::new(),
+                omitted: 0,
+            }
+        } else {
+            CapturedStream {
+                head: self.head,
+                tail,
+                omitted: self.omitted,
+            }
+        }
+    }
+}
+
+/// Length of a UTF-8 sequence at the end of `bytes` that its lead byte says
+/// is still missing continuation bytes.
+fn incomplete_char_len(bytes: &[u8]) -> usize {
+    for len in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - len];
+        if byte & 0xC0 == 0x80 {
+            continue;
+        }
+        let needed = match byte {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        return if needed > len { len } else { 0 };
+    }
+    0
+}
+
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+pub(crate) async fn run_limited<F>(
+    cmd: Command,
+    limits: OutputLimits,
+    mut on_event: F,
+) -> Result<ProcessOutput<CapturedStream>, ProcessError>
+where
+    F: FnMut(&ProcessEvent),
+{
+    let timeout = cmd.timeout;
+    // The receiver below drains continuously, so blocking never stalls the
+    // child and no line is lost before it is accounted for.
+    let (handle, mut events) = cmd.output_overflow(OutputOverflow::Block).spawn().await?;
+    let mut stdout = StreamCapture::new(limits.max_stdout, limits.tail_only);
+    let mut stderr = StreamCapture::new(limits.max_stderr, limits.tail_only);
+    while let Some(event) = events.recv().await {
+        on_event(&event);
+        match &event {
+            ProcessEvent::Stdout(line) => stdout.push_line(line),
+            ProcessEvent::Stderr(line) => stderr.push_line(line),
+            ProcessEvent::StdoutBytes(chunk) => stdout.push_chunk(chunk),
+            ProcessEvent::StderrBytes(chunk) => stderr.push_chunk(chunk),
+            _ => {}
+        }
+    }
+
+    let payload = handle.wait().await?;
+    if payload.reason == Some(ExitReason::TimedOut) {
+        return Err(ProcessError::Timeout {
+            after: timeout.unwrap_or_default(),
+        });
+    }
+    Ok(ProcessOutput {
+        code: payload.code,
+        reason: payload.reason,
+        runtime: payload.runtime,
+        stdout: stdout.finish(),
+        stderr: stderr.finish(),
+    })
+}
+
+/// Collects every line unbounded, for one-shot captures that need the
+/// streaming engine (e.g. to feed stdin). Raw chunks are concatenated as
+/// they came; text lines are stored as UTF-8, each followed by `\n`.
+pub(crate) async fn run_collected(cmd: Command) -> Result<ProcessOutput<Vec<u8>>, ProcessError> {
+    let timeout = cmd.timeout;
+    let (handle, mut events) = cmd.output_overflow(OutputOverflow::Block).spawn().await?;
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    while let Some(event) = events.recv().await {
+        match &event {
+            ProcessEvent::Stdout(line) => {
+                stdout.extend_from_slice(line.as_bytes());
+                stdout.push(b'\n');
+            }
+            ProcessEvent::Stderr(line) => {
+                stderr.extend_from_slice(line.as_bytes());
+                stderr.push(b'\n');
+            }
+            ProcessEvent::StdoutBytes(chunk) => stdout.extend_from_slice(chunk),
+            ProcessEvent::StderrBytes(chunk) => stderr.extend_from_slice(chunk),
+            _ => {}
+        }
+    }
+
+    let payload = handle.wait().await?;
+    if payload.reason == Some(ExitReason::TimedOut) {
+        return Err(ProcessError::Timeout {
+            after: timeout.unwrap_or_default(),
+        });
+    }
+    Ok(ProcessOutput {
+        code: payload.code,
+        reason: payload.reason,
+        runtime: payload.runtime,
+        stdout,
+        stderr,
+    })
+}
+
+/// [`Command::output`] through the streaming engine. On Unix the streams are
+/// collected raw and decoded once as a whole, so the result is exactly what
+/// [`super::engine::run_capture`] would produce. Elsewhere the engine only
+/// streams lines, which are joined with `\n`.
+pub(crate) async fn run_collected_text(cmd: Command) -> Result<ProcessOutput, ProcessError> {
+    #[cfg(unix)]
+    let (encoding, cmd) = (cmd.encoding, cmd.raw_output(true));
+    let out = run_collected(cmd).await?;
+    let decode = |bytes: Vec<u8>| -> String {
+        #[cfg(unix)]
+        if let Some(encoding) = encoding {
+            return encoding.decode(&bytes).0.into_owned();
+        }
+        String::from_utf8(bytes)
+            .unwrap_or_else(|error| String::from_utf8_lossy(error.as_bytes()).into_owned())
+    };
+    Ok(ProcessOutput {
+        code: out.code,
+        reason: out.reason,
+        runtime: out.runtime,
+        stdout: decode(out.stdout),
+        stderr: decode(out.stderr),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn under_budget_stream_is_kept_whole() {
+        let mut capture = StreamCapture::new(64, false);
+        capture.push_line("first");
+        capture.push_line("second");
+        assert_eq!(
+            capture.finish(),
+            CapturedStream {
+                head: "first\nsecond\n".into(),
+                tail: String::new(),
+                omitted: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn over_budget_stream_keeps_head_and_tail() {
+        let mut capture = StreamCapture::new(8, false);
+        for line in ["aaa", "bbb", "ccc", "ddd"] {
+            capture.push_line(line);
+        }
+        let stream = capture.finish();
+        assert_eq!(stream.head, "aaa\n");
+        assert_eq!(stream.tail, "ddd\n");
+        assert_eq!(stream.omitted, 8);
+    }
+
+    #[test]
+    fn raw_chunks_decode_characters_split_across_chunks() {
+        let mut capture = StreamCapture::new(64, false);
+        let text = "中文\n".as_bytes();
+        capture.push_chunk(&text[..2]);
+        capture.push_chunk(&text[2..4]);
+        capture.push_chunk(&text[4..]);
+        capture.push_chunk(b"\xe4");
+        assert_eq!(capture.finish().head, "中文\n\u{fffd}");
+    }
+
+    #[test]
+    fn tail_only_keeps_newest_bytes_on_char_boundaries() {
+        let mut capture = StreamCapture::new(5, true);
+        capture.push_line("中文中文");
+        capture.push_line("ab");
+        let stream = capture.finish();
+        assert!(stream.head.is_empty());
+        assert_eq!(stream.tail, "\nab\n");
+        assert_eq!(stream.omitted, "中文中文".len());
+    }
+}
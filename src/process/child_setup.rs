@@ -0,0 +1,364 @@
+//! Unix child configuration applied between fork and exec.
+//!
+//! Everything that can fail for ordinary reasons (user lookup, group lists) is
+//! resolved in the parent by [`ChildSetup::from_command`]; [`ChildSetup::run`]
+//! then only issues syscalls, since allocating or locking in the forked child
+//! is not async-signal-safe.
+
+use std::{
+    ffi::CString,
+    io,
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::ffi::{OsStrExt, OsStringExt},
+    },
+};
+
+#[cfg(target_os = "linux")]
+use crate::os::caps::{self, Capability};
+
+use super::{command::Command, error::ProcessError};
+
+#[derive(Default)]
+pub(crate) struct ChildSetup {
+    pub pty_slave: Option<RawFd>,
+    /// Write ends of the engine's stdout and stderr pipes.
+    pub output: Option<(RawFd, RawFd)>,
+    detach_session: bool,
+    /// `(source, target)` pairs; the sources stay open in the parent's
+    /// [`Command`] until the child has been spawned.
+    fds: Vec<(RawFd, RawFd)>,
+    /// Scratch slots for the sources while they are moved into place.
+    parked_fds: Vec<RawFd>,
+    listen_env: Option<ListenEnv>,
+    uid: Option<libc::uid_t>,
+    gid: Option<libc::gid_t>,
+    groups: Option<Vec<libc::gid_t>>,
+    #[cfg(target_os = "linux")]
+    ambient_caps: Vec<Capability>,
+    #[cfg(target_os = "linux")]
+    sandbox: Option<super::sandbox::PreparedSandbox>,
+}
+
+impl ChildSetup {
+    pub fn from_command(cmd: &Command) -> Result<Self, ProcessError> {
+        let mut setup = Self {
+            detach_session: cmd.detach_session,
+            fds: passed_fds(cmd)?,
+            parked_fds: vec![-1; cmd.passed_fds.len()],
+            listen_env: (cmd.listen_fds > 0).then(|| ListenEnv::new(cmd)),
+            uid: cmd.uid,
+            gid: cmd.gid,
+            groups: cmd.groups.clone(),
+            #[cfg(target_os = "linux")]
+            ambient_caps: cmd.ambient_caps.clone(),
+            #[cfg(target_os = "linux")]
+            sandbox: cmd
+                .sandbox
+                .as_ref()
+                .map(super::sandbox::prepare)
+                .transpose()?,
+            ..Self::default()
+        };
+        #[cfg(target_os = "linux")]
+        if !setup.ambient_caps.is_empty() {
+            // Checked here so the caller gets a named list rather than the
+            // child's bare EPERM from capset.
+            let missing = caps::current()?.missing_for_ambient(&setup.ambient_caps);
+            if !missing.is_empty() {
+                return Err(ProcessError::MissingCapabilities { missing });
+            }
+        }
+        if let Some(name) = &cmd.user {
+            let user = nix::unistd::User::from_name(name)
+                .map_err(io::Error::from)?
+                .ok_or_else(|| ProcessError::UnknownUser { name: name.clone() })?;
+            setup.uid.get_or_insert(user.uid.as_raw());
+            let gid = *setup.gid.get_or_insert(user.gid.as_raw());
+            // setgroups needs CAP_SETGID; without it the child keeps the
+            // manager's groups, as with a plain uid.
+            if setup.groups.is_none() && may_set_groups() {
+                setup.groups = Some(user_groups(name, gid)?);
+            }
+        }
+        Ok(setup)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        if !self.ambient_caps.is_empty() || self.sandbox.is_some() {
+            return false;
+        }
+        self.pty_slave.is_none()
+            && self.output.is_none()
+            && !self.detach_session
+            && self.fds.is_empty()
+            && self.uid.is_none()
+            && self.gid.is_none()
+            && self.groups.is_none()
+    }
+
+    /// Runs in the forked child. Mounts and groups and gid go first: once the
+    /// uid is dropped the child may no longer change them. Sandbox
+    /// restrictions come last so they cannot block the steps before them.
+    pub fn run(&mut self) -> io::Result<()> {
+        if self.detach_session {
+            new_session()?;
+        }
+        if let Some(slave) = self.pty_slave {
+            super::pty::attach_slave(slave)?;
+            if self.detach_session {
+                super::pty::make_controlling(slave)?;
+            }
+        }
+        if let Some((stdout, stderr)) = self.output {
+            super::pipes::attach_output(stdout, stderr)?;
+        }
+        self.move_fds()?;
+        if let Some(env) = &mut self.listen_env {
+            env.install();
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(sandbox) = &self.sandbox {
+            sandbox.apply_mounts()?;
+        }
+        #[cfg(target_os = "linux")]
+        if !self.ambient_caps.is_empty() && self.uid.is_some() {
+            caps::keep_caps_across_setuid()?;
+        }
+        // SAFETY: plain credential syscalls on data prepared before fork.
+        unsafe {
+            match &self.groups {
+                Some(groups) => check(libc::setgroups(groups.len() as _, groups.as_ptr()))?,
+                // Like std, a root parent dropping to another uid clears its
+                // supplementary groups instead of leaking them.
+                None if self.uid.is_some() && libc::getuid() == 0 => {
+                    check(libc::setgroups(0, std::ptr::null()))?
+                }
+                None => {}
+            }
+            if let Some(gid) = self.gid {
+                check(libc::setgid(gid))?;
+            }
+            if let Some(uid) = self.uid {
+                check(libc::setuid(uid))?;
+            }
+        }
+        #[cfg(target_os = "linux")]
+        if !self.ambient_caps.is_empty() {
+            caps::raise_ambient(&self.ambient_caps)?;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(sandbox) = &self.sandbox {
+            sandbox.apply_restrictions()?;
+        }
+        Ok(())
+    }
+
+    /// Moves every source onto its target. The sources are first parked above
+    /// all targets, so a source that is also another entry's target is not
+    /// overwritten early; the `dup2` onto the target clears close-on-exec.
+    fn move_fds(&mut self) -> io::Result<()> {
+        let Some(floor) = self.fds.iter().map(|&(_, target)| target + 1).max() else {
+            return Ok(());
+        };
+        for (slot, &(source, _)) in self.parked_fds.iter_mut().zip(&self.fds) {
+            // SAFETY: fcntl on a descriptor the parent keeps open.
+            *slot = unsafe { libc::fcntl(source, libc::F_DUPFD_CLOEXEC, floor) };
+            check(*slot)?;
+        }
+        for (&parked, &(_, target)) in self.parked_fds.iter().zip(&self.fds) {
+            // SAFETY: dup2 between descriptors owned by the child.
+            check(unsafe { libc::dup2(parked, target) })?;
+        }
+        Ok(())
+    }
+}
+
+/// Validates the targets of [`Command::pass_fd`] and [`Command::listen_fds`].
+fn passed_fds(cmd: &Command) -> io::Result<Vec<(RawFd, RawFd)>> {
+    let mut fds: Vec<(RawFd, RawFd)> = Vec::with_capacity(cmd.passed_fds.len());
+    for (fd, target) in &cmd.passed_fds {
+        let invalid = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot pass a descriptor as fd {target}: {reason}"),
+            )
+        };
+        if *target < 3 {
+            return Err(invalid("0 to 2 are the child's stdio"));
+        }
+        if fds.iter().any(|(_, existing)| existing == target) {
+            return Err(invalid("the target is already used"));
+        }
+        fds.push((fd.as_raw_fd(), *target));
+    }
+    Ok(fds)
+}
+
+/// The child's complete environment for socket activation. `LISTEN_PID` is
+/// only known after fork, so the entries and the `environ` array are built
+/// here and the child writes its pid into a slot reserved for it.
+struct ListenEnv {
+    _entries: Vec<CString>,
+    pid_entry: Vec<u8>,
+    /// Addresses of the entries and `pid_entry`, then a terminating 0.
+    pointers: Vec<usize>,
+}
+
+const LISTEN_PID_PREFIX: &[u8] = b"LISTEN_PID=";
+
+impl ListenEnv {
+    fn new(cmd: &Command) -> Self {
+        let entries: Vec<CString> = cmd
+            .resolved_env()
+            .into_iter()
+            .filter(|(key, _)| {
+                !matches!(
+                    key.as_bytes(),
+                    b"LISTEN_FDS" | b"LISTEN_PID" | b"LISTEN_FDNAMES"
+                )
+            })
+            .filter_map(|(key, value)| {
+                let mut entry = key.into_vec();
+                entry.push(b'=');
+                entry.extend_from_slice(value.as_bytes());
+                CString::new(entry).ok()
+            })
+            .chain(CString::new(format!("LISTEN_FDS={}", cmd.listen_fds)).ok())
+            .collect();
+        // Room for any u32 pid plus the terminating NUL.
+        let mut pid_entry = LISTEN_PID_PREFIX.to_vec();
+        pid_entry.resize(LISTEN_PID_PREFIX.len() + 11, 0);
+        let pointers = entries
+            .iter()
+            .map(|entry| entry.as_ptr() as usize)
+            .chain([pid_entry.as_ptr() as usize, 0])
+            .collect();
+        Self {
+            _entries: entries,
+            pid_entry,
+            pointers,
+        }
+    }
+
+    /// Fills in `LISTEN_PID` and points `environ` at the prepared array,
+    /// which the engine's `execvp` then passes on.
+    fn install(&mut self) {
+        // SAFETY: getpid cannot fail.
+        let mut pid = unsafe { libc::getpid() } as u32;
+        let mut digits = [0u8; 10];
+        let mut len = 0;
+        loop {
+            digits[len] = b'0' + (pid % 10) as u8;
+            len += 1;
+            pid /= 10;
+            if pid == 0 {
+                break;
+            }
+        }
+        let value = &mut self.pid_entry[LISTEN_PID_PREFIX.len()..];
+        for (slot, digit) in value.iter_mut().zip(digits[..len].iter().rev()) {
+            *slot = *digit;
+        }
+        value[len] = 0;
+        // SAFETY: the array and every entry it points to live in this
+        // ChildSetup, which outlives the exec.
+        unsafe { *environ() = self.pointers.as_ptr().cast() };
+    }
+}
+
+#[cfg(not(target_vendor = "apple"))]
+unsafe fn environ() -> *mut *const *const libc::c_char {
+    unsafe extern "C" {
+        static mut environ: *const *const libc::c_char;
+    }
+    // SAFETY: only the address of the libc global is taken.
+    unsafe { &raw mut environ }
+}
+
+#[cfg(target_vendor = "apple")]
+unsafe fn environ() -> *mut *const *const libc::c_char {
+    // SAFETY: _NSGetEnviron returns the address of the process's `environ`.
+    unsafe { libc::_NSGetEnviron().cast() }
+}
+
+/// Makes the child the leader of a new session and of a process group whose
+/// id is its pid. Process-group containment may already have made it the
+/// leader of that group, which `setsid` refuses, so it first rejoins the
+/// parent's group; the pgid ends up the same either way.
+fn new_session() -> io::Result<()> {
+    // SAFETY: plain process-group syscalls without pointers.
+    unsafe {
+        if libc::getpgrp() == libc::getpid() {
+            check(libc::setpgid(0, libc::getpgid(libc::getppid())))?;
+        }
+        check(libc::setsid())
+    }
+}
+
+fn check(rc: libc::c_int) -> io::Result<()> {
+    if rc == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether this process may replace its supplementary groups: CAP_SETGID
+/// on Linux, root elsewhere.
+fn may_set_groups() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        const CAP_SETGID: u32 = 6;
+        crate::os::caps::current()
+            .is_ok_and(|state| state.effective.bits() & (1 << CAP_SETGID) != 0)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        nix::unistd::geteuid().is_root()
+    }
+}
+
+#[cfg(not(target_vendor = "apple"))]
+fn user_groups(name: &str, gid: libc::gid_t) -> io::Result<Vec<libc::gid_t>> {
+    let name =
+        std::ffi::CString::new(name).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let groups = nix::unistd::getgrouplist(&name, nix::unistd::Gid::from_raw(gid))?;
+    Ok(groups.into_iter().map(|gid| gid.as_raw()).collect())
+}
+
+/// nix has no `getgrouplist` on Apple platforms; keep the primary group only.
+#[cfg(target_vendor = "apple")]
+fn user_groups(_name: &str, gid: libc::gid_t) -> io::Result<Vec<libc::gid_t>> {
+    Ok(vec![gid])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_resolves_ids_and_explicit_values_win() {
+        let setup = ChildSetup::from_command(&Command::new("prog").user("root").gid(42)).unwrap();
+        assert_eq!(setup.uid, Some(0));
+        assert_eq!(setup.gid, Some(42));
+        assert!(setup.groups.is_some());
+        assert!(!setup.is_empty());
+        assert!(
+            ChildSetup::from_command(&Command::new("prog"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn unknown_user_is_a_typed_error() {
+        let error = ChildSetup::from_command(&Command::new("prog").user("nyanpasu-no-such-user"))
+            .err()
+            .unwrap();
+        assert!(
+            matches!(error, ProcessError::UnknownUser { name } if name == "nyanpasu-no-such-user")
+        );
+    }
+}
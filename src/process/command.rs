@@ -1,11 +1,86 @@
 use std::{
     ffi::{OsStr, OsString},
-    path::PathBuf,
+    path::{Path, PathBuf},
     time::Duration,
 };
 
 use super::pid_file::EpochPidFile;
 
+/// What the output ring does when a slow receiver lets it fill up.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputOverflow {
+    /// Discard the oldest buffered lines to make room for new output.
+    #[default]
+    DropOldest,
+    /// Keep the buffered lines and discard new output until there is room.
+    DropNewest,
+    /// Never discard: stop reading the child's output until the receiver
+    /// catches up. Unread output stays in the pipe, and a child that fills it
+    /// blocks in `write` until there is room again.
+    ///
+    /// On Windows the pipes are read by processkit, which keeps draining them
+    /// into a buffer capped at [`Command::output_buffer_size`]; there `Block`
+    /// bounds memory but does not stall the child.
+    Block,
+}
+
+/// One-shot stdin contents for [`Command::stdin_from`]. The pipe is closed
+/// once the source is exhausted, so the child sees EOF.
+#[non_exhaustive]
+pub enum StdinSource {
+    Bytes(bytes::Bytes),
+    /// Opened when the child is spawned; a missing file fails the spawn.
+    File(PathBuf),
+    Reader(Box<dyn tokio::io::AsyncRead + Send + Unpin>),
+}
+
+impl StdinSource {
+    /// Streams `reader` into the child until it reports EOF.
+    pub fn reader(reader: impl tokio::io::AsyncRead + Send + Unpin + 'static) -> Self {
+        Self::Reader(Box::new(reader))
+    }
+}
+
+impl From<bytes::Bytes> for StdinSource {
+    fn from(data: bytes::Bytes) -> Self {
+        Self::Bytes(data)
+    }
+}
+
+impl From<Vec<u8>> for StdinSource {
+    fn from(data: Vec<u8>) -> Self {
+        Self::Bytes(data.into())
+    }
+}
+
+impl From<&[u8]> for StdinSource {
+    fn from(data: &[u8]) -> Self {
+        Self::Bytes(bytes::Bytes::copy_from_slice(data))
+    }
+}
+
+impl From<PathBuf> for StdinSource {
+    fn from(path: PathBuf) -> Self {
+        Self::File(path)
+    }
+}
+
+impl From<&Path> for StdinSource {
+    fn from(path: &Path) -> Self {
+        Self::File(path.to_path_buf())
+    }
+}
+
+/// Which part of the manager's environment the child starts from, before
+/// [`Command::env`] and [`Command::env_remove`] are applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum EnvBase {
+    Inherit,
+    Clear,
+    Only(Vec<OsString>),
+}
+
 pub(crate) enum PidFile {
     Legacy(PathBuf),
     Epoch(EpochPidFile),
@@ -15,14 +90,42 @@ pub(crate) enum PidFile {
 pub struct Command {
     pub(crate) program: OsString,
     pub(crate) args: Vec<OsString>,
-    pub(crate) envs: Vec<(OsString, OsString)>,
+    pub(crate) env_base: EnvBase,
+    /// Overrides in call order; `None` removes the variable.
+    pub(crate) envs: Vec<(OsString, Option<OsString>)>,
     pub(crate) current_dir: Option<PathBuf>,
     pub(crate) encoding: Option<&'static encoding_rs::Encoding>,
+    pub(crate) raw_output: bool,
     pub(crate) hide_window: bool,
     pub(crate) kill_grace: Duration,
     pub(crate) event_channel_capacity: usize,
+    pub(crate) output_buffer_size: usize,
+    pub(crate) output_overflow: OutputOverflow,
     pub(crate) timeout: Option<Duration>,
     pub(crate) pipe_stdin: bool,
+    pub(crate) stdin_source: Option<StdinSource>,
+    #[cfg(unix)]
+    pub(crate) pty: Option<super::pty::PtySize>,
+    #[cfg(unix)]
+    pub(crate) detach_session: bool,
+    /// Descriptors handed to the child, as `(source, target)`.
+    #[cfg(unix)]
+    pub(crate) passed_fds: Vec<(std::os::fd::OwnedFd, std::os::fd::RawFd)>,
+    /// How many of `passed_fds` are socket-activation listeners at 3, 4, ...
+    #[cfg(unix)]
+    pub(crate) listen_fds: usize,
+    #[cfg(unix)]
+    pub(crate) uid: Option<u32>,
+    #[cfg(unix)]
+    pub(crate) gid: Option<u32>,
+    #[cfg(unix)]
+    pub(crate) groups: Option<Vec<u32>>,
+    #[cfg(unix)]
+    pub(crate) user: Option<String>,
+    #[cfg(target_os = "linux")]
+    pub(crate) ambient_caps: Vec<crate::os::caps::Capability>,
+    #[cfg(target_os = "linux")]
+    pub(crate) sandbox: Option<super::sandbox::Sandbox>,
     pub(crate) pid_file: Option<PidFile>,
 }
 
@@ -32,14 +135,39 @@ impl Command {
         Self {
             program: program.as_ref().to_os_string(),
             args: Vec::new(),
+            env_base: EnvBase::Inherit,
             envs: Vec::new(),
             current_dir: None,
             encoding: None,
+            raw_output: false,
             hide_window: true,
             kill_grace: Duration::from_secs(5),
             event_channel_capacity: 64,
+            output_buffer_size: 256 * 1024,
+            output_overflow: OutputOverflow::DropOldest,
             timeout: None,
             pipe_stdin: false,
+            stdin_source: None,
+            #[cfg(unix)]
+            pty: None,
+            #[cfg(unix)]
+            detach_session: false,
+            #[cfg(unix)]
+            passed_fds: Vec::new(),
+            #[cfg(unix)]
+            listen_fds: 0,
+            #[cfg(unix)]
+            uid: None,
+            #[cfg(unix)]
+            gid: None,
+            #[cfg(unix)]
+            groups: None,
+            #[cfg(unix)]
+            user: None,
+            #[cfg(target_os = "linux")]
+            ambient_caps: Vec::new(),
+            #[cfg(target_os = "linux")]
+            sandbox: None,
             pid_file: None,
         }
     }
@@ -64,7 +192,52 @@ impl Command {
     /// Sets an environment variable for the child.
     pub fn env(mut self, k: impl AsRef<OsStr>, v: impl AsRef<OsStr>) -> Self {
         self.envs
-            .push((k.as_ref().to_os_string(), v.as_ref().to_os_string()));
+            .push((k.as_ref().to_os_string(), Some(v.as_ref().to_os_string())));
+        self
+    }
+
+    /// Sets environment variables for the child in iteration order.
+    pub fn envs<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.envs.extend(
+            vars.into_iter()
+                .map(|(k, v)| (k.as_ref().to_os_string(), Some(v.as_ref().to_os_string()))),
+        );
+        self
+    }
+
+    /// Removes a variable from the child's environment, whether inherited or
+    /// set by an earlier [`Command::env`].
+    pub fn env_remove(mut self, k: impl AsRef<OsStr>) -> Self {
+        self.envs.push((k.as_ref().to_os_string(), None));
+        self
+    }
+
+    /// Starts the child from an empty environment and discards variables set
+    /// so far; later [`Command::env`] calls still apply.
+    pub fn env_clear(mut self) -> Self {
+        self.env_base = EnvBase::Clear;
+        self.envs.clear();
+        self
+    }
+
+    /// Inherits only the listed variables from the manager's environment,
+    /// read when the child is spawned. Variables set with [`Command::env`] are
+    /// kept. Replaces an earlier [`Command::env_clear`] or allow-list.
+    pub fn inherit_env_only<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.env_base = EnvBase::Only(
+            keys.into_iter()
+                .map(|key| key.as_ref().to_os_string())
+                .collect(),
+        );
         self
     }
 
@@ -80,6 +253,21 @@ impl Command {
         self
     }
 
+    /// Delivers output as undecoded [`super::event::ProcessEvent::StdoutBytes`]
+    /// and [`super::event::ProcessEvent::StderrBytes`] chunks instead of text.
+    ///
+    /// Chunks are the bytes exactly as the child wrote them, in the sizes the
+    /// pipe returned them; concatenating a stream's chunks reproduces it. Raw
+    /// mode ignores [`Command::encoding`]. Use [`Command::output_bytes`] for an
+    /// undecoded one-shot capture.
+    ///
+    /// Only supported on Unix; elsewhere [`Command::spawn`] fails with
+    /// [`super::error::ProcessError::RawOutputUnsupported`].
+    pub fn raw_output(mut self, raw: bool) -> Self {
+        self.raw_output = raw;
+        self
+    }
+
     /// Controls whether the child window is hidden on Windows.
     pub fn hide_window(mut self, hide: bool) -> Self {
         self.hide_window = hide;
@@ -94,14 +282,30 @@ impl Command {
 
     /// Sets the process-event channel capacity.
     ///
-    /// A full channel pauses the event pump and therefore pipe reads. Receivers
-    /// should drain promptly: once the engine's 256 KiB output ring fills, its
-    /// oldest lines are silently dropped by design.
+    /// Output that does not fit into a full channel waits in the output ring
+    /// (see [`Command::output_buffer_size`]). Receivers should drain promptly.
     pub fn event_channel_capacity(mut self, cap: usize) -> Self {
         self.event_channel_capacity = cap.max(1);
         self
     }
 
+    /// Sets the output ring size in bytes (default 256 KiB).
+    ///
+    /// The ring holds lines the event channel cannot accept yet. On Unix,
+    /// lines and raw chunks never exceed this size: a longer line is delivered
+    /// in pieces, so the ring bounds memory even for output without newlines.
+    pub fn output_buffer_size(mut self, bytes: usize) -> Self {
+        self.output_buffer_size = bytes.max(1);
+        self
+    }
+
+    /// Sets what happens when the output ring is full. Discarded lines are
+    /// reported through [`super::event::ProcessEvent::OutputDropped`].
+    pub fn output_overflow(mut self, overflow: OutputOverflow) -> Self {
+        self.output_overflow = overflow;
+        self
+    }
+
     /// Sets the maximum process lifetime before the whole process tree is killed.
     pub fn timeout(mut self, d: Duration) -> Self {
         self.timeout = Some(d);
@@ -114,6 +318,165 @@ impl Command {
         self
     }
 
+    /// Feeds `source` to the child's stdin and closes the pipe at its end.
+    ///
+    /// Takes precedence over [`Command::pipe_stdin`]: the pipe belongs to the
+    /// feed, so [`super::handle::ProcessHandle::write_stdin`] returns
+    /// [`super::error::ProcessError::StdinUnavailable`].
+    pub fn stdin_from(mut self, source: impl Into<StdinSource>) -> Self {
+        self.stdin_source = Some(source.into());
+        self
+    }
+
+    /// Runs the child on a pseudo-terminal of `size` so it sees a TTY.
+    ///
+    /// stdout and stderr are merged by the terminal and arrive as
+    /// [`super::event::ProcessEvent::Stdout`] lines, with the terminal's
+    /// `\r\n` line endings normalized; [`Command::raw_output`] chunks keep
+    /// them. With [`Command::pipe_stdin`] writes go
+    /// to the terminal and [`super::handle::ProcessHandle::close_stdin`] sends
+    /// its EOF character. The child keeps the usual containment and kill
+    /// semantics. The pty only becomes its controlling terminal together with
+    /// [`Command::detach_session`]; otherwise programs that open `/dev/tty`
+    /// directly still fail.
+    #[cfg(unix)]
+    pub fn pty(mut self, size: super::pty::PtySize) -> Self {
+        self.pty = Some(size);
+        self
+    }
+
+    /// Runs the child in a new session (`setsid`), so Ctrl+C or a hang-up in
+    /// the terminal the manager was started from does not reach it and it only
+    /// stops through [`super::handle::ProcessHandle::graceful_kill`] or
+    /// [`super::handle::ProcessHandle::kill`]. Containment is unchanged; with
+    /// [`super::handle::Containment::ProcessGroup`] the child still leads a
+    /// group whose id is its pid.
+    #[cfg(unix)]
+    pub fn detach_session(mut self, detach: bool) -> Self {
+        self.detach_session = detach;
+        self
+    }
+
+    /// Hands `fd` to the child as descriptor `target`, e.g. a socket bound to
+    /// a privileged port before [`Command::uid`] drops root. `target` must be
+    /// 3 or above and used once; spawning fails with an `InvalidInput` I/O
+    /// error otherwise. The manager's copy is closed along with the command.
+    #[cfg(unix)]
+    pub fn pass_fd(mut self, fd: std::os::fd::OwnedFd, target: std::os::fd::RawFd) -> Self {
+        self.passed_fds.push((fd, target));
+        self
+    }
+
+    /// Hands `fds` to the child the way systemd socket activation does: as
+    /// descriptors 3, 4, ... in order, with `LISTEN_FDS` set to their count
+    /// and `LISTEN_PID` to the child's pid, which `sd_listen_fds` checks.
+    /// Further [`Command::pass_fd`] targets must come after them.
+    #[cfg(unix)]
+    pub fn listen_fds(mut self, fds: impl IntoIterator<Item = std::os::fd::OwnedFd>) -> Self {
+        for fd in fds {
+            let target = 3 + self.listen_fds as std::os::fd::RawFd;
+            self.passed_fds.push((fd, target));
+            self.listen_fds += 1;
+        }
+        self
+    }
+
+    /// Runs the child with this user id. Unless [`Command::groups`] is set, a
+    /// root manager also clears the child's supplementary groups.
+    #[cfg(unix)]
+    pub fn uid(mut self, uid: u32) -> Self {
+        self.uid = Some(uid);
+        self
+    }
+
+    /// Runs the child with this primary group id.
+    #[cfg(unix)]
+    pub fn gid(mut self, gid: u32) -> Self {
+        self.gid = Some(gid);
+        self
+    }
+
+    /// Replaces the child's supplementary groups.
+    #[cfg(unix)]
+    pub fn groups(mut self, groups: impl IntoIterator<Item = u32>) -> Self {
+        self.groups = Some(groups.into_iter().collect());
+        self
+    }
+
+    /// Runs the child as the named user, with that user's uid, primary group
+    /// and, if the manager may set them (root, or `CAP_SETGID` on Linux),
+    /// supplementary groups; otherwise the child keeps the manager's
+    /// supplementary groups. The name is resolved at spawn, failing with
+    /// [`super::error::ProcessError::UnknownUser`]; explicit [`Command::uid`],
+    /// [`Command::gid`] and [`Command::groups`] take precedence. The
+    /// environment (`HOME`, `USER`, ...) is left as configured.
+    #[cfg(unix)]
+    pub fn user(mut self, name: impl Into<String>) -> Self {
+        self.user = Some(name.into());
+        self
+    }
+
+    /// Hands `caps` to the child as ambient capabilities, so they survive
+    /// [`Command::uid`] and `execve` into an ordinary binary. The child gets
+    /// exactly these capabilities. Spawning fails with
+    /// [`super::error::ProcessError::MissingCapabilities`] unless each is in
+    /// the manager's permitted and bounding sets (see [`crate::os::caps::current`]).
+    #[cfg(target_os = "linux")]
+    pub fn ambient_caps(
+        mut self,
+        caps: impl IntoIterator<Item = crate::os::caps::Capability>,
+    ) -> Self {
+        self.ambient_caps = caps.into_iter().collect();
+        self
+    }
+
+    /// Confines the child with `sandbox`, applied after the credential
+    /// changes and right before `exec`. Fails closed: an option the host
+    /// cannot honour fails the spawn with
+    /// [`super::error::ProcessError::SandboxUnavailable`].
+    #[cfg(target_os = "linux")]
+    pub fn sandbox(mut self, sandbox: super::sandbox::Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// The child's environment: the [`EnvBase`] with the overrides applied.
+    pub(crate) fn resolved_env(&self) -> Vec<(OsString, OsString)> {
+        let mut env: Vec<(OsString, OsString)> = match &self.env_base {
+            EnvBase::Inherit => std::env::vars_os().collect(),
+            EnvBase::Clear => Vec::new(),
+            EnvBase::Only(keys) => keys
+                .iter()
+                .filter_map(|key| Some((key.clone(), std::env::var_os(key)?)))
+                .collect(),
+        };
+        for (key, value) in &self.envs {
+            env.retain(|(existing, _)| existing != key);
+            if let Some(value) = value {
+                env.push((key.clone(), value.clone()));
+            }
+        }
+        env
+    }
+
+    /// Whether the environment is set up between fork and exec instead of by
+    /// the engine, because a value depends on the child's pid.
+    pub(crate) fn env_in_child(&self) -> bool {
+        #[cfg(unix)]
+        if self.listen_fds > 0 {
+            return true;
+        }
+        false
+    }
+
+    /// Whether the one-shot captures must go through the streaming engine.
+    /// On Unix they always do, since only the pump reports the signal that
+    /// ended the child.
+    #[cfg(not(unix))]
+    fn needs_streaming_capture(&self) -> bool {
+        self.stdin_source.is_some()
+    }
+
     /// Records the child pid at `path` in the legacy numeric format.
     ///
     /// Numeric records cannot prove epoch/start identity and therefore do not
@@ -134,9 +497,10 @@ impl Command {
     /// final event on the channel; use [`super::handle::ProcessHandle::wait`]
     /// for the authoritative termination signal.
     ///
-    /// Event delivery applies backpressure: a full channel pauses the pump and
-    /// pipe reads, and output beyond the engine's 256 KiB ring silently drops
-    /// the oldest lines. Drain the receiver promptly. If a receiver stops
+    /// Event delivery applies backpressure: a full channel parks output in the
+    /// output ring, which overflows according to [`Command::output_overflow`]
+    /// and reports losses as [`super::event::ProcessEvent::OutputDropped`].
+    /// Drain the receiver promptly. If a receiver stops
     /// draining during termination, buffered output — including the terminal
     /// events — is dropped after five seconds so
     /// [`super::handle::ProcessHandle::kill`],
@@ -157,14 +521,69 @@ impl Command {
             containment: parts.containment,
             ctrl: parts.ctrl_tx,
             terminated: parts.terminated_rx,
+            #[cfg(unix)]
+            pty: parts.pty,
         };
         Ok((handle, parts.events_rx))
     }
 
     /// One-shot run capturing stdout/stderr. A non-zero exit is data, not an error;
     /// launch failures, timeouts, and execution-engine failures are `Err`.
+    ///
+    /// The output is captured byte for byte; with a pty it is what the
+    /// terminal produced, so lines end in `\r\n`. On Windows a capture with
+    /// [`Command::stdin_from`] is collected line by line instead, so each
+    /// captured line ends in `\n`, and [`Command::output_bytes`] fails with
+    /// [`super::error::ProcessError::RawOutputUnsupported`].
     pub async fn output(self) -> Result<super::error::ProcessOutput, super::error::ProcessError> {
-        super::engine::run_capture(self).await
+        #[cfg(not(unix))]
+        if !self.needs_streaming_capture() {
+            return super::engine::run_capture(self).await;
+        }
+        super::capture::run_collected_text(self).await
+    }
+
+    /// Like [`Command::output`], but bounds how much of each stream is kept.
+    ///
+    /// A stream over its budget keeps its first and last bytes (or only the
+    /// last with [`super::capture::OutputLimits::tail_only`]) and reports the
+    /// elided byte count, so a misbehaving binary cannot balloon memory.
+    pub async fn output_with(
+        self,
+        limits: super::capture::OutputLimits,
+    ) -> Result<
+        super::error::ProcessOutput<super::capture::CapturedStream>,
+        super::error::ProcessError,
+    > {
+        super::capture::run_limited(self, limits, |_| {}).await
+    }
+
+    /// Like [`Command::output_with`], additionally passing every event to
+    /// `on_event` as it arrives, before it is captured.
+    pub async fn output_with_callback<F>(
+        self,
+        limits: super::capture::OutputLimits,
+        on_event: F,
+    ) -> Result<
+        super::error::ProcessOutput<super::capture::CapturedStream>,
+        super::error::ProcessError,
+    >
+    where
+        F: FnMut(&super::event::ProcessEvent),
+    {
+        super::capture::run_limited(self, limits, on_event).await
+    }
+
+    /// Like [`Command::output`], but returns the stdout/stderr bytes undecoded,
+    /// so binary or non-UTF-8 output survives intact.
+    pub async fn output_bytes(
+        self,
+    ) -> Result<super::error::ProcessOutput<Vec<u8>>, super::error::ProcessError> {
+        #[cfg(not(unix))]
+        if !self.needs_streaming_capture() {
+            return super::engine::run_capture_bytes(self).await;
+        }
+        super::capture::run_collected(self.raw_output(true)).await
     }
 }
 
@@ -177,10 +596,16 @@ mod tests {
     fn defaults_match_design() {
         let c = Command::new("prog");
         assert_eq!(c.event_channel_capacity, 64);
+        assert_eq!(c.output_buffer_size, 256 * 1024);
+        assert_eq!(c.output_overflow, OutputOverflow::DropOldest);
         assert_eq!(c.kill_grace, Duration::from_secs(5));
         assert!(c.hide_window);
         assert!(!c.pipe_stdin);
+        assert!(c.stdin_source.is_none());
+        assert_eq!(c.env_base, EnvBase::Inherit);
+        assert!(c.envs.is_empty());
         assert!(c.encoding.is_none());
+        assert!(!c.raw_output);
         assert!(c.pid_file.is_none());
         assert!(c.timeout.is_none());
     }
@@ -202,4 +627,23 @@ mod tests {
         assert!(c.pipe_stdin);
         assert!(!c.hide_window);
     }
+
+    #[test]
+    fn env_overrides_keep_call_order() {
+        let c = Command::new("prog")
+            .env("DROPPED", "1")
+            .env_clear()
+            .envs([("A", "1"), ("B", "2")])
+            .env_remove("A")
+            .inherit_env_only(["PATH"]);
+        assert_eq!(c.env_base, EnvBase::Only(vec!["PATH".into()]));
+        assert_eq!(
+            c.envs,
+            vec![
+                ("A".into(), Some("1".into())),
+                ("B".into(), Some("2".into())),
+                ("A".into(), None),
+            ]
+        );
+    }
 }
@@ -0,0 +1,442 @@
+//! The containment a recorded core was spawned into.
+//!
+//! Descendants that reparent before the reap's snapshots cannot be attributed
+//! through the process tree. The kernel group they were started in outlives
+//! the manager, so the epoch record keeps its identity: the cgroup v2 path
+//! under `Containment::CgroupV2`, the process group and session otherwise.
+//! Reaping then kills everything still in that group. Job objects die with
+//! their last handle, so Windows records nothing.
+//!
+//! Group ids outlive their members only by convention: after a reboot or pid
+//! wraparound the same path or pgid can name someone else's processes. A
+//! cgroup is therefore also recorded by its boot-bound directory inode and
+//! only killed while that still matches; a process group has no such
+//! identity and is only killed while the recorded root pins its id.
+
+use std::path::{Component, Path, PathBuf};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{
+    handle::Containment,
+    pid_file::{hex_decode, hex_encode, invalid_data},
+};
+
+/// Kernel group identity stored in an epoch pid record.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "kebab-case", try_from = "RawContainment")
+)]
+pub enum RecordedContainment {
+    /// The core's cgroup, relative to the cgroup v2 mount. `instance` hashes
+    /// the boot id with the cgroup directory's inode, so a cgroup recreated
+    /// under the same path does not match.
+    CgroupV2 {
+        path: PathBuf,
+        instance: Option<u64>,
+    },
+    /// The core's process group and session.
+    ProcessGroup { pgid: u32, sid: u32 },
+}
+
+impl RecordedContainment {
+    /// Reads the group `pid` was spawned into. `None` when the mechanism has
+    /// no persistent identity or the group is shared with this manager, which
+    /// a reap must never kill.
+    pub(crate) fn capture(pid: u32, containment: Containment) -> Option<Self> {
+        #[cfg(not(unix))]
+        let _ = pid;
+        match containment {
+            #[cfg(target_os = "linux")]
+            Containment::CgroupV2 => {
+                let path = linux::cgroup_of(&pid.to_string()).ok()??;
+                let own = linux::cgroup_of("self").ok()??;
+                (valid_cgroup(&path) && !own.starts_with(&path)).then(|| Self::CgroupV2 {
+                    instance: linux::cgroup_instance(&path).ok().flatten(),
+                    path,
+                })
+            }
+            #[cfg(unix)]
+            Containment::ProcessGroup => {
+                // SAFETY: getpgid and getsid only read the ids of a numeric pid.
+                let (pgid, sid, own) = unsafe {
+                    (
+                        libc::getpgid(pid as libc::pid_t),
+                        libc::getsid(pid as libc::pid_t),
+                        libc::getpgid(0),
+                    )
+                };
+                (pgid > 0 && sid > 0 && pgid != own).then_some(Self::ProcessGroup {
+                    pgid: pgid as u32,
+                    sid: sid as u32,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub(super) fn serialize(&self) -> String {
+        match self {
+            Self::CgroupV2 { path, instance } => {
+                let path = hex_encode(path.as_os_str().as_encoded_bytes());
+                match instance {
+                    Some(instance) => format!("cgroup2:{path}:{instance}"),
+                    None => format!("cgroup2:{path}"),
+                }
+            }
+            Self::ProcessGroup { pgid, sid } => format!("pgrp:{pgid}:{sid}"),
+        }
+    }
+
+    pub(super) fn parse(value: &str) -> std::io::Result<Self> {
+        let invalid = || invalid_data("invalid epoch pid containment");
+        match value.split_once(':').ok_or_else(invalid)? {
+            ("cgroup2", value) => {
+                let (path, instance) = match value.split_once(':') {
+                    Some((path, instance)) => {
+                        (path, Some(instance.parse().map_err(|_| invalid())?))
+                    }
+                    None => (value, None),
+                };
+                let path =
+                    PathBuf::from(String::from_utf8(hex_decode(path)?).map_err(|_| invalid())?);
+                if !valid_cgroup(&path) {
+                    return Err(invalid());
+                }
+                Ok(Self::CgroupV2 { path, instance })
+            }
+            ("pgrp", ids) => {
+                let (pgid, sid) = ids.split_once(':').ok_or_else(invalid)?;
+                let pgid = pgid.parse().map_err(|_| invalid())?;
+                let sid = sid.parse().map_err(|_| invalid())?;
+                if pgid == 0 || sid == 0 {
+                    return Err(invalid());
+                }
+                Ok(Self::ProcessGroup { pgid, sid })
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Kills every process still in the group. `root_alive` says the recorded
+    /// root was just validated, which keeps the group's id from being reused.
+    /// Otherwise only a cgroup whose recorded instance still matches is
+    /// killed. Returns whether anything was signalled.
+    pub(super) async fn kill_members(&self, root_alive: bool) -> std::io::Result<bool> {
+        #[cfg(target_os = "linux")]
+        {
+            match self {
+                Self::CgroupV2 { path, instance } => {
+                    if !root_alive
+                        && (instance.is_none() || linux::cgroup_instance(path)? != *instance)
+                    {
+                        tracing::debug!(
+                            "not killing cgroup {}: it cannot be proven to be the recorded one",
+                            path.display()
+                        );
+                        return Ok(false);
+                    }
+                    linux::kill_cgroup(path).await
+                }
+                Self::ProcessGroup { pgid, sid } => {
+                    if !root_alive {
+                        tracing::debug!(
+                            "not killing process group {pgid}: its root has exited and the id may be reused"
+                        );
+                        return Ok(false);
+                    }
+                    linux::kill_process_group(*pgid, *sid).await
+                }
+            }
+        }
+        #[cfg(all(unix, not(target_os = "linux")))]
+        {
+            let Self::ProcessGroup { pgid, .. } = self else {
+                return Ok(false);
+            };
+            // SAFETY: getpgid(0) only reads this process's group.
+            if !root_alive || unsafe { libc::getpgid(0) } as u32 == *pgid {
+                return Ok(false);
+            }
+            // SAFETY: the recorded root is a live member of the group.
+            if unsafe { libc::killpg(*pgid as libc::pid_t, libc::SIGKILL) } == 0 {
+                return Ok(true);
+            }
+            let error = std::io::Error::last_os_error();
+            match error.raw_os_error() {
+                Some(libc::ESRCH) => Ok(false),
+                _ => Err(error),
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = root_alive;
+            Ok(false)
+        }
+    }
+}
+
+/// The unvalidated shape of [`RecordedContainment`], so a deserialized record
+/// cannot name the root cgroup either.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum RawContainment {
+    CgroupV2 {
+        path: PathBuf,
+        #[serde(default)]
+        instance: Option<u64>,
+    },
+    ProcessGroup {
+        pgid: u32,
+        sid: u32,
+    },
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RawContainment> for RecordedContainment {
+    type Error = String;
+
+    fn try_from(raw: RawContainment) -> Result<Self, String> {
+        match raw {
+            RawContainment::CgroupV2 { path, instance } if valid_cgroup(&path) => {
+                Ok(Self::CgroupV2 { path, instance })
+            }
+            RawContainment::CgroupV2 { path, .. } => {
+                Err(format!("invalid cgroup `{}`", path.display()))
+            }
+            RawContainment::ProcessGroup { pgid, sid } if pgid != 0 && sid != 0 => {
+                Ok(Self::ProcessGroup { pgid, sid })
+            }
+            RawContainment::ProcessGroup { .. } => Err("invalid process group".into()),
+        }
+    }
+}
+
+/// An absolute, normalized path below the cgroup root. Killing the root
+/// cgroup would take down the whole host.
+fn valid_cgroup(path: &Path) -> bool {
+    path.has_root()
+        && path.components().count() > 1
+        && path
+            .components()
+            .all(|part| matches!(part, Component::RootDir | Component::Normal(_)))
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    use crate::os::PidHandle;
+
+    const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+    const KILL_WAIT: Duration = Duration::from_secs(5);
+    const POLL: Duration = Duration::from_millis(50);
+
+    /// The unified (`0::`) entry of `/proc/{pid}/cgroup`.
+    pub(super) fn cgroup_of(pid: &str) -> std::io::Result<Option<PathBuf>> {
+        let raw = match std::fs::read_to_string(format!("/proc/{pid}/cgroup")) {
+            Ok(raw) => raw,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        Ok(raw
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .map(PathBuf::from))
+    }
+
+    /// The boot-bound inode of the cgroup's directory, or `None` once it is
+    /// gone.
+    pub(super) fn cgroup_instance(path: &Path) -> std::io::Result<Option<u64>> {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = Path::new(CGROUP_ROOT).join(path.strip_prefix("/").unwrap_or(path));
+        let inode = match std::fs::metadata(dir) {
+            Ok(metadata) => metadata.ino(),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let boot_id = std::fs::read_to_string("/proc/sys/kernel/random/boot_id")?;
+        Ok(Some(crate::process::pid_file::boot_bound_start_token(
+            boot_id.trim(),
+            inode,
+        )))
+    }
+
+    /// Prefers `cgroup.kill` (Linux 5.14+), which also reaches processes
+    /// forked during the kill; older kernels signal each member in turn.
+    pub(super) async fn kill_cgroup(path: &Path) -> std::io::Result<bool> {
+        if cgroup_of("self")?.is_some_and(|own| own.starts_with(path)) {
+            tracing::warn!(
+                "refusing to kill cgroup {}: it holds the manager",
+                path.display()
+            );
+            return Ok(false);
+        }
+        let dir = Path::new(CGROUP_ROOT).join(path.strip_prefix("/").unwrap_or(path));
+        let members = match cgroup_members(&dir) {
+            Ok(members) => members,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(error) => return Err(error),
+        };
+        if members.is_empty() {
+            let _ = std::fs::remove_dir(&dir);
+            return Ok(false);
+        }
+        match std::fs::write(dir.join("cgroup.kill"), "1") {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                for pid in members {
+                    let Ok(handle) = PidHandle::open(pid) else {
+                        continue;
+                    };
+                    if cgroup_of(&pid.to_string()).ok().flatten().as_deref() == Some(path) {
+                        let _ = handle.send_signal(nix::sys::signal::Signal::SIGKILL);
+                    }
+                }
+            }
+            Err(error) => return Err(error),
+        }
+        let deadline = tokio::time::Instant::now() + KILL_WAIT;
+        while !cgroup_members(&dir).unwrap_or_default().is_empty() {
+            if tokio::time::Instant::now() >= deadline {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("cgroup {} survived the kill", path.display()),
+                ));
+            }
+            tokio::time::sleep(POLL).await;
+        }
+        let _ = std::fs::remove_dir(&dir);
+        Ok(true)
+    }
+
+    fn cgroup_members(dir: &Path) -> std::io::Result<Vec<u32>> {
+        Ok(std::fs::read_to_string(dir.join("cgroup.procs"))?
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect())
+    }
+
+    /// Each member is pinned by a [`PidHandle`] before its group is checked
+    /// again, so a pid reused in between is not signalled.
+    pub(super) async fn kill_process_group(pgid: u32, sid: u32) -> std::io::Result<bool> {
+        // SAFETY: getpgid(0) only reads this process's group.
+        if unsafe { libc::getpgid(0) } as u32 == pgid {
+            tracing::warn!("refusing to kill process group {pgid}: it holds the manager");
+            return Ok(false);
+        }
+        let mut handles = Vec::new();
+        for pid in group_members(pgid, sid)? {
+            let Ok(handle) = PidHandle::open(pid) else {
+                continue;
+            };
+            if group_of(pid) != Some((pgid, sid)) {
+                continue;
+            }
+            match handle.send_signal(nix::sys::signal::Signal::SIGKILL) {
+                Ok(()) => handles.push(handle),
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error),
+            }
+        }
+        for handle in &handles {
+            tokio::time::timeout(KILL_WAIT, handle.wait())
+                .await
+                .map_err(|_| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("group member {} survived SIGKILL", handle.pid()),
+                    )
+                })??;
+        }
+        Ok(!handles.is_empty())
+    }
+
+    fn group_members(pgid: u32, sid: u32) -> std::io::Result<Vec<u32>> {
+        let own = std::process::id();
+        let mut members = Vec::new();
+        for entry in std::fs::read_dir("/proc")? {
+            let Some(pid) = entry?
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse().ok())
+            else {
+                continue;
+            };
+            if pid != own && group_of(pid) == Some((pgid, sid)) {
+                members.push(pid);
+            }
+        }
+        Ok(members)
+    }
+
+    /// `(pgrp, session)` from `/proc/{pid}/stat`; zombies count as gone.
+    fn group_of(pid: u32) -> Option<(u32, u32)> {
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+        let (_, rest) = stat.rsplit_once(") ")?;
+        let mut fields = rest.split_whitespace();
+        if fields.next()? == "Z" {
+            return None;
+        }
+        let _ppid = fields.next()?;
+        Some((fields.next()?.parse().ok()?, fields.next()?.parse().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn containment_round_trips_and_rejects_unsafe_cgroups() {
+        for value in [
+            RecordedContainment::CgroupV2 {
+                path: PathBuf::from("/nyanpasu/core 3"),
+                instance: Some(77),
+            },
+            RecordedContainment::CgroupV2 {
+                path: PathBuf::from("/nyanpasu/core 3"),
+                instance: None,
+            },
+            RecordedContainment::ProcessGroup { pgid: 12, sid: 10 },
+        ] {
+            assert_eq!(
+                RecordedContainment::parse(&value.serialize()).unwrap(),
+                value
+            );
+        }
+        for path in ["/", "relative", "/a/../b"] {
+            let raw = RecordedContainment::CgroupV2 {
+                path: PathBuf::from(path),
+                instance: None,
+            }
+            .serialize();
+            assert!(RecordedContainment::parse(&raw).is_err(), "{path}");
+        }
+        assert!(RecordedContainment::parse("pgrp:0:1").is_err());
+        assert!(RecordedContainment::parse("job:1").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_managers_own_group_is_never_recorded() {
+        assert_eq!(
+            RecordedContainment::capture(std::process::id(), Containment::ProcessGroup),
+            None
+        );
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            RecordedContainment::capture(std::process::id(), Containment::CgroupV2),
+            None
+        );
+    }
+}
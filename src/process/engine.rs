@@ -2,13 +2,17 @@
 
 use std::{collections::VecDeque, future::pending, sync::Arc, time::Duration};
 
+#[cfg(not(unix))]
 use processkit::prelude::StreamExt;
-use tokio::sync::{mpsc, oneshot, watch};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::{mpsc, oneshot, watch},
+};
 
 use super::{
-    command::{Command, PidFile},
+    command::{Command, EnvBase, OutputOverflow, PidFile, StdinSource},
     error::ProcessError,
-    event::{ProcessEvent, TerminatedPayload},
+    event::{ExitReason, ProcessEvent, TerminatedPayload},
     handle::{Containment, Ctrl},
     pid_file::PidFileGuard,
 };
@@ -19,14 +23,19 @@ pub(crate) struct SpawnParts {
     pub ctrl_tx: mpsc::Sender<Ctrl>,
     pub terminated_rx: watch::Receiver<Option<Result<TerminatedPayload, String>>>,
     pub events_rx: mpsc::Receiver<ProcessEvent>,
+    #[cfg(unix)]
+    pub pty: Option<Arc<super::pty::PtyChild>>,
 }
 
 struct PumpParts {
     run: processkit::RunningProcess,
-    events: processkit::OutputEvents,
+    output: OutputSource,
     group: Arc<processkit::ProcessGroup>,
-    stdin_tx: Option<mpsc::Sender<StdinWrite>>,
+    stdin_tx: Option<mpsc::Sender<StdinRequest>>,
     kill_grace: Duration,
+    output_buffer_size: usize,
+    output_overflow: OutputOverflow,
+    started_at: tokio::time::Instant,
     timeout_at: Option<tokio::time::Instant>,
     ev_tx: mpsc::Sender<ProcessEvent>,
     ctrl_rx: mpsc::Receiver<Ctrl>,
@@ -36,13 +45,34 @@ struct PumpParts {
 
 fn build_pk(cmd: &Command, include_timeout: bool) -> processkit::Command {
     let mut pk = processkit::Command::new(&cmd.program).args(&cmd.args);
-    for (key, value) in &cmd.envs {
-        pk = pk.env(key, value);
+    // Otherwise the child's environment is installed by ChildSetup, and the
+    // engine must leave it alone so that exec picks it up.
+    if !cmd.env_in_child() {
+        match &cmd.env_base {
+            EnvBase::Inherit => {}
+            EnvBase::Clear => pk = pk.env_clear(),
+            EnvBase::Only(keys) => {
+                pk = pk.env_clear();
+                for key in keys {
+                    if let Some(value) = std::env::var_os(key) {
+                        pk = pk.env(key, value);
+                    }
+                }
+            }
+        }
+        for (key, value) in &cmd.envs {
+            pk = match value {
+                Some(value) => pk.env(key, value),
+                None => pk.env_remove(key),
+            };
+        }
     }
     if let Some(dir) = &cmd.current_dir {
         pk = pk.current_dir(dir);
     }
-    if let Some(encoding) = cmd.encoding {
+    if !cmd.raw_output
+        && let Some(encoding) = cmd.encoding
+    {
         pk = pk.encoding(encoding);
     }
     if include_timeout && let Some(timeout) = cmd.timeout {
@@ -52,28 +82,55 @@ fn build_pk(cmd: &Command, include_timeout: bool) -> processkit::Command {
     if cmd.hide_window {
         pk = pk.create_no_window();
     }
-    pk = pk.output_buffer(processkit::OutputBufferPolicy::unbounded().with_max_bytes(256 * 1024));
-    if cmd.pipe_stdin {
+    // On Unix the engine reads the child's output itself and this buffer stays
+    // empty. Elsewhere the pump drains it promptly and enforces the ring,
+    // counting every discarded line; the cap only bounds what piles up while
+    // `Block` has stopped draining.
+    pk = pk.output_buffer(
+        processkit::OutputBufferPolicy::unbounded().with_max_bytes(cmd.output_buffer_size),
+    );
+    if cmd.pipe_stdin || cmd.stdin_source.is_some() {
         pk = pk.keep_stdin_open();
     }
     pk
 }
 
+#[cfg(not(unix))]
+fn capture_error(program: String) -> impl FnOnce(processkit::Error) -> ProcessError {
+    move |error| match error {
+        processkit::Error::Spawn { .. } | processkit::Error::NotFound { .. } => {
+            ProcessError::Spawn {
+                program,
+                message: error.to_string(),
+            }
+        }
+        error => ProcessError::Engine(error.to_string()),
+    }
+}
+
+/// Installs the Unix fork-to-exec steps, if any are configured.
+#[cfg(unix)]
+fn with_child_setup(
+    pk: processkit::Command,
+    mut setup: super::child_setup::ChildSetup,
+) -> processkit::Command {
+    if setup.is_empty() {
+        return pk;
+    }
+    // SAFETY: ChildSetup::run only issues async-signal-safe syscalls on data
+    // prepared before fork.
+    unsafe { pk.pre_exec(move || setup.run()) }
+}
+
+/// processkit's one-shot capture. Only used where there are no signals to
+/// report; on Unix the captures run through the pump.
+#[cfg(not(unix))]
 pub(crate) async fn run_capture(cmd: Command) -> Result<super::error::ProcessOutput, ProcessError> {
     let program = cmd.program.to_string_lossy().into_owned();
     let timeout = cmd.timeout;
-    let result = build_pk(&cmd, true)
-        .output_string()
-        .await
-        .map_err(|error| match error {
-            processkit::Error::Spawn { .. } | processkit::Error::NotFound { .. } => {
-                ProcessError::Spawn {
-                    program,
-                    message: error.to_string(),
-                }
-            }
-            error => ProcessError::Engine(error.to_string()),
-        })?;
+    let pk = build_pk(&cmd, true);
+    let started_at = tokio::time::Instant::now();
+    let result = pk.output_string().await.map_err(capture_error(program))?;
 
     if result.timed_out() {
         return Err(ProcessError::Timeout {
@@ -83,11 +140,38 @@ pub(crate) async fn run_capture(cmd: Command) -> Result<super::error::ProcessOut
 
     Ok(super::error::ProcessOutput {
         code: result.code(),
+        reason: result.code().map(ExitReason::Exited),
+        runtime: started_at.elapsed(),
         stdout: result.stdout().clone(),
         stderr: result.stderr().to_owned(),
     })
 }
 
+#[cfg(not(unix))]
+pub(crate) async fn run_capture_bytes(
+    cmd: Command,
+) -> Result<super::error::ProcessOutput<Vec<u8>>, ProcessError> {
+    let program = cmd.program.to_string_lossy().into_owned();
+    let timeout = cmd.timeout;
+    let pk = build_pk(&cmd, true);
+    let started_at = tokio::time::Instant::now();
+    let result = pk.output_bytes().await.map_err(capture_error(program))?;
+
+    if result.timed_out() {
+        return Err(ProcessError::Timeout {
+            after: timeout.unwrap_or_default(),
+        });
+    }
+
+    Ok(super::error::ProcessOutput {
+        code: result.code(),
+        reason: result.code().map(ExitReason::Exited),
+        runtime: started_at.elapsed(),
+        stdout: result.stdout().to_vec(),
+        stderr: result.stderr().to_vec(),
+    })
+}
+
 fn map_containment(mechanism: processkit::Mechanism) -> Containment {
     match mechanism {
         processkit::Mechanism::JobObject => Containment::JobObject,
@@ -97,13 +181,56 @@ fn map_containment(mechanism: processkit::Mechanism) -> Containment {
     }
 }
 
-fn map_outcome(outcome: &processkit::Outcome) -> TerminatedPayload {
+fn map_outcome(
+    outcome: &processkit::Outcome,
+    stop_cause: Option<ExitReason>,
+    runtime: Duration,
+) -> TerminatedPayload {
     TerminatedPayload {
         code: outcome.code(),
         signal: outcome.signal(),
+        reason: classify_exit(
+            outcome.code(),
+            outcome.signal(),
+            core_dumped(outcome),
+            stop_cause,
+        ),
+        runtime,
     }
 }
 
+/// The wait status's `WCOREDUMP` bit.
+#[cfg(unix)]
+fn core_dumped(outcome: &processkit::Outcome) -> bool {
+    use std::os::unix::process::ExitStatusExt;
+
+    outcome.status().is_some_and(|status| status.core_dumped())
+}
+
+#[cfg(not(unix))]
+fn core_dumped(_outcome: &processkit::Outcome) -> bool {
+    false
+}
+
+/// A manager-initiated stop explains the exit better than the raw status: a
+/// hard kill otherwise reads as an ordinary `SIGKILL` (or exit code 1 on
+/// Windows) and is indistinguishable from a crash.
+fn classify_exit(
+    code: Option<i32>,
+    signal: Option<i32>,
+    core_dumped: bool,
+    stop_cause: Option<ExitReason>,
+) -> Option<ExitReason> {
+    stop_cause
+        .or_else(|| {
+            signal.map(|signal| ExitReason::Signaled {
+                signal,
+                core_dumped,
+            })
+        })
+        .or_else(|| code.map(ExitReason::Exited))
+}
+
 struct FinishOutput {
     payload: TerminatedPayload,
     stderr_tail: Vec<String>,
@@ -128,10 +255,15 @@ fn split_stderr_tail(stderr: &str) -> Vec<String> {
     }
 }
 
-fn normalize_finish(result: processkit::Result<processkit::Finished>) -> FinishOutput {
+fn normalize_finish(
+    result: processkit::Result<processkit::Finished>,
+    stop_cause: Option<ExitReason>,
+    started_at: tokio::time::Instant,
+) -> FinishOutput {
+    let runtime = started_at.elapsed();
     match result {
         Ok(finished) => FinishOutput {
-            payload: map_outcome(&finished.outcome),
+            payload: map_outcome(&finished.outcome, stop_cause, runtime),
             stderr_tail: split_stderr_tail(&finished.stderr),
             error: None,
         },
@@ -139,6 +271,8 @@ fn normalize_finish(result: processkit::Result<processkit::Finished>) -> FinishO
             payload: TerminatedPayload {
                 code: None,
                 signal: None,
+                reason: stop_cause,
+                runtime,
             },
             stderr_tail: Vec::new(),
             error: Some(error.to_string()),
@@ -153,38 +287,314 @@ async fn hard_kill_deadline(deadline: Option<tokio::time::Instant>) {
     }
 }
 
-type StdinWrite = (Vec<u8>, oneshot::Sender<Result<(), ProcessError>>);
+/// Output ring between the child's output and the event channel.
+///
+/// Sizes approximate the original stream: line payload plus its terminator,
+/// or a raw chunk's length.
+/// Discarded lines accumulate in `unreported` and surface as one
+/// [`ProcessEvent::OutputDropped`] ahead of the next delivered event.
+struct OutputQueue {
+    events: VecDeque<ProcessEvent>,
+    bytes: usize,
+    max_bytes: usize,
+    overflow: OutputOverflow,
+    unreported: usize,
+}
+
+impl OutputQueue {
+    fn new(max_bytes: usize, overflow: OutputOverflow) -> Self {
+        Self {
+            events: VecDeque::new(),
+            bytes: 0,
+            max_bytes,
+            overflow,
+            unreported: 0,
+        }
+    }
+
+    fn event_size(event: &ProcessEvent) -> usize {
+        match event {
+            ProcessEvent::Stdout(line) | ProcessEvent::Stderr(line) => line.len() + 1,
+            ProcessEvent::StdoutBytes(chunk) | ProcessEvent::StderrBytes(chunk) => chunk.len(),
+            _ => 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        !self.events.is_empty() && self.bytes >= self.max_bytes
+    }
+
+    /// `Block` stops reading while the ring is full, leaving output in the
+    /// child's pipe until a full pipe blocks the child; the drop strategies
+    /// always keep reading.
+    fn accepts_input(&self) -> bool {
+        self.overflow != OutputOverflow::Block || !self.is_full()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.events.is_empty() && self.unreported == 0
+    }
+
+    fn push(&mut self, event: ProcessEvent) {
+        let size = Self::event_size(&event);
+        match self.overflow {
+            OutputOverflow::DropNewest
+                if !self.events.is_empty() && self.bytes + size > self.max_bytes =>
+            {
+                self.unreported += 1;
+                return;
+            }
+            OutputOverflow::DropOldest => {
+                while !self.events.is_empty() && self.bytes + size > self.max_bytes {
+                    let dropped = self.events.pop_front().expect("queued event");
+                    self.bytes -= Self::event_size(&dropped);
+                    self.unreported += 1;
+                }
+            }
+            _ => {}
+        }
+        self.bytes += size;
+        self.events.push_back(event);
+    }
+
+    fn pop(&mut self) -> Option<ProcessEvent> {
+        if self.unreported > 0 {
+            let count = std::mem::take(&mut self.unreported);
+            return Some(ProcessEvent::OutputDropped { count });
+        }
+        let event = self.events.pop_front()?;
+        self.bytes -= Self::event_size(&event);
+        Some(event)
+    }
+
+    fn count_dropped(&mut self) {
+        self.unreported += 1;
+    }
+
+    /// Drops every queued line, keeping them in the pending drop report.
+    fn discard(&mut self) {
+        self.unreported += self.events.len();
+        self.events.clear();
+        self.bytes = 0;
+    }
+
+    /// Forgets everything; used once the receiver is gone.
+    fn clear(&mut self) {
+        self.events.clear();
+        self.bytes = 0;
+        self.unreported = 0;
+    }
+}
+
+enum StdinRequest {
+    Write(Vec<u8>, oneshot::Sender<Result<(), ProcessError>>),
+    Close(oneshot::Sender<Result<(), ProcessError>>),
+}
+
+impl StdinRequest {
+    fn reject(self) {
+        let (Self::Write(_, reply) | Self::Close(reply)) = self;
+        let _ = reply.send(Err(ProcessError::StdinUnavailable));
+    }
+}
+
+/// A [`StdinSource`] resolved before launch, so a missing file fails the
+/// spawn instead of silently feeding nothing.
+enum StdinFeed {
+    Bytes(bytes::Bytes),
+    Reader(Box<dyn AsyncRead + Send + Unpin>),
+}
+
+impl StdinFeed {
+    async fn open(source: StdinSource) -> Result<Self, ProcessError> {
+        Ok(match source {
+            StdinSource::Bytes(data) => Self::Bytes(data),
+            StdinSource::File(path) => Self::Reader(Box::new(tokio::fs::File::open(path).await?)),
+            StdinSource::Reader(reader) => Self::Reader(reader),
+        })
+    }
+}
+
+/// Where stdin writes land: the engine's pipe, or the pty master in pty mode.
+enum StdinPipe {
+    Engine(processkit::ProcessStdin),
+    #[cfg(unix)]
+    Pty(Arc<super::pty::PtyMaster>),
+}
+
+impl StdinPipe {
+    async fn write(&mut self, data: &[u8]) -> bool {
+        match self {
+            Self::Engine(stdin) => stdin.write(data).await.is_ok() && stdin.flush().await.is_ok(),
+            #[cfg(unix)]
+            Self::Pty(master) => master.write_all(data).await.is_ok(),
+        }
+    }
+
+    /// Prepares end of input. The engine pipe delivers EOF once dropped; a
+    /// pty has no pipe to close and needs its EOF character instead.
+    async fn close(&mut self) -> bool {
+        match self {
+            Self::Engine(stdin) => stdin.flush().await.is_ok(),
+            #[cfg(unix)]
+            Self::Pty(master) => master.send_eof().await.is_ok(),
+        }
+    }
+}
+
+/// Output feeding the pump. On Unix the engine owns the child's stdout and
+/// stderr (pipes, or the pty) and reads them itself; elsewhere it is
+/// processkit's line stream, which is text only.
+enum OutputSource {
+    #[cfg(not(unix))]
+    Engine(processkit::OutputEvents),
+    #[cfg(unix)]
+    Streams {
+        stdout: super::pipes::ChildStream,
+        /// `None` for a pty, which merges stderr into stdout.
+        stderr: Option<super::pipes::ChildStream>,
+        encoding: Option<&'static encoding_rs::Encoding>,
+    },
+}
+
+impl OutputSource {
+    /// The next output event, or `None` once every stream has ended.
+    /// Cancel-safe, so it can sit in the pump's `select!`.
+    async fn next(&mut self) -> Option<ProcessEvent> {
+        match self {
+            #[cfg(not(unix))]
+            Self::Engine(events) => loop {
+                match events.next().await? {
+                    processkit::OutputEvent::Stdout(line) => {
+                        return Some(ProcessEvent::Stdout(line.into_text()));
+                    }
+                    processkit::OutputEvent::Stderr(line) => {
+                        return Some(ProcessEvent::Stderr(line.into_text()));
+                    }
+                    _ => {}
+                }
+            },
+            #[cfg(unix)]
+            Self::Streams {
+                stdout,
+                stderr,
+                encoding,
+            } => {
+                use super::pipes::StreamItem;
+
+                let (item, is_stderr) = loop {
+                    let stderr_open = stderr.as_ref().is_some_and(|stderr| !stderr.is_done());
+                    if stdout.is_done() && !stderr_open {
+                        return None;
+                    }
+                    tokio::select! {
+                        item = stdout.next(), if !stdout.is_done() => {
+                            if let Some(item) = item {
+                                break (item, false);
+                            }
+                        }
+                        item = async { stderr.as_mut()?.next().await }, if stderr_open => {
+                            if let Some(item) = item {
+                                break (item, true);
+                            }
+                        }
+                    }
+                };
+                Some(match (item, is_stderr) {
+                    (StreamItem::Chunk(bytes), false) => ProcessEvent::StdoutBytes(bytes.into()),
+                    (StreamItem::Chunk(bytes), true) => ProcessEvent::StderrBytes(bytes.into()),
+                    (StreamItem::Line(line), is_stderr) => {
+                        let text = match encoding {
+                            Some(encoding) => encoding.decode(&line).0.into_owned(),
+                            None => String::from_utf8_lossy(&line).into_owned(),
+                        };
+                        if is_stderr {
+                            ProcessEvent::Stderr(text)
+                        } else {
+                            ProcessEvent::Stdout(text)
+                        }
+                    }
+                })
+            }
+        }
+    }
+}
 
 const DYING_EVENT_STALL: Duration = Duration::from_secs(5);
 
-async fn write_stdin(
-    mut stdin: processkit::ProcessStdin,
-    mut requests: mpsc::Receiver<StdinWrite>,
-) {
-    while let Some((data, reply)) = requests.recv().await {
-        if stdin.write(&data).await.is_err() || stdin.flush().await.is_err() {
-            let _ = reply.send(Err(ProcessError::StdinUnavailable));
-            drop(stdin);
-            requests.close();
-            while let Some((_, reply)) = requests.recv().await {
-                let _ = reply.send(Err(ProcessError::StdinUnavailable));
+async fn write_stdin(mut stdin: StdinPipe, mut requests: mpsc::Receiver<StdinRequest>) {
+    while let Some(request) = requests.recv().await {
+        match request {
+            StdinRequest::Write(data, reply) => {
+                if !stdin.write(&data).await {
+                    let _ = reply.send(Err(ProcessError::StdinUnavailable));
+                    break;
+                }
+                let _ = reply.send(Ok(()));
+            }
+            StdinRequest::Close(reply) => {
+                let result = if stdin.close().await {
+                    Ok(())
+                } else {
+                    Err(ProcessError::StdinUnavailable)
+                };
+                let _ = reply.send(result);
+                break;
             }
-            return;
         }
-        let _ = reply.send(Ok(()));
+    }
+    // Dropping the pipe is what delivers EOF to the child.
+    drop(stdin);
+    requests.close();
+    while let Some(request) = requests.recv().await {
+        request.reject();
     }
 }
 
+/// Copies a one-shot [`StdinFeed`] into the child, then closes the pipe. A
+/// child that exits without reading everything is not an error.
+async fn feed_stdin(mut stdin: StdinPipe, feed: StdinFeed) {
+    match feed {
+        StdinFeed::Bytes(data) => {
+            if !stdin.write(&data).await {
+                tracing::debug!("child closed stdin before reading all input");
+                return;
+            }
+        }
+        StdinFeed::Reader(mut reader) => {
+            let mut buf = vec![0; 64 * 1024];
+            loop {
+                match reader.read(&mut buf).await {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        if !stdin.write(&buf[..n]).await {
+                            tracing::debug!("child closed stdin before reading all input");
+                            return;
+                        }
+                    }
+                    Err(error) => {
+                        tracing::warn!("failed to read stdin source: {error}");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    let _ = stdin.close().await;
+}
+
 fn handle_ctrl(
     ctrl: Ctrl,
     group: &processkit::ProcessGroup,
-    stdin_tx: &Option<mpsc::Sender<StdinWrite>>,
+    stdin_tx: &mut Option<mpsc::Sender<StdinRequest>>,
     kill_grace: Duration,
     hard_kill_at: &mut Option<tokio::time::Instant>,
+    stop_cause: &mut Option<ExitReason>,
 ) -> bool {
     match ctrl {
         Ctrl::Kill(reply) => {
             *hard_kill_at = None;
+            stop_cause.get_or_insert(ExitReason::KilledByHandle);
             let result = group
                 .kill_all()
                 .map_err(|error| ProcessError::Engine(error.to_string()));
@@ -192,6 +602,7 @@ fn handle_ctrl(
             true
         }
         Ctrl::GracefulKill(reply) => {
+            stop_cause.get_or_insert(ExitReason::GracefullyStopped);
             #[cfg(unix)]
             let result = match group.signal(processkit::Signal::Term) {
                 Ok(()) => {
@@ -220,24 +631,58 @@ fn handle_ctrl(
         }
         Ctrl::WriteStdin(data, reply) => {
             if let Some(stdin_tx) = stdin_tx {
-                if let Err(error) = stdin_tx.try_send((data, reply)) {
-                    let (_, reply) = error.into_inner();
-                    let _ = reply.send(Err(ProcessError::StdinUnavailable));
+                if let Err(error) = stdin_tx.try_send(StdinRequest::Write(data, reply)) {
+                    error.into_inner().reject();
                 }
             } else {
                 let _ = reply.send(Err(ProcessError::StdinUnavailable));
             }
             false
         }
+        Ctrl::CloseStdin(reply) => {
+            // Taking the sender makes later writes fail fast; the close itself
+            // queues behind writes already accepted so none are cut off.
+            match stdin_tx.take() {
+                Some(stdin_tx) => match stdin_tx.try_send(StdinRequest::Close(reply)) {
+                    Ok(()) => {}
+                    Err(mpsc::error::TrySendError::Full(request)) => {
+                        tokio::spawn(async move {
+                            if let Err(error) = stdin_tx.send(request).await {
+                                error.0.reject();
+                            }
+                        });
+                    }
+                    Err(mpsc::error::TrySendError::Closed(request)) => request.reject(),
+                },
+                None => {
+                    let _ = reply.send(Err(ProcessError::StdinUnavailable));
+                }
+            }
+            false
+        }
     }
 }
 
-pub(crate) async fn spawn(cmd: Command) -> Result<SpawnParts, ProcessError> {
+pub(crate) async fn spawn(mut cmd: Command) -> Result<SpawnParts, ProcessError> {
     let program = cmd.program.to_string_lossy().into_owned();
     let capacity = cmd.event_channel_capacity;
     let kill_grace = cmd.kill_grace;
+    let raw_output = cmd.raw_output;
+    let output_buffer_size = cmd.output_buffer_size;
+    let output_overflow = cmd.output_overflow;
     let pipe_stdin = cmd.pipe_stdin;
     let timeout = cmd.timeout;
+    #[cfg(unix)]
+    let detach_session = cmd.detach_session;
+    // Raw chunks need the engine to own the child's output pipes.
+    #[cfg(not(unix))]
+    if raw_output {
+        return Err(ProcessError::RawOutputUnsupported);
+    }
+    let stdin_feed = match cmd.stdin_source.take() {
+        Some(source) => Some(StdinFeed::open(source).await?),
+        None => None,
+    };
     let epoch_pid_required = matches!(&cmd.pid_file, Some(PidFile::Epoch(_)));
     let expected_exe = std::path::Path::new(&cmd.program)
         .file_name()
@@ -259,44 +704,139 @@ pub(crate) async fn spawn(cmd: Command) -> Result<SpawnParts, ProcessError> {
         ),
         None => None,
     };
-    // A shared-group RunningProcess only times out its direct child. The pump
-    // owns the shared group deadline so descendants holding inherited pipes are
-    // killed as well.
-    let pk = build_pk(&cmd, false);
-
-    let spawn_error = |error: processkit::Error| ProcessError::Spawn {
-        program: program.clone(),
-        message: error.to_string(),
+    if let Some(tag) = pid_guard.as_ref().and_then(PidFileGuard::intent_tag) {
+        cmd.envs
+            .push((super::intent::INTENT_ENV.into(), Some(tag.into())));
+    }
+    // The intent published before spawn must not outlive a child that never
+    // started, so every failure from here on removes it again.
+    let started = async {
+        // A shared-group RunningProcess only times out its direct child. The pump
+        // owns the shared group deadline so descendants holding inherited pipes are
+        // killed as well.
+        let pk = build_pk(&cmd, false);
+        #[cfg(unix)]
+        let pty = cmd.pty.map(super::pty::Pty::open).transpose()?;
+        #[cfg(unix)]
+        let pipes = match pty {
+            Some(_) => None,
+            None => Some((
+                super::pipes::OutputPipe::open()?,
+                super::pipes::OutputPipe::open()?,
+            )),
+        };
+        #[cfg(unix)]
+        let pk = {
+            use std::os::fd::AsRawFd;
+
+            let mut setup = super::child_setup::ChildSetup::from_command(&cmd)?;
+            setup.pty_slave = pty.as_ref().map(super::pty::Pty::slave_fd);
+            setup.output = pipes
+                .as_ref()
+                .map(|((_, stdout), (_, stderr))| (stdout.as_raw_fd(), stderr.as_raw_fd()));
+            with_child_setup(pk, setup)
+        };
+
+        let spawn_error = |error: processkit::Error| ProcessError::Spawn {
+            program: program.clone(),
+            message: error.to_string(),
+        };
+        let group = Arc::new(processkit::ProcessGroup::new().map_err(&spawn_error)?);
+        let containment = map_containment(group.mechanism());
+        let run = group.start(&pk).await.map_err(spawn_error)?;
+        // Dropping the parent's slave lets the master see the hang-up at exit.
+        #[cfg(unix)]
+        let pty_master = pty.map(|pty| pty.master);
+        // Likewise the parent's write ends, or the readers never see EOF.
+        #[cfg(unix)]
+        let pipes = pipes.map(|((stdout, _), (stderr, _))| (stdout, stderr));
+        let pid = run
+            .pid()
+            .ok_or_else(|| ProcessError::Engine("spawned process has no pid".into()))?;
+        #[cfg(unix)]
+        let streams = (pty_master, pipes);
+        #[cfg(not(unix))]
+        let streams = ();
+        Ok::<_, ProcessError>((group, containment, run, pid, streams))
+    }
+    .await;
+    let (group, containment, mut run, pid, streams) = match started {
+        Ok(started) => started,
+        Err(error) => {
+            if let Some(guard) = &pid_guard {
+                guard.cleanup().await;
+            }
+            return Err(error);
+        }
     };
-    let group = Arc::new(processkit::ProcessGroup::new().map_err(&spawn_error)?);
-    let containment = map_containment(group.mechanism());
-    let mut run = group.start(&pk).await.map_err(spawn_error)?;
-    let timeout_at = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
-    let pid = run
-        .pid()
-        .ok_or_else(|| ProcessError::Engine("spawned process has no pid".into()))?;
     if let Some(g) = &pid_guard
-        && let Err(e) = g.write(pid).await
+        && let Err(e) = g.write(pid, containment).await
     {
         if epoch_pid_required {
             let _ = group.kill_all();
             let _ = run.finish().await;
+            g.cleanup().await;
             return Err(ProcessError::Io(e));
         }
         tracing::warn!("failed to write pid file: {e}");
     }
-    let stdin_tx = if pipe_stdin {
-        run.take_stdin().map(|stdin| {
-            let (stdin_tx, stdin_rx) = mpsc::channel(64);
-            tokio::spawn(write_stdin(stdin, stdin_rx));
-            stdin_tx
-        })
+    #[cfg(unix)]
+    let (pty_master, pipes) = streams;
+    #[cfg(not(unix))]
+    let () = streams;
+    let started_at = tokio::time::Instant::now();
+    let timeout_at = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+    #[cfg(unix)]
+    let pty_stdin = pty_master.clone().map(StdinPipe::Pty);
+    #[cfg(not(unix))]
+    let pty_stdin = None;
+    let stdin_pipe = if stdin_feed.is_some() || pipe_stdin {
+        pty_stdin.or_else(|| run.take_stdin().map(StdinPipe::Engine))
     } else {
         None
     };
-    let events = run
-        .output_events()
-        .map_err(|error| ProcessError::Engine(error.to_string()))?;
+    let stdin_tx = match (stdin_pipe, stdin_feed) {
+        (Some(stdin), Some(feed)) => {
+            tokio::spawn(feed_stdin(stdin, feed));
+            None
+        }
+        (Some(stdin), None) => {
+            let (stdin_tx, stdin_rx) = mpsc::channel(64);
+            tokio::spawn(write_stdin(stdin, stdin_rx));
+            Some(stdin_tx)
+        }
+        (None, _) => None,
+    };
+    #[cfg(unix)]
+    let output = {
+        use super::pipes::ChildStream;
+
+        let lines = !raw_output;
+        let (stdout, stderr) = match pipes {
+            Some((stdout, stderr)) => (
+                ChildStream::pipe(stdout, lines, output_buffer_size),
+                Some(ChildStream::pipe(stderr, lines, output_buffer_size)),
+            ),
+            None => (
+                ChildStream::pty(
+                    pty_master.clone().expect("pty without pipes"),
+                    lines,
+                    output_buffer_size,
+                ),
+                None,
+            ),
+        };
+        OutputSource::Streams {
+            stdout,
+            stderr,
+            encoding: cmd.encoding,
+        }
+    };
+    #[cfg(not(unix))]
+    let output = OutputSource::Engine(
+        run.output_events()
+            .map_err(|error| ProcessError::Engine(error.to_string()))?,
+    );
 
     let (ev_tx, ev_rx) = mpsc::channel(capacity);
     let (ctrl_tx, ctrl_rx) = mpsc::channel(8);
@@ -304,10 +844,13 @@ pub(crate) async fn spawn(cmd: Command) -> Result<SpawnParts, ProcessError> {
 
     tokio::spawn(pump(PumpParts {
         run,
-        events,
+        output,
         group,
         stdin_tx,
         kill_grace,
+        output_buffer_size,
+        output_overflow,
+        started_at,
         timeout_at,
         ev_tx,
         ctrl_rx,
@@ -321,16 +864,22 @@ pub(crate) async fn spawn(cmd: Command) -> Result<SpawnParts, ProcessError> {
         ctrl_tx,
         terminated_rx: term_rx,
         events_rx: ev_rx,
+        #[cfg(unix)]
+        pty: pty_master
+            .map(|master| Arc::new(super::pty::PtyChild::new(master, pid, detach_session))),
     })
 }
 
 async fn pump(parts: PumpParts) {
     let PumpParts {
         run,
-        mut events,
+        mut output,
         group,
-        stdin_tx,
+        mut stdin_tx,
         kill_grace,
+        output_buffer_size,
+        output_overflow,
+        started_at,
         timeout_at,
         ev_tx,
         mut ctrl_rx,
@@ -339,23 +888,28 @@ async fn pump(parts: PumpParts) {
     } = parts;
     let mut finish = Box::pin(run.finish());
     let mut finish_output: Option<FinishOutput> = None;
-    let mut pending_event = None;
+    let mut queue = OutputQueue::new(output_buffer_size, output_overflow);
     let mut hard_kill_at = timeout_at;
     let mut events_open = true;
+    let mut events_done = false;
     let mut ctrl_open = true;
     let mut dying = false;
     let mut drop_output = false;
     let mut drop_pending_at = None;
-    let mut dropped_output_events = 0usize;
     let mut abandoned = false;
+    let mut stop_cause = None;
 
     loop {
         if !events_open && !ctrl_open && !abandoned {
             abandoned = true;
             dying = true;
             hard_kill_at = None;
+            stop_cause.get_or_insert(ExitReason::Abandoned);
             let _ = group.kill_all();
         }
+        if events_done && (queue.is_empty() || drop_output || !events_open) {
+            break;
+        }
 
         tokio::select! {
             biased;
@@ -364,12 +918,13 @@ async fn pump(parts: PumpParts) {
                     if handle_ctrl(
                         ctrl,
                         &group,
-                        &stdin_tx,
+                        &mut stdin_tx,
                         kill_grace,
                         &mut hard_kill_at,
+                        &mut stop_cause,
                     ) {
                         dying = true;
-                        if pending_event.is_some() && drop_pending_at.is_none() {
+                        if !queue.is_empty() && drop_pending_at.is_none() {
                             drop_pending_at = Some(tokio::time::Instant::now() + DYING_EVENT_STALL);
                         }
                     }
@@ -379,15 +934,16 @@ async fn pump(parts: PumpParts) {
             _ = hard_kill_deadline(hard_kill_at) => {
                 hard_kill_at = None;
                 dying = true;
+                stop_cause.get_or_insert(ExitReason::TimedOut);
                 let _ = group.kill_all();
-                if pending_event.is_some() && drop_pending_at.is_none() {
+                if !queue.is_empty() && drop_pending_at.is_none() {
                     drop_pending_at = Some(tokio::time::Instant::now() + DYING_EVENT_STALL);
                 }
             }
             result = &mut finish, if finish_output.is_none() => {
-                let out = normalize_finish(result);
+                let out = normalize_finish(result, stop_cause, started_at);
                 dying = true;
-                if pending_event.is_some() && drop_pending_at.is_none() {
+                if !queue.is_empty() && drop_pending_at.is_none() {
                     drop_pending_at = Some(tokio::time::Instant::now() + DYING_EVENT_STALL);
                 }
                 let watch = match &out.error {
@@ -397,57 +953,46 @@ async fn pump(parts: PumpParts) {
                 let _ = term_tx.send(Some(watch));
                 finish_output = Some(out);
             }
-            _ = hard_kill_deadline(drop_pending_at), if dying && !drop_output && pending_event.is_some() => {
+            _ = hard_kill_deadline(drop_pending_at), if dying && !drop_output && !queue.is_empty() => {
                 drop_pending_at = None;
-                if pending_event.take().is_some() {
-                    dropped_output_events += 1;
-                }
+                queue.discard();
                 drop_output = true;
             }
-            permit = ev_tx.reserve(), if events_open && pending_event.is_some() && !drop_output => match permit {
+            permit = ev_tx.reserve(), if events_open && !queue.is_empty() && !drop_output => match permit {
                 Ok(permit) => {
-                    permit.send(pending_event.take().expect("pending event"));
-                    drop_pending_at = None;
+                    permit.send(queue.pop().expect("queued event"));
+                    drop_pending_at = (dying && !queue.is_empty())
+                        .then(|| tokio::time::Instant::now() + DYING_EVENT_STALL);
                 }
                 Err(_) => {
                     events_open = false;
-                    pending_event = None;
+                    queue.clear();
                     drop_pending_at = None;
                 }
             },
             _ = ev_tx.closed(), if events_open => {
                 events_open = false;
-                pending_event = None;
+                queue.clear();
                 drop_pending_at = None;
             }
-            event = events.next(), if pending_event.is_none() => match event {
-                Some(processkit::OutputEvent::Stdout(line)) => {
-                    if drop_output {
-                        dropped_output_events += 1;
-                    } else if events_open {
-                        pending_event = Some(ProcessEvent::Stdout(line.into_text()));
-                        if dying {
-                            drop_pending_at = Some(tokio::time::Instant::now() + DYING_EVENT_STALL);
-                        }
-                    }
-                }
-                Some(processkit::OutputEvent::Stderr(line)) => {
-                    if drop_output {
-                        dropped_output_events += 1;
-                    } else if events_open {
-                        pending_event = Some(ProcessEvent::Stderr(line.into_text()));
-                        if dying {
-                            drop_pending_at = Some(tokio::time::Instant::now() + DYING_EVENT_STALL);
+            event = output.next(), if !events_done && queue.accepts_input() => {
+                match event {
+                    Some(_) if drop_output => queue.count_dropped(),
+                    Some(event) => {
+                        if events_open {
+                            queue.push(event);
+                            if dying && drop_pending_at.is_none() {
+                                drop_pending_at = Some(tokio::time::Instant::now() + DYING_EVENT_STALL);
+                            }
                         }
                     }
+                    None => events_done = true,
                 }
-                Some(_) => {}
-                None => break,
-            },
+            }
         }
     }
 
-    drop(events);
+    drop(output);
     let finish_output = match finish_output {
         Some(out) => out,
         None => {
@@ -455,6 +1000,7 @@ async fn pump(parts: PumpParts) {
                 if !events_open && !ctrl_open && !abandoned {
                     abandoned = true;
                     hard_kill_at = None;
+                    stop_cause.get_or_insert(ExitReason::Abandoned);
                     let _ = group.kill_all();
                 }
 
@@ -465,19 +1011,21 @@ async fn pump(parts: PumpParts) {
                             let _ = handle_ctrl(
                                 ctrl,
                                 &group,
-                                &stdin_tx,
+                                &mut stdin_tx,
                                 kill_grace,
                                 &mut hard_kill_at,
+                                &mut stop_cause,
                             );
                         }
                         None => ctrl_open = false,
                     },
                     _ = hard_kill_deadline(hard_kill_at) => {
                         hard_kill_at = None;
+                        stop_cause.get_or_insert(ExitReason::TimedOut);
                         let _ = group.kill_all();
                     }
                     _ = ev_tx.closed(), if events_open => events_open = false,
-                    result = &mut finish => break normalize_finish(result),
+                    result = &mut finish => break normalize_finish(result, stop_cause, started_at),
                 }
             };
             let watch = match &out.error {
@@ -491,14 +1039,18 @@ async fn pump(parts: PumpParts) {
     cleanup_pid_file(&pid_guard).await;
 
     let mut terminal_events = VecDeque::new();
+    if events_open {
+        // Undelivered queued lines precede the drained tail; after a stall
+        // only the pending drop report remains.
+        while let Some(event) = queue.pop() {
+            terminal_events.push_back(event);
+        }
+    }
+    // Only processkit's line stream leaves a drained tail; the engine's own
+    // pipes are read to the end above.
     for line in finish_output.stderr_tail {
         terminal_events.push_back(ProcessEvent::Stderr(line));
     }
-    if dropped_output_events > 0 {
-        terminal_events.push_back(ProcessEvent::Error(format!(
-            "dropped {dropped_output_events} buffered output events: receiver not draining"
-        )));
-    }
     if let Some(error) = finish_output.error {
         terminal_events.push_back(ProcessEvent::Error(error));
     }
@@ -518,9 +1070,10 @@ async fn pump(parts: PumpParts) {
                     let _ = handle_ctrl(
                         ctrl,
                         &group,
-                        &stdin_tx,
+                        &mut stdin_tx,
                         kill_grace,
                         &mut hard_kill_at,
+                        &mut stop_cause,
                     );
                 }
                 None => ctrl_open = false,
@@ -550,7 +1103,73 @@ async fn cleanup_pid_file(pid_guard: &Option<PidFileGuard>) {
 
 #[cfg(test)]
 mod tests {
-    use super::split_stderr_tail;
+    use super::{
+        ExitReason, OutputOverflow, OutputQueue, ProcessEvent, classify_exit, split_stderr_tail,
+    };
+
+    fn line(text: &str) -> ProcessEvent {
+        ProcessEvent::Stdout(text.to_owned())
+    }
+
+    fn drain(queue: &mut OutputQueue) -> Vec<String> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|event| match event {
+                ProcessEvent::Stdout(line) => line,
+                ProcessEvent::OutputDropped { count } => format!("dropped:{count}"),
+                other => panic!("unexpected event {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn output_queue_applies_overflow_strategy_and_reports_drops() {
+        // Each three-byte line occupies four bytes including its terminator.
+        let mut oldest = OutputQueue::new(8, OutputOverflow::DropOldest);
+        for text in ["aaa", "bbb", "ccc"] {
+            oldest.push(line(text));
+        }
+        assert_eq!(drain(&mut oldest), ["dropped:1", "bbb", "ccc"]);
+
+        let mut newest = OutputQueue::new(8, OutputOverflow::DropNewest);
+        for text in ["aaa", "bbb", "ccc"] {
+            newest.push(line(text));
+        }
+        assert_eq!(drain(&mut newest), ["dropped:1", "aaa", "bbb"]);
+
+        let mut block = OutputQueue::new(8, OutputOverflow::Block);
+        block.push(line("aaa"));
+        assert!(block.accepts_input());
+        block.push(line("bbb"));
+        assert!(!block.accepts_input());
+        block.discard();
+        block.count_dropped();
+        assert_eq!(drain(&mut block), ["dropped:3"]);
+        assert!(block.is_empty());
+    }
+
+    #[test]
+    fn classify_exit_prefers_manager_stop_cause() {
+        assert_eq!(
+            classify_exit(Some(3), None, false, None),
+            Some(ExitReason::Exited(3))
+        );
+        assert_eq!(
+            classify_exit(None, Some(11), true, None),
+            Some(ExitReason::Signaled {
+                signal: 11,
+                core_dumped: true
+            })
+        );
+        assert_eq!(
+            classify_exit(None, Some(9), false, Some(ExitReason::KilledByHandle)),
+            Some(ExitReason::KilledByHandle)
+        );
+        assert_eq!(
+            classify_exit(Some(0), None, false, Some(ExitReason::GracefullyStopped)),
+            Some(ExitReason::GracefullyStopped)
+        );
+        assert_eq!(classify_exit(None, None, false, None), None);
+    }
 
     #[test]
     fn split_stderr_tail_reconstructs_joined_lines() {
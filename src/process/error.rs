@@ -1,5 +1,7 @@
 use std::time::Duration;
 
+use super::event::ExitReason;
+
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum ProcessError {
@@ -13,21 +15,61 @@ pub enum ProcessError {
     AlreadyExited,
     #[error("stdin is not piped (enable Command::pipe_stdin) or already closed")]
     StdinUnavailable,
+    /// [`crate::process::Command::ambient_caps`] asked for capabilities the
+    /// manager cannot pass on; they must be in its permitted and bounding sets.
+    #[cfg(target_os = "linux")]
+    #[error("manager lacks capabilities for the child: {}", join_caps(.missing))]
+    MissingCapabilities {
+        missing: Vec<crate::os::caps::Capability>,
+    },
+    /// A [`super::Sandbox`] option that the kernel or the manager's
+    /// privileges do not support. The child is never spawned unconfined.
+    #[cfg(target_os = "linux")]
+    #[error("sandbox {feature} unavailable: {reason}")]
+    SandboxUnavailable {
+        feature: &'static str,
+        reason: String,
+    },
+    #[error("unknown user `{name}`")]
+    UnknownUser { name: String },
+    #[error("process was not spawned with Command::pty")]
+    PtyUnavailable,
+    /// Streaming [`crate::process::Command::raw_output`] needs the engine to
+    /// own the child's output pipes, which it only does on Unix.
+    #[error("raw output streaming is not supported on this platform")]
+    RawOutputUnsupported,
     /// Engine-internal failures that have no dedicated variant. The engine maps
     /// processkit errors to strings here so processkit types never leak.
     #[error("process engine error: {0}")]
     Engine(String),
 }
 
+#[cfg(target_os = "linux")]
+fn join_caps(caps: &[crate::os::caps::Capability]) -> String {
+    caps.iter()
+        .map(|cap| cap.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 /// Result of [`crate::process::Command::output`] — one-shot capture.
+///
+/// [`crate::process::Command::output_bytes`] returns `ProcessOutput<Vec<u8>>`
+/// with the undecoded stream contents.
 #[derive(Debug, Clone)]
-pub struct ProcessOutput {
+pub struct ProcessOutput<T = String> {
     pub code: Option<i32>,
-    pub stdout: String,
-    pub stderr: String,
+    /// Classified like [`crate::process::TerminatedPayload::reason`]; on
+    /// Windows only [`ExitReason::Exited`] occurs. A timeout is always
+    /// [`ProcessError::Timeout`].
+    pub reason: Option<ExitReason>,
+    /// Wall-clock time from launch until the capture completed.
+    pub runtime: Duration,
+    pub stdout: T,
+    pub stderr: T,
 }
 
-impl ProcessOutput {
+impl<T> ProcessOutput<T> {
     pub fn success(&self) -> bool {
         self.code == Some(0)
     }
@@ -39,8 +81,10 @@ mod tests {
 
     #[test]
     fn process_output_success_only_on_zero() {
-        let mk = |code| ProcessOutput {
+        let mk = |code: Option<i32>| ProcessOutput {
             code,
+            reason: code.map(ExitReason::Exited),
+            runtime: Duration::ZERO,
             stdout: String::new(),
             stderr: String::new(),
         };
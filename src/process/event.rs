@@ -1,9 +1,39 @@
-/// Exit information of a terminated child. Field semantics match the legacy
+use std::time::Duration;
+
+/// Why a child terminated, as observed by the process pump.
+///
+/// Terminations initiated by the manager (handle kills, timeouts, abandonment)
+/// take precedence over the raw status, so a hard kill is reported as
+/// [`ExitReason::KilledByHandle`] rather than as a signal death.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The child exited on its own with this code.
+    Exited(i32),
+    /// The child was terminated by a signal nobody in this process sent.
+    /// `core_dumped` is the wait status's `WCOREDUMP` bit.
+    Signaled { signal: i32, core_dumped: bool },
+    /// [`crate::process::Command::timeout`] elapsed and the tree was killed.
+    TimedOut,
+    /// [`crate::process::ProcessHandle::kill`] terminated the tree.
+    KilledByHandle,
+    /// [`crate::process::ProcessHandle::graceful_kill`] stopped the tree,
+    /// including escalation to a hard kill after the grace period.
+    GracefullyStopped,
+    /// Every handle and the event receiver were dropped, so the tree was killed.
+    Abandoned,
+}
+
+/// Exit information of a terminated child. `code` and `signal` match the legacy
 /// `core::TerminatedPayload` so downstream migration is a rename.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TerminatedPayload {
     pub code: Option<i32>,
     pub signal: Option<i32>,
+    /// `None` when the engine could not observe the exit status.
+    pub reason: Option<ExitReason>,
+    /// Wall-clock time between spawn and observed termination.
+    pub runtime: Duration,
 }
 
 /// Events delivered on the channel returned by [`crate::process::Command::spawn`].
@@ -18,6 +48,18 @@ pub struct TerminatedPayload {
 pub enum ProcessEvent {
     Stdout(String),
     Stderr(String),
+    /// A chunk of undecoded stdout, delivered instead of [`ProcessEvent::Stdout`]
+    /// when [`crate::process::Command::raw_output`] is enabled.
+    StdoutBytes(bytes::Bytes),
+    /// A chunk of undecoded stderr, delivered instead of [`ProcessEvent::Stderr`]
+    /// when [`crate::process::Command::raw_output`] is enabled.
+    StderrBytes(bytes::Bytes),
+    /// `count` output lines or chunks were discarded by the output ring before delivery
+    /// (see [`crate::process::Command::output_overflow`]) or because the
+    /// receiver stalled during termination.
+    OutputDropped {
+        count: usize,
+    },
     /// Non-fatal IO/decode error while pumping output. The process may still be alive.
     Error(String),
     Terminated(TerminatedPayload),
@@ -1,4 +1,14 @@
-use tokio::sync::{mpsc, oneshot, watch};
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use tokio::{
+    io::AsyncWrite,
+    sync::{mpsc, oneshot, watch},
+};
 
 use super::{error::ProcessError, event::TerminatedPayload};
 
@@ -15,6 +25,7 @@ pub(crate) enum Ctrl {
     GracefulKill(oneshot::Sender<Result<(), ProcessError>>),
     Kill(oneshot::Sender<Result<(), ProcessError>>),
     WriteStdin(Vec<u8>, oneshot::Sender<Result<(), ProcessError>>),
+    CloseStdin(oneshot::Sender<Result<(), ProcessError>>),
 }
 
 /// Cloneable handle to a spawned child. Dropping all handles and the event
@@ -25,6 +36,8 @@ pub struct ProcessHandle {
     pub(crate) containment: Containment,
     pub(crate) ctrl: mpsc::Sender<Ctrl>,
     pub(crate) terminated: watch::Receiver<Option<Result<TerminatedPayload, String>>>,
+    #[cfg(unix)]
+    pub(crate) pty: Option<std::sync::Arc<super::pty::PtyChild>>,
 }
 
 impl ProcessHandle {
@@ -74,6 +87,35 @@ impl ProcessHandle {
             .await
     }
 
+    /// Closes the child's stdin pipe so it reads EOF, after any writes already
+    /// accepted by [`ProcessHandle::write_stdin`] have been flushed. Later
+    /// writes, and a second close, fail with [`ProcessError::StdinUnavailable`].
+    pub async fn close_stdin(&self) -> Result<(), ProcessError> {
+        self.send_ctrl(Ctrl::CloseStdin).await
+    }
+
+    /// Resizes the terminal of a child spawned with
+    /// [`crate::process::Command::pty`] and announces it with `SIGWINCH`.
+    /// With [`crate::process::Command::detach_session`] the kernel signals the
+    /// terminal's foreground process group; otherwise the direct child is
+    /// signalled.
+    #[cfg(unix)]
+    pub fn resize_pty(&self, size: super::pty::PtySize) -> Result<(), ProcessError> {
+        let pty = self.pty.as_ref().ok_or(ProcessError::PtyUnavailable)?;
+        pty.resize(size)?;
+        Ok(())
+    }
+
+    /// Returns an [`AsyncWrite`] over the stdin pipe, for use with
+    /// `tokio::io::copy` and friends. Shutting the writer down closes stdin.
+    pub fn stdin_writer(&self) -> StdinWriter {
+        StdinWriter {
+            handle: self.clone(),
+            pending: None,
+            shut_down: false,
+        }
+    }
+
     pub(crate) async fn send_ctrl(
         &self,
         make: impl FnOnce(oneshot::Sender<Result<(), ProcessError>>) -> Ctrl,
@@ -98,3 +140,61 @@ impl ProcessHandle {
         }
     }
 }
+
+type PendingStdin = Pin<Box<dyn Future<Output = Result<(), ProcessError>> + Send>>;
+
+/// [`AsyncWrite`] adapter returned by [`ProcessHandle::stdin_writer`].
+///
+/// Each `poll_write` hands its buffer to the stdin writer task and completes
+/// once the previous write has landed, so an error surfaces on the next
+/// write, flush, or shutdown. Writes are kept one in flight at a time.
+pub struct StdinWriter {
+    handle: ProcessHandle,
+    pending: Option<PendingStdin>,
+    shut_down: bool,
+}
+
+impl StdinWriter {
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(pending) = &mut self.pending {
+            let result = ready!(pending.as_mut().poll(cx));
+            self.pending = None;
+            result.map_err(|error| io::Error::new(io::ErrorKind::BrokenPipe, error))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for StdinWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        let handle = this.handle.clone();
+        let data = buf.to_vec();
+        this.pending = Some(Box::pin(async move { handle.write_stdin(&data).await }));
+        // Start the write now; its outcome is collected by the next poll.
+        if let Poll::Ready(Err(error)) = this.poll_pending(cx) {
+            return Poll::Ready(Err(error));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.shut_down {
+            ready!(this.poll_pending(cx))?;
+            let handle = this.handle.clone();
+            this.pending = Some(Box::pin(async move { handle.close_stdin().await }));
+            this.shut_down = true;
+        }
+        this.poll_pending(cx)
+    }
+}
@@ -0,0 +1,368 @@
+//! Spawn intent records, which cover the window before an epoch pid record.
+//!
+//! The epoch record can only be published once the child runs and its
+//! identity is known. A manager killed between `group.start()` and that
+//! publication used to leave an orphan nothing could attribute. Before spawn,
+//! the manager now publishes `core-{epoch}.intent` with a random tag and
+//! passes the tag to the child in [`INTENT_ENV`]. Right after start, while the
+//! identity is still pending, the intent is rewritten with the child's cgroup,
+//! which it was placed in before exec; recovery then kills that cgroup, which
+//! no descendant can leave and which works across users.
+//!
+//! Without a cgroup (other containment, or a manager killed before the
+//! rewrite), recovery falls back to the tag: descendants inherit it, so every
+//! process whose initial environment carries it is killed. That fallback is
+//! weaker: a process can scrub or forge its environment, and another user's
+//! environment is unreadable.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use super::{
+    containment::RecordedContainment,
+    pid_file::{
+        OrphanReapOutcome, ProcessIdentity, hex_decode, hex_encode, invalid_data, parse_field,
+        required,
+    },
+};
+
+/// Set in the environment of every child spawned with an epoch pid record.
+pub(crate) const INTENT_ENV: &str = "NYANPASU_SPAWN_INTENT";
+
+const INTENT_VERSION: u32 = 1;
+const KILL_WAIT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct IntentRecord {
+    pub epoch: u64,
+    pub executable: String,
+    pub manager_pid: u32,
+    pub manager_start_token: u64,
+    pub tag: String,
+    /// The child's cgroup, once it has started in one of its own.
+    pub cgroup: Option<RecordedContainment>,
+}
+
+/// `core-{epoch}.pid` → `core-{epoch}.intent`.
+pub(crate) fn intent_path(pid_path: &Path) -> PathBuf {
+    pid_path.with_extension("intent")
+}
+
+impl IntentRecord {
+    pub fn new(epoch: u64, executable: String) -> std::io::Result<Self> {
+        let manager = ProcessIdentity::of(std::process::id())?.ok_or_else(|| {
+            std::io::Error::other("cannot read the manager's own process identity")
+        })?;
+        Ok(Self {
+            epoch,
+            executable,
+            manager_pid: manager.pid(),
+            manager_start_token: manager.start_token(),
+            tag: new_tag(manager.start_token()),
+            cgroup: None,
+        })
+    }
+
+    /// Whether the manager that wrote this record is this process.
+    fn written_by_self(&self) -> bool {
+        self.manager_pid == std::process::id()
+    }
+
+    fn manager_alive(&self) -> std::io::Result<bool> {
+        Ok(ProcessIdentity::of(self.manager_pid)?
+            .is_some_and(|manager| manager.start_token() == self.manager_start_token))
+    }
+}
+
+/// A tag unique to this manager process and call.
+fn new_tag(manager_start_token: u64) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!(
+        "{:08x}{manager_start_token:016x}{nanos:032x}{:08x}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+    )
+}
+
+/// Publishes `record` at `path`, which must not exist yet.
+pub(crate) async fn write_intent(path: &Path, record: &IntentRecord) -> std::io::Result<()> {
+    use crate::io::atomic_fs::{AtomicWriteOptions, write_atomic};
+
+    write_atomic(
+        path,
+        serialize_intent(record),
+        AtomicWriteOptions::new().create_new(true),
+    )
+    .await
+    .map_err(into_io)
+}
+
+/// Replaces the intent at `path` with `record`, an update of the same spawn.
+pub(crate) async fn replace_intent(path: &Path, record: &IntentRecord) -> std::io::Result<()> {
+    use crate::io::atomic_fs::{AtomicWriteOptions, write_atomic};
+
+    write_atomic(path, serialize_intent(record), AtomicWriteOptions::new())
+        .await
+        .map_err(into_io)
+}
+
+fn into_io(error: crate::io::atomic_fs::AtomicFsError) -> std::io::Error {
+    match error {
+        crate::io::atomic_fs::AtomicFsError::Io(error) => error,
+        other => std::io::Error::other(other.to_string()),
+    }
+}
+
+pub(crate) async fn read_intent(path: &Path) -> std::io::Result<Option<IntentRecord>> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(raw) => parse_intent(&raw).map(Some),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Removes the intent at `path` if it is still `expected`.
+pub(crate) async fn remove_intent(path: &Path, expected: &IntentRecord) -> std::io::Result<()> {
+    if read_intent(path).await?.as_ref() != Some(expected) {
+        return Ok(());
+    }
+    match tokio::fs::remove_file(path).await {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// Kills the recorded cgroup of the intent at `path`, or every process
+/// tagged by it if none was recorded, then removes it. Refuses while another
+/// live manager still owns the intent.
+pub(crate) async fn recover_intent(path: &Path) -> std::io::Result<OrphanReapOutcome> {
+    let Some(record) = read_intent(path).await? else {
+        return Ok(OrphanReapOutcome::NotFound);
+    };
+    if !record.written_by_self() && record.manager_alive()? {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!(
+                "epoch {} is being spawned by live manager pid {}",
+                record.epoch, record.manager_pid
+            ),
+        ));
+    }
+    let killed = match &record.cgroup {
+        // The cgroup is only killed while its recorded instance still holds.
+        Some(cgroup) => cgroup.kill_members(false).await?,
+        None => kill_tagged(&record.tag).await?,
+    };
+    remove_intent(path, &record).await?;
+    Ok(if killed {
+        OrphanReapOutcome::Killed
+    } else {
+        OrphanReapOutcome::AlreadyExited
+    })
+}
+
+fn tagged_pids(tag: &str) -> Vec<u32> {
+    use sysinfo::{ProcessRefreshKind, RefreshKind, System, UpdateKind};
+
+    let needle = format!("{INTENT_ENV}={tag}");
+    let kind = RefreshKind::nothing()
+        .with_processes(ProcessRefreshKind::nothing().with_environ(UpdateKind::Always));
+    let mut system = System::new_with_specifics(kind);
+    system.refresh_specifics(kind);
+    let own = std::process::id();
+    system
+        .processes()
+        .iter()
+        .filter(|(pid, process)| {
+            pid.as_u32() != own && process.environ().iter().any(|var| *var == *needle)
+        })
+        .map(|(pid, _)| pid.as_u32())
+        .collect()
+}
+
+/// Unix: each process is pinned by a [`crate::os::PidHandle`] before the tag
+/// is checked again, so with a pidfd the signal cannot reach a process that
+/// reused the pid in between.
+#[cfg(unix)]
+async fn kill_tagged(tag: &str) -> std::io::Result<bool> {
+    let mut handles = Vec::new();
+    for pid in tagged_pids(tag) {
+        let handle = match crate::os::PidHandle::open(pid) {
+            Ok(handle) => handle,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        };
+        if !tagged_pids(tag).contains(&pid) || !handle.is_alive()? {
+            continue;
+        }
+        match handle.send_signal(nix::sys::signal::Signal::SIGKILL) {
+            Ok(()) => handles.push(handle),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+    }
+    for handle in &handles {
+        tokio::time::timeout(KILL_WAIT, handle.wait())
+            .await
+            .map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("tagged pid {} survived SIGKILL", handle.pid()),
+                )
+            })??;
+    }
+    Ok(!handles.is_empty())
+}
+
+/// Without a process handle the tag is checked again right before the kill,
+/// leaving the usual pid-reuse window.
+#[cfg(not(unix))]
+async fn kill_tagged(tag: &str) -> std::io::Result<bool> {
+    use sysinfo::{Pid, ProcessRefreshKind, RefreshKind, System};
+
+    let kind = RefreshKind::nothing().with_processes(ProcessRefreshKind::nothing());
+    let mut system = System::new_with_specifics(kind);
+    let mut killed = false;
+    for pid in tagged_pids(tag) {
+        if !tagged_pids(tag).contains(&pid) {
+            continue;
+        }
+        system.refresh_specifics(kind);
+        if system
+            .process(Pid::from_u32(pid))
+            .is_some_and(|process| process.kill())
+        {
+            killed = true;
+        }
+    }
+    let deadline = tokio::time::Instant::now() + KILL_WAIT;
+    while !tagged_pids(tag).is_empty() {
+        if tokio::time::Instant::now() >= deadline {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "tagged processes survived termination",
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    Ok(killed)
+}
+
+fn serialize_intent(record: &IntentRecord) -> String {
+    let mut raw = format!(
+        "version={INTENT_VERSION}\nepoch={}\nexecutable={}\nmanager-pid={}\nmanager-start-token={}\ntag={}\n",
+        record.epoch,
+        hex_encode(record.executable.as_bytes()),
+        record.manager_pid,
+        record.manager_start_token,
+        record.tag,
+    );
+    if let Some(cgroup) = &record.cgroup {
+        raw.push_str(&format!("cgroup={}\n", cgroup.serialize()));
+    }
+    raw
+}
+
+fn parse_intent(raw: &str) -> std::io::Result<IntentRecord> {
+    let mut fields = BTreeMap::new();
+    for line in raw.lines() {
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid_data("malformed intent record line"))?;
+        if fields.insert(key, value).is_some() {
+            return Err(invalid_data("duplicate intent record field"));
+        }
+    }
+    let version = parse_field::<u32>(&fields, "version")?;
+    if version != INTENT_VERSION {
+        return Err(invalid_data(format!(
+            "unsupported intent record version {version}"
+        )));
+    }
+    let tag = required(&fields, "tag")?;
+    if tag.is_empty() || !tag.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(invalid_data("invalid intent tag"));
+    }
+    Ok(IntentRecord {
+        epoch: parse_field(&fields, "epoch")?,
+        executable: String::from_utf8(hex_decode(required(&fields, "executable")?)?)
+            .map_err(|_| invalid_data("intent executable is not UTF-8"))?,
+        manager_pid: parse_field(&fields, "manager-pid")?,
+        manager_start_token: parse_field(&fields, "manager-start-token")?,
+        tag: tag.to_owned(),
+        cgroup: match fields.get("cgroup") {
+            Some(value) => match RecordedContainment::parse(value)? {
+                cgroup @ RecordedContainment::CgroupV2 { .. } => Some(cgroup),
+                _ => return Err(invalid_data("intent containment is not a cgroup")),
+            },
+            None => None,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intent_round_trips_and_tags_are_unique() {
+        let mut record = IntentRecord::new(4, "mihomo".into()).unwrap();
+        assert_eq!(parse_intent(&serialize_intent(&record)).unwrap(), record);
+        record.cgroup = Some(RecordedContainment::CgroupV2 {
+            path: "/nyanpasu/4".into(),
+            instance: Some(9),
+        });
+        assert_eq!(parse_intent(&serialize_intent(&record)).unwrap(), record);
+        assert_ne!(
+            IntentRecord::new(4, "mihomo".into()).unwrap().tag,
+            record.tag
+        );
+        assert_eq!(
+            intent_path(Path::new("/run/core-4.pid")),
+            Path::new("/run/core-4.intent")
+        );
+    }
+
+    #[tokio::test]
+    async fn recovering_an_untagged_intent_removes_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("core-2.intent");
+        let record = IntentRecord::new(2, "mihomo".into()).unwrap();
+        write_intent(&path, &record).await.unwrap();
+        assert!(write_intent(&path, &record).await.is_err());
+        assert_eq!(
+            recover_intent(&path).await.unwrap(),
+            OrphanReapOutcome::AlreadyExited
+        );
+        assert!(!path.exists());
+        assert_eq!(
+            recover_intent(&path).await.unwrap(),
+            OrphanReapOutcome::NotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn recovery_kills_through_a_recorded_cgroup_only_while_it_holds() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("core-3.intent");
+        let mut record = IntentRecord::new(3, "mihomo".into()).unwrap();
+        record.cgroup = Some(RecordedContainment::CgroupV2 {
+            path: "/nyanpasu-test/missing".into(),
+            instance: Some(1),
+        });
+        write_intent(&path, &record).await.unwrap();
+        assert_eq!(
+            recover_intent(&path).await.unwrap(),
+            OrphanReapOutcome::AlreadyExited
+        );
+        assert!(!path.exists());
+    }
+}
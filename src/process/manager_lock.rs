@@ -0,0 +1,263 @@
+//! A per-user singleton lock for the manager process.
+//!
+//! The lock itself is an [`acquire_dir_lock`] file. Windows opens it without
+//! sharing, so the holder is published next to it in `{lock}.holder` (pid
+//! and start token), and a contended caller reports that holder only while
+//! its identity still matches. Takeover asks first: the new instance
+//! publishes `{lock}.takeover`, the holder observes it through
+//! [`ManagerLock::takeover_requested`] and shuts down, and the lock is
+//! acquired once released. A holder that does not answer in time, such as an
+//! older manager that never polls, is sent SIGTERM on Unix, after its
+//! published identity is checked again.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use super::pid_file::{ProcessIdentity, invalid_data, parse_field};
+use crate::io::atomic_fs::{
+    AtomicFsError, AtomicWriteOptions, DirLock, acquire_dir_lock, write_atomic,
+};
+
+const HOLDER_VERSION: u32 = 1;
+const POLL: Duration = Duration::from_millis(100);
+/// How long a holder sent SIGTERM has to release the lock.
+const TERM_GRACE: Duration = Duration::from_secs(5);
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum ManagerLockError {
+    #[error("another instance ({}) is running", describe(.holder))]
+    Held { holder: Option<ProcessIdentity> },
+    #[error("another instance ({}) did not hand over the lock in time", describe(.holder))]
+    TakeoverTimedOut { holder: Option<ProcessIdentity> },
+    #[error("unsafe manager lock path: {0}")]
+    UnsafePath(PathBuf),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+fn describe(holder: &Option<ProcessIdentity>) -> String {
+    match holder {
+        Some(holder) => format!("pid {}", holder.pid()),
+        None => "unknown pid".into(),
+    }
+}
+
+/// Held for the lifetime of the manager; dropping it releases the lock.
+#[derive(Debug)]
+pub struct ManagerLock {
+    path: PathBuf,
+    _lock: DirLock,
+}
+
+impl ManagerLock {
+    /// Takes the lock at `path`, failing with [`ManagerLockError::Held`] while
+    /// another instance holds it.
+    pub async fn acquire(path: impl AsRef<Path>) -> Result<Self, ManagerLockError> {
+        let path = path.as_ref();
+        let lock = match acquire_dir_lock(path) {
+            Ok(lock) => lock,
+            Err(AtomicFsError::Contended(_)) => {
+                return Err(ManagerLockError::Held {
+                    holder: Self::holder(path).await?,
+                });
+            }
+            Err(AtomicFsError::UnsafePath(path)) => return Err(ManagerLockError::UnsafePath(path)),
+            Err(AtomicFsError::Io(error)) => return Err(error.into()),
+        };
+        let this = Self {
+            path: path.to_owned(),
+            _lock: lock,
+        };
+        // A request aimed at the previous holder is answered by now.
+        remove_if_exists(&takeover_path(path)).await?;
+        this.publish_holder().await?;
+        Ok(this)
+    }
+
+    /// Like [`ManagerLock::acquire`], but asks the current holder to shut down
+    /// and waits up to `timeout` for it to release the lock. On Unix, a
+    /// holder that is still running then is sent SIGTERM and given a few more
+    /// seconds; elsewhere, and for a holder whose identity cannot be
+    /// confirmed, the takeover stays cooperative and times out.
+    pub async fn take_over(
+        path: impl AsRef<Path>,
+        timeout: Duration,
+    ) -> Result<Self, ManagerLockError> {
+        let path = path.as_ref();
+        let mut deadline = tokio::time::Instant::now() + timeout;
+        let mut requested = false;
+        let mut terminated = false;
+        loop {
+            let holder = match Self::acquire(path).await {
+                Err(ManagerLockError::Held { holder }) => holder,
+                Ok(lock) => return Ok(lock),
+                Err(error) => {
+                    if requested {
+                        let _ = remove_if_exists(&takeover_path(path)).await;
+                    }
+                    return Err(error);
+                }
+            };
+            if !requested {
+                tracing::info!("requesting takeover from instance ({})", describe(&holder));
+                let request = format!("pid={}\n", std::process::id());
+                write_atomic(takeover_path(path), request, AtomicWriteOptions::new())
+                    .await
+                    .map_err(into_io)?;
+                requested = true;
+            }
+            if tokio::time::Instant::now() >= deadline
+                && !terminated
+                && let Some(holder) = &holder
+                && terminate(holder).unwrap_or_else(|error| {
+                    tracing::warn!("cannot signal instance (pid {}): {error}", holder.pid());
+                    false
+                })
+            {
+                tracing::warn!(
+                    "instance (pid {}) did not answer the takeover request; sent SIGTERM",
+                    holder.pid()
+                );
+                terminated = true;
+                deadline = tokio::time::Instant::now() + TERM_GRACE;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                // A request left behind would still stop the holder after
+                // this caller has given up.
+                remove_if_exists(&takeover_path(path)).await?;
+                return Err(ManagerLockError::TakeoverTimedOut { holder });
+            }
+            tokio::time::sleep(POLL).await;
+        }
+    }
+
+    /// The live instance recorded as holding the lock at `path`. `None` when
+    /// the record is missing, not yet published or left by an exited holder.
+    pub async fn holder(path: impl AsRef<Path>) -> std::io::Result<Option<ProcessIdentity>> {
+        let raw = match tokio::fs::read_to_string(holder_path(path.as_ref())).await {
+            Ok(raw) => raw,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let (pid, start_token) = match parse_holder(&raw) {
+            Ok(holder) => holder,
+            Err(error) => {
+                tracing::warn!("ignoring malformed manager lock holder: {error}");
+                return Ok(None);
+            }
+        };
+        Ok(ProcessIdentity::of(pid)?.filter(|identity| identity.start_token() == start_token))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Resolves once another instance has called [`ManagerLock::take_over`].
+    /// The holder should then shut down and drop the lock.
+    pub async fn takeover_requested(&self) {
+        let request = takeover_path(&self.path);
+        while !tokio::fs::try_exists(&request).await.unwrap_or(false) {
+            tokio::time::sleep(POLL).await;
+        }
+    }
+
+    async fn publish_holder(&self) -> std::io::Result<()> {
+        let identity = ProcessIdentity::of(std::process::id())?.ok_or_else(|| {
+            std::io::Error::other("cannot read the manager's own process identity")
+        })?;
+        let record = format!(
+            "version={HOLDER_VERSION}\npid={}\nstart-token={}\n",
+            identity.pid(),
+            identity.start_token()
+        );
+        write_atomic(holder_path(&self.path), record, AtomicWriteOptions::new())
+            .await
+            .map_err(into_io)
+    }
+}
+
+impl Drop for ManagerLock {
+    fn drop(&mut self) {
+        // Removed while the lock is still held, so a successor's record is
+        // never deleted.
+        let _ = std::fs::remove_file(holder_path(&self.path));
+    }
+}
+
+/// Sends SIGTERM to `holder` if it still is that process and not this one.
+/// Returns whether it was signalled.
+#[cfg(unix)]
+fn terminate(holder: &ProcessIdentity) -> std::io::Result<bool> {
+    if holder.pid() == std::process::id() {
+        return Ok(false);
+    }
+    let handle = match crate::os::PidHandle::open(holder.pid()) {
+        Ok(handle) => handle,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(error) => return Err(error),
+    };
+    if !handle.verify(holder)? {
+        return Ok(false);
+    }
+    match handle.send_signal(nix::sys::signal::Signal::SIGTERM) {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(not(unix))]
+fn terminate(_holder: &ProcessIdentity) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+fn holder_path(lock: &Path) -> PathBuf {
+    sidecar(lock, "holder")
+}
+
+fn takeover_path(lock: &Path) -> PathBuf {
+    sidecar(lock, "takeover")
+}
+
+fn sidecar(lock: &Path, suffix: &str) -> PathBuf {
+    let mut name = lock.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn parse_holder(raw: &str) -> std::io::Result<(u32, u64)> {
+    let fields = raw
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .collect::<BTreeMap<_, _>>();
+    let version = parse_field::<u32>(&fields, "version")?;
+    if version != HOLDER_VERSION {
+        return Err(invalid_data(format!(
+            "unsupported manager lock holder version {version}"
+        )));
+    }
+    Ok((
+        parse_field(&fields, "pid")?,
+        parse_field(&fields, "start-token")?,
+    ))
+}
+
+fn into_io(error: AtomicFsError) -> std::io::Error {
+    match error {
+        AtomicFsError::Io(error) => error,
+        other => std::io::Error::other(other.to_string()),
+    }
+}
+
+async fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
@@ -35,21 +35,43 @@
 //!
 //! Design: docs/superpowers/specs/2026-07-16-nyanpasu-utils-process-module-design.md
 
+mod capture;
+#[cfg(unix)]
+mod child_setup;
 mod command;
+mod containment;
 mod engine;
 mod error;
 mod event;
 mod handle;
+mod intent;
+mod manager_lock;
 mod pid_file;
+#[cfg(unix)]
+mod pipes;
+#[cfg(unix)]
+mod pty;
+mod runtime_dir;
+#[cfg(target_os = "linux")]
+mod sandbox;
 mod supervisor;
 
-pub use command::Command;
+pub use capture::{CapturedStream, OutputLimits};
+pub use command::{Command, OutputOverflow, StdinSource};
+pub use containment::RecordedContainment;
 pub use error::{ProcessError, ProcessOutput};
-pub use event::{ProcessEvent, TerminatedPayload};
-pub use handle::{Containment, ProcessHandle};
+pub use event::{ExitReason, ProcessEvent, TerminatedPayload};
+pub use handle::{Containment, ProcessHandle, StdinWriter};
+pub use manager_lock::{ManagerLock, ManagerLockError};
 pub use pid_file::{
-    EpochPidFile, EpochPidRecord, OrphanReapOutcome, read_epoch_pid_file, reap_epoch_pid_file,
+    EpochPidFile, EpochPidRecord, OrphanReapOutcome, ProcessIdentity, read_epoch_pid_file,
+    reap_epoch_pid_file,
 };
+#[cfg(unix)]
+pub use pty::PtySize;
+pub use runtime_dir::{RuntimeArtifacts, RuntimeDir, SweepReport};
+#[cfg(target_os = "linux")]
+pub use sandbox::{MountIsolation, Sandbox, SeccompPreset};
 pub use supervisor::{
     Backoff, ReadinessProbe, RestartPolicy, RestartStormPolicy, Supervisor, SupervisorBuilder,
     SupervisorEvent,
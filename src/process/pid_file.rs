@@ -1,13 +1,25 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
     path::{Path, PathBuf},
-    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    sync::atomic::{AtomicU32, Ordering},
     time::Duration,
 };
 
-use tokio::io::AsyncWriteExt;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-const EPOCH_PID_VERSION: u32 = 2;
+use super::{
+    containment::RecordedContainment,
+    handle::Containment,
+    intent::{self, IntentRecord},
+};
+
+/// Later versions only add fields, so readers accept any version from 3 on
+/// and skip keys they do not know.
+const EPOCH_PID_VERSION: u32 = 3;
+/// Records written before the containment field; still readable, with their
+/// exact field set.
+const EPOCH_PID_VERSION_NO_CONTAINMENT: u32 = 2;
 const IDENTITY_WAIT_ATTEMPTS: usize = 20;
 const IDENTITY_WAIT_DELAY: Duration = Duration::from_millis(25);
 const KILL_WAIT_ATTEMPTS: usize = 100;
@@ -44,28 +56,188 @@ impl EpochPidFile {
 }
 
 /// Versioned pid-file contents used for post-manager-kill orphan recovery.
+///
+/// [`str::parse`] and [`EpochPidRecord::to_string`] convert to and from the
+/// on-disk format. With the `serde` feature the record also (de)serializes
+/// as a map carrying the same `version`.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "VersionedEpochPidRecord", try_from = "VersionedEpochPidRecord")
+)]
 pub struct EpochPidRecord {
     pub pid: u32,
     pub epoch: u64,
     pub executable: String,
     pub start_token: u64,
     pub runtime_config: PathBuf,
+    /// The kernel group the core was spawned into, if it has a persistent
+    /// identity. `None` in records from before version 3, and for a kind
+    /// recorded by a newer version that this one does not know.
+    pub containment: Option<RecordedContainment>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 pub enum OrphanReapOutcome {
     NotFound,
     AlreadyExited,
     Killed,
 }
 
+impl EpochPidRecord {
+    /// The on-disk format. Fails if the runtime config path is not UTF-8.
+    pub fn to_string(&self) -> std::io::Result<String> {
+        serialize_epoch_record(self)
+    }
+}
+
+impl std::str::FromStr for EpochPidRecord {
+    type Err = std::io::Error;
+
+    fn from_str(raw: &str) -> std::io::Result<Self> {
+        parse_epoch_record(raw)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct VersionedEpochPidRecord {
+    version: u32,
+    pid: u32,
+    epoch: u64,
+    executable: String,
+    start_token: u64,
+    runtime_config: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    containment: Option<VersionedContainment>,
+}
+
+/// A containment kind this version does not know, which a newer manager may
+/// have recorded, deserializes as `Unknown` instead of failing the record.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum VersionedContainment {
+    Known(RecordedContainment),
+    #[serde(skip_serializing)]
+    Unknown(serde::de::IgnoredAny),
+}
+
+#[cfg(feature = "serde")]
+impl From<EpochPidRecord> for VersionedEpochPidRecord {
+    fn from(record: EpochPidRecord) -> Self {
+        Self {
+            version: EPOCH_PID_VERSION,
+            pid: record.pid,
+            epoch: record.epoch,
+            executable: record.executable,
+            start_token: record.start_token,
+            runtime_config: record.runtime_config,
+            containment: record.containment.map(VersionedContainment::Known),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<VersionedEpochPidRecord> for EpochPidRecord {
+    type Error = String;
+
+    fn try_from(record: VersionedEpochPidRecord) -> Result<Self, String> {
+        if record.version < EPOCH_PID_VERSION_NO_CONTAINMENT {
+            return Err(format!(
+                "unsupported epoch pid record version {}",
+                record.version
+            ));
+        }
+        // Same rules as the text form: version 2 predates containment, and
+        // only a newer version may carry a kind this one does not know.
+        let containment = match record.containment {
+            Some(VersionedContainment::Known(containment))
+                if record.version >= EPOCH_PID_VERSION =>
+            {
+                Some(containment)
+            }
+            Some(VersionedContainment::Unknown(_)) if record.version > EPOCH_PID_VERSION => None,
+            Some(_) => {
+                return Err(format!(
+                    "invalid containment in epoch pid record version {}",
+                    record.version
+                ));
+            }
+            None => None,
+        };
+        Ok(Self {
+            pid: record.pid,
+            epoch: record.epoch,
+            executable: record.executable,
+            start_token: record.start_token,
+            runtime_config: record.runtime_config,
+            containment,
+        })
+    }
+}
+
+/// A live process pinned down beyond its pid, which the OS may reuse: the
+/// executable's file name plus a start token (the boot-bound start time on
+/// Linux, the creation time on Windows, the start time elsewhere).
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct ProcessIdentity {
+pub struct ProcessIdentity {
+    pid: u32,
     executable: String,
     start_token: u64,
 }
 
+impl ProcessIdentity {
+    /// Queries the process currently holding `pid`; `None` if there is none.
+    /// A process that exits mid-query also yields `None` rather than a mixed
+    /// identity.
+    pub fn of(pid: u32) -> std::io::Result<Option<Self>> {
+        process_identity(pid)
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// The executable's file name, without its directory.
+    pub fn executable(&self) -> &str {
+        &self.executable
+    }
+
+    /// Opaque; only meaningful when compared with another token on this host.
+    pub fn start_token(&self) -> u64 {
+        self.start_token
+    }
+
+    /// Whether both identities describe the same process, i.e. the pid was
+    /// not reused in between. Executable names compare case-insensitively on
+    /// Windows.
+    pub fn matches(&self, other: &Self) -> bool {
+        self.pid == other.pid
+            && self.start_token == other.start_token
+            && exe_names_equal(&self.executable, &other.executable)
+    }
+
+    /// Whether `handle`'s child is still this process. `false` once the
+    /// child has exited, even if its pid has been reused since.
+    pub fn is_same_process_as(
+        &self,
+        handle: &super::handle::ProcessHandle,
+    ) -> std::io::Result<bool> {
+        if handle.pid() != self.pid || handle.terminated.borrow().is_some() {
+            return Ok(false);
+        }
+        Ok(Self::of(self.pid)?.is_some_and(|current| self.matches(&current)))
+    }
+}
+
 /// Owns one pid file around a spawned child.
 ///
 /// Legacy numeric files remain supported for writing and cleanup, but no longer
@@ -80,6 +252,8 @@ pub(crate) enum PidFileGuard {
         spec: EpochPidFile,
         expected_exe: String,
         record: parking_lot::Mutex<Option<EpochPidRecord>>,
+        /// Published before spawn; superseded by the record.
+        intent: parking_lot::Mutex<Option<IntentRecord>>,
     },
 }
 
@@ -104,15 +278,28 @@ impl PidFileGuard {
             reap_record(&record).await?;
             remove_record_if_matches(&spec.path, &record).await?;
         }
+        let intent_path = intent::intent_path(&spec.path);
+        intent::recover_intent(&intent_path).await?;
+        let intent = IntentRecord::new(spec.epoch, expected_exe.clone())?;
+        intent::write_intent(&intent_path, &intent).await?;
 
         Ok(Self::Epoch {
             spec,
             expected_exe,
             record: parking_lot::Mutex::new(None),
+            intent: parking_lot::Mutex::new(Some(intent)),
         })
     }
 
-    pub(crate) async fn write(&self, pid: u32) -> std::io::Result<()> {
+    /// The value for [`intent::INTENT_ENV`] in the child's environment.
+    pub(crate) fn intent_tag(&self) -> Option<String> {
+        match self {
+            Self::Legacy { .. } => None,
+            Self::Epoch { intent, .. } => intent.lock().as_ref().map(|intent| intent.tag.clone()),
+        }
+    }
+
+    pub(crate) async fn write(&self, pid: u32, containment: Containment) -> std::io::Result<()> {
         match self {
             Self::Legacy { path, pid: slot } => {
                 crate::os::create_pid_file(path, pid).await?;
@@ -123,13 +310,25 @@ impl PidFileGuard {
                 spec,
                 expected_exe,
                 record,
+                intent,
             } => {
                 // The child is observable between group.start() and this
-                // identity-bound record publication. The manager sweeps stale
-                // `core-{epoch}.pid.tmp-*` files, but a hard kill inside this
-                // narrow pre-record interval can leave an orphan without an
-                // authoritative identity record; such a process is never
-                // killed on an unproven numeric PID.
+                // identity-bound record publication. A hard kill inside this
+                // interval leaves only the spawn intent, so it first learns
+                // the child's cgroup; until then its tag lets recovery find
+                // the child without trusting a numeric PID.
+                let cgroup = RecordedContainment::capture(pid, containment)
+                    .filter(|cgroup| matches!(cgroup, RecordedContainment::CgroupV2 { .. }));
+                let pending = intent.lock().clone();
+                if let Some(mut pending) = pending
+                    && cgroup.is_some()
+                {
+                    pending.cgroup = cgroup;
+                    match intent::replace_intent(&intent::intent_path(&spec.path), &pending).await {
+                        Ok(()) => *intent.lock() = Some(pending),
+                        Err(error) => tracing::warn!("failed to record the spawn cgroup: {error}"),
+                    }
+                }
                 let identity = wait_for_process_identity(pid).await?.ok_or_else(|| {
                     identity_error(format!("spawned pid {pid} disappeared before recording"))
                 })?;
@@ -145,9 +344,17 @@ impl PidFileGuard {
                     executable: identity.executable,
                     start_token: identity.start_token,
                     runtime_config: spec.runtime_config.clone(),
+                    containment: RecordedContainment::capture(pid, containment),
                 };
                 write_epoch_record(&spec.path, &value).await?;
                 *record.lock() = Some(value);
+                let published = intent.lock().take();
+                if let Some(published) = published
+                    && let Err(error) =
+                        intent::remove_intent(&intent::intent_path(&spec.path), &published).await
+                {
+                    tracing::warn!("failed to remove spawn intent: {error}");
+                }
                 Ok(())
             }
         }
@@ -163,7 +370,19 @@ impl PidFileGuard {
                 }
                 tokio::fs::remove_file(path).await
             }
-            Self::Epoch { spec, record, .. } => {
+            Self::Epoch {
+                spec,
+                record,
+                intent,
+                ..
+            } => {
+                let pending = intent.lock().take();
+                if let Some(pending) = pending
+                    && let Err(error) =
+                        intent::remove_intent(&intent::intent_path(&spec.path), &pending).await
+                {
+                    tracing::warn!("failed to remove spawn intent: {error}");
+                }
                 let value = record.lock().clone();
                 match value {
                     Some(value) => remove_record_if_matches(&spec.path, &value).await,
@@ -217,10 +436,13 @@ pub async fn read_epoch_pid_file(
 ///
 /// Live descendants are captured before the recorded root is killed, and each
 /// captured process is killed only while its own executable and start token
-/// still match. Descendants that reparent or exit before the two enumeration
-/// snapshots observe them cannot be attributed to the epoch and are
-/// deliberately not killed; persistent group/job identity would be required
-/// to close that gap.
+/// still match. Descendants that reparent before the two enumeration
+/// snapshots observe them are reached through the recorded containment: the
+/// core's cgroup is killed through `cgroup.kill`, even after the root has
+/// exited as long as the cgroup's recorded instance still matches, and the
+/// members of its process group and session are killed one by one while the
+/// root is alive. Records without containment (version 2, Windows, or a group
+/// shared with the manager) leave such descendants running.
 pub async fn reap_epoch_pid_file(
     path: impl AsRef<Path>,
     runtime_dir: impl AsRef<Path>,
@@ -355,7 +577,11 @@ fn validate_record_for_spec(
 
 async fn reap_record(record: &EpochPidRecord) -> std::io::Result<OrphanReapOutcome> {
     let Some(identity) = identity_for_reap(record.pid).await? else {
-        return Ok(OrphanReapOutcome::AlreadyExited);
+        return Ok(if kill_contained(record, false).await? {
+            OrphanReapOutcome::Killed
+        } else {
+            OrphanReapOutcome::AlreadyExited
+        });
     };
     if !record_matches_identity(record, &identity) {
         return Err(identity_error(format!(
@@ -365,6 +591,7 @@ async fn reap_record(record: &EpochPidRecord) -> std::io::Result<OrphanReapOutco
     }
 
     let descendants = capture_descendants(record.pid);
+    let killed_contained = kill_contained(record, true).await?;
     let root_outcome =
         reap_record_with_kill(record, false, || kill_recorded_process(record)).await?;
     let mut killed_descendant = false;
@@ -376,6 +603,7 @@ async fn reap_record(record: &EpochPidRecord) -> std::io::Result<OrphanReapOutco
             executable: descendant.identity.executable,
             start_token: descendant.identity.start_token,
             runtime_config: record.runtime_config.clone(),
+            containment: None,
         };
         match reap_record_with_kill(&descendant_record, true, || {
             kill_recorded_process(&descendant_record)
@@ -393,13 +621,22 @@ async fn reap_record(record: &EpochPidRecord) -> std::io::Result<OrphanReapOutco
             failures.join("; ")
         )));
     }
-    if killed_descendant {
+    if killed_descendant || killed_contained {
         Ok(OrphanReapOutcome::Killed)
     } else {
         Ok(root_outcome)
     }
 }
 
+/// Kills what is left in the record's containment; `root_alive` says the
+/// root was validated just before.
+async fn kill_contained(record: &EpochPidRecord, root_alive: bool) -> std::io::Result<bool> {
+    match &record.containment {
+        Some(containment) => containment.kill_members(root_alive).await,
+        None => Ok(false),
+    }
+}
+
 async fn reap_record_with_kill<K, F>(
     record: &EpochPidRecord,
     unowned_is_dead: bool,
@@ -621,6 +858,7 @@ fn process_identity(pid: u32) -> std::io::Result<Option<ProcessIdentity>> {
         .and_then(|name| name.to_str())
         .ok_or_else(|| identity_error(format!("cannot resolve executable for live pid {pid}")))?;
     Ok(Some(ProcessIdentity {
+        pid,
         executable: executable.to_owned(),
         start_token: process.start_time(),
     }))
@@ -648,6 +886,7 @@ fn process_identity(pid: u32) -> std::io::Result<Option<ProcessIdentity>> {
         .ok_or_else(|| identity_error(format!("cannot resolve executable for live pid {pid}")))?;
     let boot_id = std::fs::read_to_string("/proc/sys/kernel/random/boot_id")?;
     Ok(Some(ProcessIdentity {
+        pid,
         executable: executable.to_owned(),
         start_token: boot_bound_start_token(boot_id.trim(), first_ticks),
     }))
@@ -673,7 +912,7 @@ fn linux_start_ticks(pid: u32) -> std::io::Result<Option<u64>> {
 }
 
 #[cfg(target_os = "linux")]
-fn boot_bound_start_token(boot_id: &str, ticks: u64) -> u64 {
+pub(super) fn boot_bound_start_token(boot_id: &str, ticks: u64) -> u64 {
     let mut hash = 0xcbf2_9ce4_8422_2325_u64;
     for byte in boot_id.bytes().chain(ticks.to_le_bytes()) {
         hash ^= u64::from(byte);
@@ -773,6 +1012,7 @@ fn process_identity_from_handle(
         .and_then(|name| name.to_str())
         .ok_or_else(|| identity_error(format!("cannot resolve executable for live pid {pid}")))?;
     Ok(ProcessIdentity {
+        pid,
         executable: executable.to_owned(),
         start_token: (u64::from(creation.dwHighDateTime) << 32) | u64::from(creation.dwLowDateTime),
     })
@@ -830,14 +1070,11 @@ async fn kill_recorded_process(record: &EpochPidRecord) -> std::io::Result<()> {
 
 #[cfg(target_os = "linux")]
 async fn kill_recorded_process(record: &EpochPidRecord) -> std::io::Result<()> {
-    let pidfd = match LinuxPidFd::open(record.pid) {
-        Ok(pidfd) => pidfd,
-        Err(error) if error.raw_os_error() == Some(libc::ENOSYS) => {
-            return kill_revalidated_by_pid(record);
-        }
-        Err(error) => return Err(error),
-    };
-    let identity = process_identity(record.pid)?.ok_or_else(|| {
+    let handle = crate::os::PidHandle::open(record.pid)?;
+    if !handle.is_pidfd() {
+        return kill_revalidated_by_pid(record);
+    }
+    let identity = handle.identity()?.ok_or_else(|| {
         std::io::Error::new(
             std::io::ErrorKind::NotFound,
             format!("pid {} exited before pidfd validation", record.pid),
@@ -849,53 +1086,9 @@ async fn kill_recorded_process(record: &EpochPidRecord) -> std::io::Result<()> {
             record.pid
         )));
     }
-    pidfd.kill()
-}
-
-#[cfg(target_os = "linux")]
-struct LinuxPidFd(std::os::fd::RawFd);
-
-#[cfg(target_os = "linux")]
-impl LinuxPidFd {
-    fn open(pid: u32) -> std::io::Result<Self> {
-        // SAFETY: pidfd_open receives a numeric pid and zero flags.
-        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0_u32) };
-        if fd < 0 {
-            Err(std::io::Error::last_os_error())
-        } else {
-            Ok(Self(fd as std::os::fd::RawFd))
-        }
-    }
-
-    fn kill(&self) -> std::io::Result<()> {
-        // SAFETY: self.0 is a live pidfd and the optional siginfo pointer is null.
-        let result = unsafe {
-            libc::syscall(
-                libc::SYS_pidfd_send_signal,
-                self.0,
-                libc::SIGKILL,
-                std::ptr::null::<libc::siginfo_t>(),
-                0_u32,
-            )
-        };
-        if result < 0 {
-            let error = std::io::Error::last_os_error();
-            if error.raw_os_error() == Some(libc::ESRCH) {
-                Ok(())
-            } else {
-                Err(error)
-            }
-        } else {
-            Ok(())
-        }
-    }
-}
-
-#[cfg(target_os = "linux")]
-impl Drop for LinuxPidFd {
-    fn drop(&mut self) {
-        // SAFETY: self uniquely owns this pidfd.
-        unsafe { libc::close(self.0) };
+    match handle.send_signal(nix::sys::signal::Signal::SIGKILL) {
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
     }
 }
 
@@ -940,7 +1133,21 @@ async fn kill_recorded_process(record: &EpochPidRecord) -> std::io::Result<()> {
             record.pid
         )));
     }
-    crate::os::kill_pid::<String>(record.pid, None).await
+    let kind =
+        sysinfo::RefreshKind::nothing().with_processes(sysinfo::ProcessRefreshKind::nothing());
+    let mut system = sysinfo::System::new_with_specifics(kind);
+    system.refresh_specifics(kind);
+    match system.process(sysinfo::Pid::from_u32(record.pid)) {
+        Some(process) if process.kill() => Ok(()),
+        Some(_) => Err(std::io::Error::other(format!(
+            "failed to kill pid {}",
+            record.pid
+        ))),
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("pid {} exited before the kill", record.pid),
+        )),
+    }
 }
 
 fn record_matches_identity(record: &EpochPidRecord, identity: &ProcessIdentity) -> bool {
@@ -959,54 +1166,30 @@ fn exe_names_equal(left: &str, right: &str) -> bool {
 }
 
 async fn write_epoch_record(path: &Path, record: &EpochPidRecord) -> std::io::Result<()> {
-    static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+    use crate::io::atomic_fs::{AtomicFsError, AtomicWriteOptions, write_atomic};
 
     validate_pid_target(path).await?;
-    if tokio::fs::try_exists(path).await? {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::AlreadyExists,
-            format!("pid file unexpectedly exists: {}", path.display()),
-        ));
-    }
-    let counter = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
-    let temp = path.with_extension(format!("pid.tmp-{}-{counter}", std::process::id()));
-    let result = async {
-        let mut file = tokio::fs::OpenOptions::new()
-            .create_new(true)
-            .write(true)
-            .open(&temp)
-            .await?;
-        file.write_all(serialize_epoch_record(record)?.as_bytes())
-            .await?;
-        file.flush().await?;
-        file.sync_all().await?;
-        drop(file);
-        // The shared publisher fails atomically if the destination appeared
-        // after the `try_exists` check above. The staging path shares the
-        // destination directory, as required by both platform implementations.
-        crate::io::atomic_fs::atomic_move_new(&temp, path)
-            .await
-            .map_err(|error| match error {
-                crate::io::atomic_fs::AtomicFsError::Io(error)
-                    if error.kind() == std::io::ErrorKind::AlreadyExists =>
-                {
-                    std::io::Error::new(
-                        std::io::ErrorKind::AlreadyExists,
-                        format!("pid file unexpectedly exists: {}", path.display()),
-                    )
-                }
-                crate::io::atomic_fs::AtomicFsError::Io(error) => error,
-                crate::io::atomic_fs::AtomicFsError::UnsafePath(path)
-                | crate::io::atomic_fs::AtomicFsError::Contended(path) => std::io::Error::other(
-                    format!("unexpected atomic filesystem error for {}", path.display()),
-                ),
-            })
-    }
-    .await;
-    // Publication consumes `temp` on success; on failure it remains and must
-    // be cleaned up here.
-    let _ = tokio::fs::remove_file(&temp).await;
-    result
+    let raw = serialize_epoch_record(record)?;
+    // A create-new publication fails atomically if the destination appeared
+    // meanwhile. Its `{path}.tmp-{pid}-{counter}` staging file is what
+    // `RuntimeDir::sweep` collects after a crash.
+    write_atomic(path, raw, AtomicWriteOptions::new().create_new(true))
+        .await
+        .map_err(|error| match error {
+            AtomicFsError::Io(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+                std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("pid file unexpectedly exists: {}", path.display()),
+                )
+            }
+            AtomicFsError::Io(error) => error,
+            AtomicFsError::UnsafePath(path) | AtomicFsError::Contended(path) => {
+                std::io::Error::other(format!(
+                    "unexpected atomic filesystem error for {}",
+                    path.display()
+                ))
+            }
+        })
 }
 
 async fn remove_record_if_matches(path: &Path, expected: &EpochPidRecord) -> std::io::Result<()> {
@@ -1024,14 +1207,18 @@ fn serialize_epoch_record(record: &EpochPidRecord) -> std::io::Result<String> {
     let runtime_config = record.runtime_config.to_str().ok_or_else(|| {
         invalid_input("runtime config path must be UTF-8 for an epoch pid record")
     })?;
-    Ok(format!(
+    let mut raw = format!(
         "version={EPOCH_PID_VERSION}\npid={}\nepoch={}\nexecutable={}\nstart-token={}\nruntime-config={}\n",
         record.pid,
         record.epoch,
         hex_encode(record.executable.as_bytes()),
         record.start_token,
         hex_encode(runtime_config.as_bytes()),
-    ))
+    );
+    if let Some(containment) = &record.containment {
+        raw.push_str(&format!("containment={}\n", containment.serialize()));
+    }
+    Ok(raw)
 }
 
 fn parse_epoch_record(raw: &str) -> std::io::Result<EpochPidRecord> {
@@ -1052,14 +1239,18 @@ fn parse_epoch_record(raw: &str) -> std::io::Result<EpochPidRecord> {
         "start-token",
         "version",
     ];
-    if fields.len() != expected.len() || !expected.iter().all(|key| fields.contains_key(key)) {
-        return Err(invalid_data("epoch pid record fields are incomplete"));
-    }
     let version = parse_field::<u32>(&fields, "version")?;
-    if version != EPOCH_PID_VERSION {
-        return Err(invalid_data(format!(
-            "unsupported epoch pid record version {version}"
-        )));
+    let complete = match version {
+        EPOCH_PID_VERSION_NO_CONTAINMENT => fields.len() == expected.len(),
+        version if version >= EPOCH_PID_VERSION => true,
+        _ => {
+            return Err(invalid_data(format!(
+                "unsupported epoch pid record version {version}"
+            )));
+        }
+    };
+    if !complete || !expected.iter().all(|key| fields.contains_key(key)) {
+        return Err(invalid_data("epoch pid record fields are incomplete"));
     }
     let executable = String::from_utf8(hex_decode(required(&fields, "executable")?)?)
         .map_err(|_| invalid_data("epoch pid executable is not UTF-8"))?;
@@ -1071,17 +1262,26 @@ fn parse_epoch_record(raw: &str) -> std::io::Result<EpochPidRecord> {
         executable,
         start_token: parse_field(&fields, "start-token")?,
         runtime_config: PathBuf::from(runtime_config),
+        containment: match fields.get("containment") {
+            // A newer manager may record a kind this one does not know.
+            Some(value) if version > EPOCH_PID_VERSION => RecordedContainment::parse(value).ok(),
+            Some(value) => Some(RecordedContainment::parse(value)?),
+            None => None,
+        },
     })
 }
 
-fn required<'a>(fields: &'a BTreeMap<&str, &str>, key: &str) -> std::io::Result<&'a str> {
+pub(super) fn required<'a>(
+    fields: &'a BTreeMap<&str, &str>,
+    key: &str,
+) -> std::io::Result<&'a str> {
     fields
         .get(key)
         .copied()
         .ok_or_else(|| invalid_data(format!("missing epoch pid field `{key}`")))
 }
 
-fn parse_field<T: std::str::FromStr>(
+pub(super) fn parse_field<T: std::str::FromStr>(
     fields: &BTreeMap<&str, &str>,
     key: &str,
 ) -> std::io::Result<T> {
@@ -1090,7 +1290,7 @@ fn parse_field<T: std::str::FromStr>(
         .map_err(|_| invalid_data(format!("invalid epoch pid field `{key}`")))
 }
 
-fn hex_encode(bytes: &[u8]) -> String {
+pub(super) fn hex_encode(bytes: &[u8]) -> String {
     const HEX: &[u8; 16] = b"0123456789abcdef";
     let mut output = String::with_capacity(bytes.len() * 2);
     for byte in bytes {
@@ -1100,7 +1300,7 @@ fn hex_encode(bytes: &[u8]) -> String {
     output
 }
 
-fn hex_decode(value: &str) -> std::io::Result<Vec<u8>> {
+pub(super) fn hex_decode(value: &str) -> std::io::Result<Vec<u8>> {
     let (pairs, remainder) = value.as_bytes().as_chunks::<2>();
     if !remainder.is_empty() {
         return Err(invalid_data("hex field has odd length"));
@@ -1123,7 +1323,11 @@ fn hex_nibble(value: u8) -> std::io::Result<u8> {
     }
 }
 
-fn epoch_from_file_name(path: &Path, prefix: &str, suffix: &str) -> std::io::Result<u64> {
+pub(super) fn epoch_from_file_name(
+    path: &Path,
+    prefix: &str,
+    suffix: &str,
+) -> std::io::Result<u64> {
     let name = path
         .file_name()
         .and_then(|name| name.to_str())
@@ -1141,7 +1345,7 @@ fn invalid_input(message: impl Into<String>) -> std::io::Error {
     std::io::Error::new(std::io::ErrorKind::InvalidInput, message.into())
 }
 
-fn invalid_data(message: impl Into<String>) -> std::io::Error {
+pub(super) fn invalid_data(message: impl Into<String>) -> std::io::Error {
     std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
 }
 
@@ -1161,6 +1365,7 @@ mod tests {
             executable: "core=name.exe".into(),
             start_token: 99,
             runtime_config: PathBuf::from(r"C:\run dir\config-7.yaml"),
+            containment: Some(RecordedContainment::ProcessGroup { pgid: 42, sid: 40 }),
         };
         assert_eq!(
             parse_epoch_record(&serialize_epoch_record(&record).unwrap()).unwrap(),
@@ -1168,6 +1373,146 @@ mod tests {
         );
     }
 
+    #[test]
+    fn version_2_records_parse_without_containment() {
+        let raw = format!(
+            "version=2\npid=42\nepoch=7\nexecutable={}\nstart-token=99\nruntime-config={}\n",
+            hex_encode(b"mihomo"),
+            hex_encode(b"config-7.yaml"),
+        );
+        let record = parse_epoch_record(&raw).unwrap();
+        assert_eq!(record.pid, 42);
+        assert_eq!(record.containment, None);
+        assert!(parse_epoch_record(&format!("{raw}containment=pgrp:1:1\n")).is_err());
+    }
+
+    #[test]
+    fn newer_records_skip_unknown_fields() {
+        let record = EpochPidRecord {
+            pid: 42,
+            epoch: 7,
+            executable: "mihomo".into(),
+            start_token: 99,
+            runtime_config: PathBuf::from("config-7.yaml"),
+            containment: None,
+        };
+        let raw = record
+            .to_string()
+            .unwrap()
+            .replace("version=3", "version=4")
+            + "future-field=1\n";
+        assert_eq!(raw.parse::<EpochPidRecord>().unwrap(), record);
+        let unknown_kind = format!("{raw}containment=job:1\n");
+        assert_eq!(unknown_kind.parse::<EpochPidRecord>().unwrap(), record);
+        assert!(
+            unknown_kind
+                .replace("version=4", "version=3")
+                .parse::<EpochPidRecord>()
+                .is_err()
+        );
+        assert!("version=1\n".parse::<EpochPidRecord>().is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_representation_is_versioned() {
+        let record = EpochPidRecord {
+            pid: 42,
+            epoch: 7,
+            executable: "mihomo".into(),
+            start_token: 99,
+            runtime_config: PathBuf::from("config-7.yaml"),
+            containment: Some(RecordedContainment::CgroupV2 {
+                path: PathBuf::from("/nyanpasu/7"),
+                instance: Some(5),
+            }),
+        };
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["version"], EPOCH_PID_VERSION);
+        assert_eq!(json["containment"]["cgroup-v2"]["path"], "/nyanpasu/7");
+        assert_eq!(
+            serde_json::from_value::<EpochPidRecord>(json).unwrap(),
+            record
+        );
+        assert_eq!(
+            serde_json::to_string(&OrphanReapOutcome::AlreadyExited).unwrap(),
+            r#""already-exited""#
+        );
+        assert!(
+            serde_json::from_str::<EpochPidRecord>(
+                r#"{"version":3,"pid":1,"epoch":1,"executable":"x","start-token":1,
+                "runtime-config":"c","containment":{"cgroup-v2":{"path":"/"}}}"#
+            )
+            .is_err()
+        );
+        let with_containment = |version: u32, containment: &str| {
+            serde_json::from_str::<EpochPidRecord>(&format!(
+                r#"{{"version":{version},"pid":1,"epoch":1,"executable":"x","start-token":1,
+                "runtime-config":"c","containment":{containment}}}"#
+            ))
+        };
+        let process_group = r#"{"process-group":{"pgid":2,"sid":2}}"#;
+        assert!(with_containment(2, process_group).is_err());
+        assert!(
+            with_containment(3, process_group)
+                .unwrap()
+                .containment
+                .is_some()
+        );
+        let job = r#"{"job":{"handle":1}}"#;
+        assert!(with_containment(3, job).is_err());
+        assert_eq!(with_containment(4, job).unwrap().containment, None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn reaping_an_exited_root_leaves_its_process_group_alone() {
+        use std::os::unix::process::CommandExt;
+
+        let output = std::process::Command::new("sh")
+            .args(["-c", "sleep 30 >/dev/null 2>&1 & echo $!"])
+            .process_group(0)
+            .output()
+            .unwrap();
+        let orphan = std::str::from_utf8(&output.stdout)
+            .unwrap()
+            .trim()
+            .parse::<u32>()
+            .unwrap();
+        let handle = crate::os::PidHandle::open(orphan).unwrap();
+        // SAFETY: getpgid and getsid only read the ids of a numeric pid.
+        let (pgid, sid) = unsafe {
+            (
+                libc::getpgid(orphan as libc::pid_t) as u32,
+                libc::getsid(orphan as libc::pid_t) as u32,
+            )
+        };
+        let record = EpochPidRecord {
+            // The shell led the group and has already been reaped.
+            pid: pgid,
+            epoch: 1,
+            executable: "sh".into(),
+            start_token: 0,
+            runtime_config: PathBuf::from("config-1.yaml"),
+            containment: Some(RecordedContainment::ProcessGroup { pgid, sid }),
+        };
+        if process_identity(pgid).unwrap().is_some() {
+            eprintln!("skipping: the shell's pid was reused");
+            return;
+        }
+
+        // Nothing pins the group id once its root is gone, so the reap must
+        // not assume the group is still the core's.
+        assert_eq!(
+            reap_record(&record).await.unwrap(),
+            OrphanReapOutcome::AlreadyExited
+        );
+        assert!(handle.is_alive().unwrap());
+        handle
+            .send_signal(nix::sys::signal::Signal::SIGKILL)
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn write_epoch_record_second_publish_does_not_clobber_first() {
         let dir = tempfile::tempdir().unwrap();
@@ -1178,6 +1523,7 @@ mod tests {
             executable: "first.exe".into(),
             start_token: 1,
             runtime_config: dir.path().join("config-1.yaml"),
+            containment: None,
         };
         let second = EpochPidRecord {
             pid: 222,
@@ -1185,6 +1531,7 @@ mod tests {
             executable: "second.exe".into(),
             start_token: 2,
             runtime_config: dir.path().join("config-1.yaml"),
+            containment: None,
         };
 
         write_epoch_record(&pid_path, &first).await.unwrap();
@@ -1204,6 +1551,7 @@ mod tests {
     #[test]
     fn second_snapshot_only_descendant_is_captured() {
         let late_identity = ProcessIdentity {
+            pid: 22,
             executable: "late-child".into(),
             start_token: 22,
         };
@@ -1218,6 +1566,7 @@ mod tests {
     #[test]
     fn unreadable_second_snapshot_identity_is_not_attributed() {
         let first_identity = ProcessIdentity {
+            pid: 7,
             executable: "old-child".into(),
             start_token: 7,
         };
@@ -1275,14 +1624,18 @@ mod tests {
             executable: identity.executable,
             start_token: identity.start_token,
             runtime_config: PathBuf::from("config-1.yaml"),
+            containment: None,
         };
 
-        let outcome = reap_record_with_kill(&record, false, || async move {
-            crate::os::kill_pid::<String>(pid, None).await?;
-            Err(std::io::Error::new(
-                std::io::ErrorKind::PermissionDenied,
-                "simulated already-terminating process",
-            ))
+        let outcome = reap_record_with_kill(&record, false, || {
+            let killed = child.kill();
+            async move {
+                killed?;
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "simulated already-terminating process",
+                ))
+            }
         })
         .await
         .unwrap();
@@ -0,0 +1,248 @@
+//! Engine-owned stdout and stderr on Unix.
+//!
+//! processkit's reader splits output into lines and keeps reading whatever the
+//! consumer does, so the child's bytes are reshaped and a stalled consumer
+//! only moves the backlog into memory. Instead the child's stdout and stderr
+//! are pipes (or the pty) that the pump reads itself: raw mode sees the bytes
+//! exactly as written, and output the pump is not reading stays in the kernel,
+//! where a full pipe blocks the writer.
+
+use std::{
+    collections::VecDeque,
+    io,
+    os::fd::{OwnedFd, RawFd},
+    sync::Arc,
+};
+
+use nix::fcntl::{FcntlArg, FdFlag, OFlag, fcntl};
+use tokio::io::unix::AsyncFd;
+
+use super::pty::PtyMaster;
+
+/// The read end of a child's stdout or stderr pipe.
+pub(crate) struct OutputPipe(AsyncFd<OwnedFd>);
+
+impl OutputPipe {
+    /// Returns the reader and the write end for the child. Both are
+    /// close-on-exec; the child's `dup2` onto its stdio clears the flag there.
+    pub fn open() -> io::Result<(Self, OwnedFd)> {
+        let (read, write) = nix::unistd::pipe().map_err(io::Error::from)?;
+        for fd in [&read, &write] {
+            fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)).map_err(io::Error::from)?;
+        }
+        fcntl(&read, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).map_err(io::Error::from)?;
+        Ok((Self(AsyncFd::new(read)?), write))
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.0.readable().await?;
+            match guard.try_io(|fd| nix::unistd::read(fd.get_ref(), buf).map_err(io::Error::from)) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// Makes `stdout` and `stderr` the child's fds 1 and 2. Runs between fork and
+/// exec. Both are first parked at 3 or above: with the parent's own stdio
+/// closed, a write end can itself be fd 1 or 2 and would otherwise be
+/// overwritten before it is moved.
+pub(crate) fn attach_output(stdout: RawFd, stderr: RawFd) -> io::Result<()> {
+    for (source, target) in [(stdout, 1), (stderr, 2)].map(|(fd, target)| {
+        // SAFETY: fcntl on a descriptor the parent keeps open until spawn.
+        (unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 3) }, target)
+    }) {
+        if source == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: dup2 between descriptors owned by the child.
+        if unsafe { libc::dup2(source, target) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// One of the child's output streams, read only when the pump asks for more.
+///
+/// Nothing it hands out is longer than `max_len`: raw reads are sized to it
+/// and longer lines are cut, so the output ring's byte budget also bounds a
+/// child that never writes a newline.
+pub(crate) struct ChildStream {
+    reader: StreamReader,
+    buf: Box<[u8]>,
+    /// Pending complete lines in text mode; unused in raw mode.
+    lines: Option<LineSplitter>,
+    ready: VecDeque<Vec<u8>>,
+    eof: bool,
+}
+
+enum StreamReader {
+    Pipe(OutputPipe),
+    Pty(Arc<PtyMaster>),
+}
+
+/// What [`ChildStream::next`] hands back: a raw chunk or a complete line.
+pub(crate) enum StreamItem {
+    Chunk(Vec<u8>),
+    Line(Vec<u8>),
+}
+
+impl ChildStream {
+    const READ_SIZE: usize = 16 * 1024;
+
+    pub fn pipe(pipe: OutputPipe, lines: bool, max_len: usize) -> Self {
+        Self::new(StreamReader::Pipe(pipe), lines, max_len)
+    }
+
+    /// The terminal line discipline turns `\n` into `\r\n`; text mode strips
+    /// the `\r` again, raw mode keeps what the terminal produced.
+    pub fn pty(master: Arc<PtyMaster>, lines: bool, max_len: usize) -> Self {
+        Self::new(StreamReader::Pty(master), lines, max_len)
+    }
+
+    fn new(reader: StreamReader, lines: bool, max_len: usize) -> Self {
+        let max_len = max_len.max(1);
+        Self {
+            reader,
+            buf: vec![0; Self::READ_SIZE.min(max_len)].into_boxed_slice(),
+            lines: lines.then(|| LineSplitter::new(max_len)),
+            ready: VecDeque::new(),
+            eof: false,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.eof && self.ready.is_empty()
+    }
+
+    /// The next chunk or line, or `None` at end of stream. Cancel-safe: bytes
+    /// are only taken from the pipe by a read that completes, and whatever
+    /// it returned is kept in `self` until handed out.
+    pub async fn next(&mut self) -> Option<StreamItem> {
+        loop {
+            if let Some(item) = self.ready.pop_front() {
+                return Some(match self.lines {
+                    Some(_) => StreamItem::Line(item),
+                    None => StreamItem::Chunk(item),
+                });
+            }
+            if self.eof {
+                return None;
+            }
+            let read = match &self.reader {
+                StreamReader::Pipe(pipe) => pipe.read(&mut self.buf).await,
+                StreamReader::Pty(master) => master.read(&mut self.buf).await,
+            };
+            match read {
+                Ok(0) => self.finish(),
+                Ok(n) => match &mut self.lines {
+                    Some(lines) => lines.push(&self.buf[..n], &mut self.ready),
+                    None => self.ready.push_back(self.buf[..n].to_vec()),
+                },
+                Err(error) => {
+                    tracing::warn!("failed to read child output: {error}");
+                    self.finish();
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.eof = true;
+        if let Some(line) = self.lines.as_mut().and_then(LineSplitter::finish) {
+            self.ready.push_back(line);
+        }
+    }
+}
+
+/// Splits a byte stream into lines without their `\n` or a `\r` before it.
+/// A line reaching `max_len` bytes is emitted there and continues as the
+/// next one.
+pub(crate) struct LineSplitter {
+    partial: Vec<u8>,
+    max_len: usize,
+}
+
+impl LineSplitter {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            partial: Vec::new(),
+            max_len: max_len.max(1),
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[u8], out: &mut VecDeque<Vec<u8>>) {
+        for piece in chunk.split_inclusive(|&b| b == b'\n') {
+            let (mut body, terminated) = match piece.split_last() {
+                Some((b'\n', body)) => (body, true),
+                _ => (piece, false),
+            };
+            loop {
+                let room = self.max_len - self.partial.len();
+                if body.len() < room || (terminated && body.len() == room) {
+                    break;
+                }
+                self.partial.extend_from_slice(&body[..room]);
+                body = &body[room..];
+                out.push_back(std::mem::take(&mut self.partial));
+            }
+            self.partial.extend_from_slice(body);
+            if terminated {
+                if self.partial.last() == Some(&b'\r') {
+                    self.partial.pop();
+                }
+                out.push_back(std::mem::take(&mut self.partial));
+            }
+        }
+    }
+
+    /// The unterminated last line, if any.
+    pub fn finish(&mut self) -> Option<Vec<u8>> {
+        (!self.partial.is_empty()).then(|| std::mem::take(&mut self.partial))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_splitter_joins_chunks_and_strips_terminators() {
+        let mut splitter = LineSplitter::new(64);
+        let mut lines = VecDeque::new();
+        splitter.push(b"one\r\ntw", &mut lines);
+        splitter.push(b"o\n\nthr", &mut lines);
+        assert_eq!(Vec::from(lines), [&b"one"[..], b"two", b""]);
+        assert_eq!(splitter.finish().as_deref(), Some(&b"thr"[..]));
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn line_splitter_cuts_lines_at_max_len() {
+        let mut splitter = LineSplitter::new(4);
+        let mut lines = VecDeque::new();
+        splitter.push(b"abcd\nabcdefghij", &mut lines);
+        assert_eq!(Vec::from(lines), [&b"abcd"[..], b"abcd", b"efgh"]);
+        assert_eq!(splitter.finish().as_deref(), Some(&b"ij"[..]));
+    }
+
+    #[tokio::test]
+    async fn raw_stream_delivers_bytes_unchanged() {
+        let (pipe, write) = OutputPipe::open().unwrap();
+        let mut stream = ChildStream::pipe(pipe, false, 1024);
+        nix::unistd::write(&write, b"a\r\nb\xff").unwrap();
+        drop(write);
+        let mut bytes = Vec::new();
+        while let Some(item) = stream.next().await {
+            let StreamItem::Chunk(chunk) = item else {
+                panic!("raw stream produced a line");
+            };
+            bytes.extend(chunk);
+        }
+        assert_eq!(bytes, b"a\r\nb\xff");
+        assert!(stream.is_done());
+    }
+}
@@ -0,0 +1,203 @@
+//! Unix pseudo-terminal plumbing for [`crate::process::Command::pty`].
+//!
+//! The child gets the pty slave as stdin/stdout/stderr. It only becomes the
+//! controlling terminal when the child also runs in its own session
+//! ([`crate::process::Command::detach_session`]), since acquiring one needs
+//! `setsid`. `isatty` is true for all three streams either way, which is what
+//! tools check before emitting colors or progress bars. Without a controlling
+//! terminal the engine sends `SIGWINCH` to the child itself on resize.
+
+use std::{
+    io,
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+    sync::Arc,
+};
+
+use nix::{
+    fcntl::{FcntlArg, FdFlag, OFlag, fcntl},
+    pty::{Winsize, openpty},
+};
+use tokio::io::unix::AsyncFd;
+
+/// Terminal dimensions in character cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PtySize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+impl Default for PtySize {
+    fn default() -> Self {
+        Self { rows: 24, cols: 80 }
+    }
+}
+
+impl PtySize {
+    fn winsize(self) -> Winsize {
+        Winsize {
+            ws_row: self.rows,
+            ws_col: self.cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        }
+    }
+}
+
+/// A freshly allocated pty. The slave must be dropped once the child is
+/// running, otherwise the master never observes the hang-up at child exit.
+pub(crate) struct Pty {
+    pub master: Arc<PtyMaster>,
+    pub slave: OwnedFd,
+}
+
+impl Pty {
+    pub fn open(size: PtySize) -> io::Result<Self> {
+        let pty = openpty(Some(&size.winsize()), None).map_err(io::Error::from)?;
+        // openpty does not set close-on-exec; neither end may leak into the
+        // child beyond the slave's dup2 onto stdio.
+        for fd in [&pty.master, &pty.slave] {
+            fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)).map_err(io::Error::from)?;
+        }
+        fcntl(&pty.master, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).map_err(io::Error::from)?;
+        Ok(Self {
+            master: Arc::new(PtyMaster(AsyncFd::new(pty.master)?)),
+            slave: pty.slave,
+        })
+    }
+
+    pub fn slave_fd(&self) -> RawFd {
+        self.slave.as_raw_fd()
+    }
+}
+
+/// Makes `slave` the child's stdin, stdout and stderr. Runs between fork and
+/// exec, so it only calls async-signal-safe functions.
+pub(crate) fn attach_slave(slave: RawFd) -> io::Result<()> {
+    for target in 0..=2 {
+        // SAFETY: dup2 is async-signal-safe and `slave` stays open in the
+        // parent until the child has been spawned.
+        if unsafe { libc::dup2(slave, target) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Makes `slave` the controlling terminal of the child's new session. Runs
+/// between fork and exec, after `setsid`.
+pub(crate) fn make_controlling(slave: RawFd) -> io::Result<()> {
+    // SAFETY: TIOCSCTTY takes an integer argument; 0 refuses to steal a
+    // terminal that is already another session's.
+    if unsafe { libc::ioctl(slave, libc::TIOCSCTTY, 0) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The parent's view of a child running on a pty.
+pub(crate) struct PtyChild {
+    pub master: Arc<PtyMaster>,
+    /// The child itself when the pty is not its controlling terminal. The
+    /// kernel only sends `SIGWINCH` to a terminal's foreground process group,
+    /// so without one the resize has to be announced explicitly.
+    winch: Option<crate::os::PidHandle>,
+}
+
+impl PtyChild {
+    pub fn new(master: Arc<PtyMaster>, pid: u32, controlling: bool) -> Self {
+        let winch = if controlling {
+            None
+        } else {
+            // Bound to the process, so a late resize cannot signal a reused pid.
+            crate::os::PidHandle::open(pid).ok()
+        };
+        Self { master, winch }
+    }
+
+    pub fn resize(&self, size: PtySize) -> io::Result<()> {
+        self.master.resize(size)?;
+        if let Some(child) = &self.winch {
+            match child.send_signal(nix::sys::signal::Signal::SIGWINCH) {
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct PtyMaster(AsyncFd<OwnedFd>);
+
+impl PtyMaster {
+    /// Reads from the master. Linux reports `EIO` once every slave fd is
+    /// closed; that is the pty's end of stream.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.0.readable().await?;
+            match guard.try_io(|fd| nix::unistd::read(fd.get_ref(), buf).map_err(io::Error::from)) {
+                Ok(Err(error)) if error.raw_os_error() == Some(libc::EIO) => return Ok(0),
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    pub async fn write_all(&self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            let mut guard = self.0.writable().await?;
+            match guard.try_io(|fd| nix::unistd::write(fd.get_ref(), data).map_err(io::Error::from))
+            {
+                Ok(Ok(written)) => data = &data[written..],
+                Ok(Err(error)) => return Err(error),
+                Err(_would_block) => continue,
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the terminal's end-of-file character. Only a canonical-mode
+    /// reader treats it as EOF; a raw-mode child just sees a `0x04` byte.
+    pub async fn send_eof(&self) -> io::Result<()> {
+        self.write_all(&[0x04]).await
+    }
+
+    pub fn resize(&self, size: PtySize) -> io::Result<()> {
+        let winsize = size.winsize();
+        // SAFETY: TIOCSWINSZ reads one `winsize` from the pointer, which is
+        // valid for the duration of the call.
+        let rc = unsafe { libc::ioctl(self.0.as_raw_fd(), libc::TIOCSWINSZ, &winsize) };
+        if rc == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::pipes::{ChildStream, StreamItem};
+
+    #[tokio::test]
+    async fn master_reads_slave_lines_and_resizes() {
+        let pty = Pty::open(PtySize::default()).unwrap();
+        let mut stream = ChildStream::pty(pty.master.clone(), true, 1024);
+        nix::unistd::write(&pty.slave, b"hello\nwor").unwrap();
+        nix::unistd::write(&pty.slave, b"ld\n").unwrap();
+        for expected in [&b"hello"[..], b"world"] {
+            match stream.next().await {
+                Some(StreamItem::Line(line)) => assert_eq!(line, expected),
+                _ => panic!("expected a line"),
+            }
+        }
+        pty.master
+            .resize(PtySize {
+                rows: 40,
+                cols: 120,
+            })
+            .unwrap();
+        drop(pty.slave);
+        assert!(stream.next().await.is_none());
+    }
+}
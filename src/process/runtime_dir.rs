@@ -0,0 +1,365 @@
+//! Whole-directory management for epoch pid records.
+//!
+//! A runtime directory holds `core-{epoch}.pid` records, the matching
+//! `config-{epoch}.yaml` runtime configs, the `epoch` counter and the
+//! `{name}.tmp-{pid}-{n}` staging files an interrupted write leaves next to
+//! any of them. [`RuntimeDir`] enumerates them,
+//! reaps every stale epoch at manager startup and hands out epochs that never
+//! repeat. Listing takes the directory's [`DirLock`] shared, so readers
+//! coexist with each other; sweeping and allocating take it exclusively.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use super::{
+    manager_lock::ManagerLock,
+    pid_file::{EpochPidFile, OrphanReapOutcome, epoch_from_file_name, reap_epoch_pid_file},
+};
+use crate::io::atomic_fs::{AtomicFsError, DirLock, DirLockOptions, acquire_with_timeout};
+
+const LOCK_FILE: &str = "runtime.lock";
+/// Holds the last allocated epoch, so epochs stay monotonic after a sweep.
+const EPOCH_FILE: &str = "epoch";
+/// How long a caller waits for a conflicting holder of `runtime.lock`.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The runtime artifacts found in a [`RuntimeDir`], keyed by epoch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuntimeArtifacts {
+    pub pid_files: BTreeMap<u64, PathBuf>,
+    /// Spawn intents left by a manager killed before publishing the record.
+    pub intent_files: BTreeMap<u64, PathBuf>,
+    pub runtime_configs: BTreeMap<u64, PathBuf>,
+    pub staging_files: Vec<PathBuf>,
+}
+
+/// What [`RuntimeDir::sweep`] did. An epoch whose reap failed keeps its pid
+/// record and runtime config for the next sweep.
+#[derive(Debug, Default)]
+pub struct SweepReport {
+    pub reaped: BTreeMap<u64, std::io::Result<OrphanReapOutcome>>,
+    /// Children found through a spawn intent, per epoch.
+    pub recovered_intents: BTreeMap<u64, std::io::Result<OrphanReapOutcome>>,
+    pub removed_configs: Vec<PathBuf>,
+    pub removed_staging_files: Vec<PathBuf>,
+    /// Configs and staging files that could not be removed; the sweep goes on
+    /// without them.
+    pub failed_removals: Vec<(PathBuf, std::io::Error)>,
+}
+
+impl SweepReport {
+    fn record_failed_removal(&mut self, path: &Path, error: AtomicFsError) {
+        tracing::warn!("failed to remove {}: {error}", path.display());
+        self.failed_removals.push((path.to_owned(), into_io(error)));
+    }
+}
+
+/// A manager-owned runtime directory. [`RuntimeDir::sweep`] and
+/// [`RuntimeDir::next_epoch`] hold its `runtime.lock` exclusively,
+/// [`RuntimeDir::artifacts`] holds it shared. Each waits a few seconds for a
+/// conflicting holder, then fails with [`std::io::ErrorKind::WouldBlock`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeDir {
+    path: PathBuf,
+}
+
+impl RuntimeDir {
+    /// Opens an existing directory; the path is canonicalized.
+    pub async fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = tokio::fs::canonicalize(path.as_ref()).await?;
+        if !tokio::fs::metadata(&path).await?.is_dir() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("runtime path is not a directory: {}", path.display()),
+            ));
+        }
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The pid record and runtime config paths for `epoch`.
+    pub fn epoch_pid_file(&self, epoch: u64) -> EpochPidFile {
+        EpochPidFile::new(
+            self.path.join(format!("core-{epoch}.pid")),
+            epoch,
+            self.path.join(format!("config-{epoch}.yaml")),
+        )
+    }
+
+    /// Lists the runtime artifacts. Unrelated files are ignored.
+    pub async fn artifacts(&self) -> std::io::Result<RuntimeArtifacts> {
+        let _lock = self.lock(DirLockOptions::shared()).await?;
+        self.scan().await
+    }
+
+    async fn scan(&self) -> std::io::Result<RuntimeArtifacts> {
+        let mut artifacts = RuntimeArtifacts::default();
+        let mut entries = tokio::fs::read_dir(&self.path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+                continue;
+            };
+            if let Some((base, _)) = name.split_once(".tmp-") {
+                if is_managed_name(base) {
+                    artifacts.staging_files.push(path);
+                }
+            } else if let Ok(epoch) = epoch_from_file_name(&path, "core-", ".pid") {
+                artifacts.pid_files.insert(epoch, path);
+            } else if let Ok(epoch) = epoch_from_file_name(&path, "core-", ".intent") {
+                artifacts.intent_files.insert(epoch, path);
+            } else if let Ok(epoch) = epoch_from_file_name(&path, "config-", ".yaml") {
+                artifacts.runtime_configs.insert(epoch, path);
+            }
+        }
+        artifacts.staging_files.sort();
+        Ok(artifacts)
+    }
+
+    /// Reaps the orphan of every recorded epoch, then removes runtime
+    /// configs without a remaining pid record and staging files whose writer
+    /// has exited. A file that cannot be removed is reported, not fatal.
+    ///
+    /// Every live core recorded here is killed, so only the single manager
+    /// may sweep, at startup before it spawns any core: `manager` is its
+    /// [`ManagerLock`], which must be the lock guarding this directory.
+    pub async fn sweep(&self, manager: &ManagerLock) -> std::io::Result<SweepReport> {
+        tracing::debug!(
+            "sweeping {} under manager lock {}",
+            self.path.display(),
+            manager.path().display()
+        );
+        let _lock = self
+            .lock(DirLockOptions::exclusive().purpose("sweep"))
+            .await?;
+        let artifacts = self.scan().await?;
+        let mut report = SweepReport::default();
+        for (epoch, path) in &artifacts.pid_files {
+            let outcome = reap_epoch_pid_file(path, &self.path).await;
+            if let Err(error) = &outcome {
+                tracing::warn!("failed to reap epoch {epoch}: {error}");
+            }
+            report.reaped.insert(*epoch, outcome);
+        }
+        for (epoch, path) in &artifacts.intent_files {
+            let outcome = super::intent::recover_intent(path).await;
+            if let Err(error) = &outcome {
+                tracing::warn!("failed to recover spawn intent of epoch {epoch}: {error}");
+            }
+            report.recovered_intents.insert(*epoch, outcome);
+        }
+        for (epoch, path) in &artifacts.runtime_configs {
+            let unreaped = report.reaped.get(epoch).is_some_and(Result::is_err)
+                || report
+                    .recovered_intents
+                    .get(epoch)
+                    .is_some_and(Result::is_err);
+            if unreaped {
+                continue;
+            }
+            match crate::io::atomic_fs::remove_regular_file(path).await {
+                Ok(()) => report.removed_configs.push(path.clone()),
+                Err(error) => report.record_failed_removal(path, error),
+            }
+        }
+        for path in &artifacts.staging_files {
+            if staging_writer_alive(path) {
+                continue;
+            }
+            match crate::io::atomic_fs::remove_regular_file(path).await {
+                Ok(()) => report.removed_staging_files.push(path.clone()),
+                Err(error) => report.record_failed_removal(path, error),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Allocates an epoch greater than every epoch allocated before and every
+    /// epoch that still has an artifact in the directory.
+    pub async fn next_epoch(&self) -> std::io::Result<u64> {
+        let _lock = self
+            .lock(DirLockOptions::exclusive().purpose("next-epoch"))
+            .await?;
+        let counter_path = self.path.join(EPOCH_FILE);
+        let last = match tokio::fs::read_to_string(&counter_path).await {
+            Ok(raw) => raw.trim().parse().map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("invalid epoch counter in {}", counter_path.display()),
+                )
+            })?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(error) => return Err(error),
+        };
+        let artifacts = self.scan().await?;
+        let seen = artifacts
+            .pid_files
+            .keys()
+            .chain(artifacts.intent_files.keys())
+            .chain(artifacts.runtime_configs.keys())
+            .copied()
+            .max()
+            .unwrap_or(0);
+        let epoch = last.max(seen).checked_add(1).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "epoch counter overflow")
+        })?;
+        crate::io::atomic_fs::write_atomic(
+            &counter_path,
+            epoch.to_string(),
+            crate::io::atomic_fs::AtomicWriteOptions::new(),
+        )
+        .await
+        .map_err(into_io)?;
+        Ok(epoch)
+    }
+
+    async fn lock(&self, options: DirLockOptions) -> std::io::Result<DirLock> {
+        acquire_with_timeout(self.path.join(LOCK_FILE), &options, LOCK_TIMEOUT)
+            .await
+            .map_err(into_io)
+    }
+}
+
+/// A file the directory manages, whose `.tmp-` siblings are its staging
+/// files.
+fn is_managed_name(name: &str) -> bool {
+    let path = Path::new(name);
+    name == EPOCH_FILE
+        || epoch_from_file_name(path, "core-", ".pid").is_ok()
+        || epoch_from_file_name(path, "core-", ".intent").is_ok()
+        || epoch_from_file_name(path, "config-", ".yaml").is_ok()
+}
+
+/// Staging files are named `{managed name}.tmp-{writer pid}-{counter}`, such
+/// as `core-{epoch}.pid.tmp-42-0`. A name that does not parse is
+/// kept, as is one whose writer is still running (including this process,
+/// which may be publishing right now).
+fn staging_writer_alive(path: &Path) -> bool {
+    let writer = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split_once(".tmp-"))
+        .and_then(|(_, suffix)| suffix.split('-').next())
+        .and_then(|pid| pid.parse::<u32>().ok());
+    match writer {
+        Some(pid) => pid == std::process::id() || pid_is_running(pid),
+        None => true,
+    }
+}
+
+/// A process that cannot be inspected counts as running.
+#[cfg(unix)]
+fn pid_is_running(pid: u32) -> bool {
+    match crate::os::PidHandle::open(pid) {
+        Ok(handle) => handle.is_alive().unwrap_or(true),
+        Err(error) => error.kind() != std::io::ErrorKind::NotFound,
+    }
+}
+
+/// A process that cannot be inspected counts as running.
+#[cfg(not(unix))]
+fn pid_is_running(pid: u32) -> bool {
+    !matches!(super::pid_file::ProcessIdentity::of(pid), Ok(None))
+}
+
+fn into_io(error: AtomicFsError) -> std::io::Error {
+    match error {
+        AtomicFsError::Io(error) => error,
+        AtomicFsError::Contended(path) => std::io::Error::new(
+            std::io::ErrorKind::WouldBlock,
+            format!("runtime directory is locked: {}", path.display()),
+        ),
+        AtomicFsError::UnsafePath(path) => std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("unsafe runtime artifact: {}", path.display()),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn artifacts_are_classified_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "core-3.pid",
+            "config-3.yaml",
+            "config-4.yaml",
+            "core-5.pid.tmp-42-0",
+            "config-4.yaml.tmp-42-2",
+            "epoch.tmp-42-1",
+            "core-6.intent",
+            "core-x.pid",
+            "notes.txt",
+            "notes.txt.tmp-42-3",
+        ] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let runtime = RuntimeDir::open(dir.path()).await.unwrap();
+        let artifacts = runtime.artifacts().await.unwrap();
+        assert_eq!(artifacts.pid_files.keys().collect::<Vec<_>>(), [&3]);
+        assert_eq!(artifacts.intent_files.keys().collect::<Vec<_>>(), [&6]);
+        assert_eq!(
+            artifacts.runtime_configs.keys().collect::<Vec<_>>(),
+            [&3, &4]
+        );
+        assert_eq!(
+            artifacts.staging_files,
+            [
+                runtime.path().join("config-4.yaml.tmp-42-2"),
+                runtime.path().join("core-5.pid.tmp-42-0"),
+                runtime.path().join("epoch.tmp-42-1"),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sweep_records_failed_removals_and_carries_on() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("elsewhere.yaml");
+        std::fs::write(&target, "").unwrap();
+        let planted = dir.path().join("config-9.yaml");
+        std::os::unix::fs::symlink(&target, &planted).unwrap();
+        let staging = dir.path().join("core-1.pid.tmp-4294967295-0");
+        std::fs::write(&staging, "").unwrap();
+
+        let runtime = RuntimeDir::open(dir.path()).await.unwrap();
+        let manager = ManagerLock::acquire(dir.path().join("manager.lock"))
+            .await
+            .unwrap();
+        let report = runtime.sweep(&manager).await.unwrap();
+        let failed = report
+            .failed_removals
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(failed, [runtime.path().join("config-9.yaml")]);
+        assert_eq!(
+            report.removed_staging_files,
+            [runtime.path().join("core-1.pid.tmp-4294967295-0")]
+        );
+        assert!(target.exists());
+    }
+
+    #[test]
+    fn staging_writer_is_parsed_from_the_name() {
+        let own = format!("core-1.pid.tmp-{}-0", std::process::id());
+        assert!(staging_writer_alive(Path::new(&own)));
+        assert!(staging_writer_alive(Path::new("core-1.pid.tmp-garbage")));
+        assert!(!staging_writer_alive(Path::new(
+            "core-1.pid.tmp-4294967295-0"
+        )));
+        assert!(!staging_writer_alive(Path::new(
+            "core-1.intent.tmp-4294967295-0"
+        )));
+    }
+}
@@ -0,0 +1,416 @@
+//! Linux hardening for cores that parse untrusted configuration.
+//!
+//! Every option fails closed: [`prepare`] checks in the parent that the kernel
+//! and the manager's privileges allow it and returns
+//! [`ProcessError::SandboxUnavailable`] otherwise, and a step that still
+//! fails in the child aborts the spawn instead of running the core unconfined.
+
+use std::{
+    ffi::CString,
+    io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use super::error::ProcessError;
+
+/// Hardening applied to the child right before `exec`. See
+/// [`crate::process::Command::sandbox`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sandbox {
+    pub(crate) no_new_privs: bool,
+    pub(crate) mounts: Option<MountIsolation>,
+    pub(crate) seccomp: Option<SeccompPreset>,
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `PR_SET_NO_NEW_PRIVS`, so setuid binaries and file capabilities
+    /// cannot raise the child's privileges. Ambient capabilities still apply.
+    pub fn no_new_privs(mut self, enabled: bool) -> Self {
+        self.no_new_privs = enabled;
+        self
+    }
+
+    /// Runs the child in a private mount namespace. Needs `CAP_SYS_ADMIN`.
+    pub fn mount_namespace(mut self, mounts: MountIsolation) -> Self {
+        self.mounts = Some(mounts);
+        self
+    }
+
+    /// Installs a seccomp filter. Implies [`Sandbox::no_new_privs`], which
+    /// the kernel requires for unprivileged filters.
+    pub fn seccomp(mut self, preset: SeccompPreset) -> Self {
+        self.seccomp = Some(preset);
+        self
+    }
+}
+
+/// The filesystem view inside the child's private mount namespace.
+///
+/// Every mount the child inherits is made read-only, then the listed paths
+/// are bind-mounted on top. Read-only binds are applied first, so a
+/// read-write path nested inside a read-only one stays writable. A read-write
+/// bind covers the mount at that path only; mounts below it stay read-only.
+/// Needs Linux 5.12 for the recursive read-only remount.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MountIsolation {
+    pub(crate) read_only: Vec<PathBuf>,
+    pub(crate) read_write: Vec<PathBuf>,
+}
+
+impl MountIsolation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The usual core layout: the binary read-only and the app directory
+    /// (config, cache, logs) read-write.
+    pub fn for_core(binary: impl Into<PathBuf>, app_dir: impl Into<PathBuf>) -> Self {
+        Self::new().read_only(binary).read_write(app_dir)
+    }
+
+    pub fn read_only(mut self, path: impl Into<PathBuf>) -> Self {
+        self.read_only.push(path.into());
+        self
+    }
+
+    pub fn read_write(mut self, path: impl Into<PathBuf>) -> Self {
+        self.read_write.push(path.into());
+        self
+    }
+}
+
+/// Seccomp filters shipped with the crate.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeccompPreset {
+    /// Allows everything except debugging other processes (`ptrace`,
+    /// `process_vm_readv`/`writev`), loading kernels (`kexec_*`) and kernel
+    /// modules (`*init_module`, `delete_module`), which fail with `EPERM`.
+    /// Syscalls from a foreign ABI (e.g. 32-bit on a 64-bit kernel) fail too.
+    Permissive,
+}
+
+/// A [`Sandbox`] resolved before fork: paths as C strings, the filter built.
+pub(crate) struct PreparedSandbox {
+    no_new_privs: bool,
+    mounts: Option<(Vec<CString>, Vec<CString>)>,
+    filter: Option<Vec<libc::sock_filter>>,
+}
+
+const CAP_SYS_ADMIN: u32 = 21;
+
+/// `mount_setattr` shares one number across architectures.
+const SYS_MOUNT_SETATTR: libc::c_long = 442;
+const AT_RECURSIVE: libc::c_uint = 0x8000;
+const MOUNT_ATTR_RDONLY: u64 = 0x1;
+
+/// `struct mount_attr` from `linux/mount.h`.
+#[repr(C)]
+struct MountAttr {
+    attr_set: u64,
+    attr_clr: u64,
+    propagation: u64,
+    userns_fd: u64,
+}
+
+pub(crate) fn prepare(sandbox: &Sandbox) -> Result<PreparedSandbox, ProcessError> {
+    let unavailable = |feature: &'static str, reason: String| ProcessError::SandboxUnavailable {
+        feature,
+        reason,
+    };
+
+    let mounts = match &sandbox.mounts {
+        Some(mounts) => {
+            let state = crate::os::caps::current()?;
+            if state.effective.bits() & (1 << CAP_SYS_ADMIN) == 0 {
+                return Err(unavailable(
+                    "mount namespace",
+                    "the manager lacks CAP_SYS_ADMIN".into(),
+                ));
+            }
+            // An invalid call that only fails with ENOSYS when the syscall
+            // itself is missing.
+            // SAFETY: no pointers are dereferenced for a bad descriptor.
+            let rc = unsafe {
+                libc::syscall(
+                    SYS_MOUNT_SETATTR,
+                    -1,
+                    std::ptr::null::<libc::c_char>(),
+                    0,
+                    std::ptr::null::<MountAttr>(),
+                    0,
+                )
+            };
+            if rc == -1 && io::Error::last_os_error().raw_os_error() == Some(libc::ENOSYS) {
+                return Err(unavailable(
+                    "mount namespace",
+                    "the kernel lacks mount_setattr (Linux 5.12+)".into(),
+                ));
+            }
+            let to_cstrings = |paths: &[PathBuf]| -> Result<Vec<CString>, ProcessError> {
+                paths.iter().map(|path| path_cstring(path)).collect()
+            };
+            Some((
+                to_cstrings(&mounts.read_only)?,
+                to_cstrings(&mounts.read_write)?,
+            ))
+        }
+        None => None,
+    };
+
+    let filter = match sandbox.seccomp {
+        Some(preset) => {
+            // SAFETY: PR_GET_SECCOMP takes no arguments.
+            if unsafe { libc::prctl(libc::PR_GET_SECCOMP) } == -1 {
+                return Err(unavailable(
+                    "seccomp",
+                    format!("kernel support missing: {}", io::Error::last_os_error()),
+                ));
+            }
+            Some(build_filter(preset).ok_or_else(|| {
+                unavailable(
+                    "seccomp",
+                    format!("no filter for {}", std::env::consts::ARCH),
+                )
+            })?)
+        }
+        None => None,
+    };
+
+    Ok(PreparedSandbox {
+        no_new_privs: sandbox.no_new_privs || filter.is_some(),
+        mounts,
+        filter,
+    })
+}
+
+fn path_cstring(path: &Path) -> Result<CString, ProcessError> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("sandbox path contains a NUL byte: {}", path.display()),
+        )
+        .into()
+    })
+}
+
+impl PreparedSandbox {
+    /// Mount isolation needs `CAP_SYS_ADMIN`, so it must run before the
+    /// child drops privileges.
+    pub fn apply_mounts(&self) -> io::Result<()> {
+        let Some((read_only, read_write)) = &self.mounts else {
+            return Ok(());
+        };
+        // SAFETY: unshare, mount and mount_setattr receive C strings and an
+        // attribute struct prepared before fork or on the stack.
+        unsafe {
+            check(libc::unshare(libc::CLONE_NEWNS))?;
+            // Keep our binds from propagating back into the host namespace.
+            check(libc::mount(
+                std::ptr::null(),
+                c"/".as_ptr(),
+                std::ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                std::ptr::null(),
+            ))?;
+            // Only this namespace's copies of the mounts turn read-only.
+            let attr = MountAttr {
+                attr_set: MOUNT_ATTR_RDONLY,
+                attr_clr: 0,
+                propagation: 0,
+                userns_fd: 0,
+            };
+            if libc::syscall(
+                SYS_MOUNT_SETATTR,
+                libc::AT_FDCWD,
+                c"/".as_ptr(),
+                AT_RECURSIVE,
+                &attr as *const MountAttr,
+                std::mem::size_of::<MountAttr>(),
+            ) == -1
+            {
+                return Err(io::Error::last_os_error());
+            }
+            for (paths, read_only) in [(read_only, true), (read_write, false)] {
+                for path in paths {
+                    check(libc::mount(
+                        path.as_ptr(),
+                        path.as_ptr(),
+                        std::ptr::null(),
+                        libc::MS_BIND | libc::MS_REC,
+                        std::ptr::null(),
+                    ))?;
+                    let mut flags = libc::MS_BIND | libc::MS_REMOUNT | libc::MS_NOSUID;
+                    if read_only {
+                        flags |= libc::MS_RDONLY;
+                    }
+                    check(libc::mount(
+                        std::ptr::null(),
+                        path.as_ptr(),
+                        std::ptr::null(),
+                        flags,
+                        std::ptr::null(),
+                    ))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The last step before `exec`, after credentials have been changed.
+    pub fn apply_restrictions(&self) -> io::Result<()> {
+        let one: libc::c_ulong = 1;
+        let zero: libc::c_ulong = 0;
+        if self.no_new_privs {
+            // SAFETY: PR_SET_NO_NEW_PRIVS takes plain integer arguments.
+            check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, one, zero, zero, zero) })?;
+        }
+        if let Some(filter) = &self.filter {
+            let program = libc::sock_fprog {
+                len: filter.len() as libc::c_ushort,
+                filter: filter.as_ptr().cast_mut(),
+            };
+            let mode = libc::SECCOMP_MODE_FILTER as libc::c_ulong;
+            // SAFETY: the kernel copies the program during the call; it
+            // points into a filter that outlives it.
+            check(unsafe {
+                libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    mode,
+                    &program as *const libc::sock_fprog,
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+fn check(rc: libc::c_int) -> io::Result<()> {
+    if rc == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xC000_003E;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xC000_00B7;
+#[cfg(target_arch = "riscv64")]
+const AUDIT_ARCH: u32 = 0xC000_00F3;
+
+/// Offsets into `struct seccomp_data`.
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+
+// Classic BPF opcodes from `linux/bpf_common.h`, pre-combined.
+/// `BPF_LD | BPF_W | BPF_ABS`
+const BPF_LD_W_ABS: u16 = 0x20;
+/// `BPF_JMP | BPF_JEQ | BPF_K`
+const BPF_JMP_JEQ_K: u16 = 0x15;
+/// `BPF_JMP | BPF_JGE | BPF_K`
+const BPF_JMP_JGE_K: u16 = 0x35;
+/// `BPF_RET | BPF_K`
+const BPF_RET_K: u16 = 0x06;
+
+fn stmt(code: u16, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
+
+#[cfg(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+))]
+fn build_filter(preset: SeccompPreset) -> Option<Vec<libc::sock_filter>> {
+    let blocked: &[libc::c_long] = match preset {
+        SeccompPreset::Permissive => &[
+            libc::SYS_ptrace,
+            libc::SYS_process_vm_readv,
+            libc::SYS_process_vm_writev,
+            libc::SYS_kexec_load,
+            libc::SYS_kexec_file_load,
+            libc::SYS_init_module,
+            libc::SYS_finit_module,
+            libc::SYS_delete_module,
+        ],
+    };
+    let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+
+    let mut filter = vec![
+        stmt(BPF_LD_W_ABS, SECCOMP_DATA_ARCH),
+        jump(BPF_JMP_JEQ_K, AUDIT_ARCH, 1, 0),
+        stmt(BPF_RET_K, deny),
+        stmt(BPF_LD_W_ABS, SECCOMP_DATA_NR),
+    ];
+    // x32 shares the x86_64 audit arch and is told apart by this bit.
+    #[cfg(target_arch = "x86_64")]
+    filter.extend([
+        jump(BPF_JMP_JGE_K, 0x4000_0000, 0, 1),
+        stmt(BPF_RET_K, deny),
+    ]);
+    for &nr in blocked {
+        filter.push(jump(BPF_JMP_JEQ_K, nr as u32, 0, 1));
+        filter.push(stmt(BPF_RET_K, deny));
+    }
+    filter.push(stmt(BPF_RET_K, libc::SECCOMP_RET_ALLOW));
+    Some(filter)
+}
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+fn build_filter(_preset: SeccompPreset) -> Option<Vec<libc::sock_filter>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seccomp_implies_no_new_privs() {
+        let prepared = prepare(&Sandbox::new().seccomp(SeccompPreset::Permissive)).unwrap();
+        assert!(prepared.no_new_privs);
+        let filter = prepared.filter.unwrap();
+        // Ends in the permissive default.
+        assert_eq!(filter.last().unwrap().k, libc::SECCOMP_RET_ALLOW);
+        assert!(filter.iter().any(|insn| insn.k == libc::SYS_ptrace as u32));
+    }
+
+    #[test]
+    fn mount_namespace_without_sys_admin_is_unavailable() {
+        let state = crate::os::caps::current().unwrap();
+        if state.effective.bits() & (1 << CAP_SYS_ADMIN) != 0 {
+            return;
+        }
+        let error =
+            prepare(&Sandbox::new().mount_namespace(MountIsolation::for_core("/bin/true", "/tmp")))
+                .err()
+                .unwrap();
+        assert!(matches!(
+            error,
+            ProcessError::SandboxUnavailable {
+                feature: "mount namespace",
+                ..
+            }
+        ));
+    }
+}
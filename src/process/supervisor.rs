@@ -348,6 +348,7 @@ impl SupervisorBuilder {
                         }
                         ReadinessProbe::Acknowledged => (tokio::time::Instant::now(), true),
                     };
+                    let started_at = tokio::time::Instant::now();
                     let mut readiness_pending = true;
                     let mut cancelled = false;
                     let mut kill_task = None;
@@ -407,6 +408,8 @@ impl SupervisorBuilder {
                                 None => break TerminatedPayload {
                                     code: None,
                                     signal: None,
+                                    reason: None,
+                                    runtime: started_at.elapsed(),
                                 },
                             },
                         }
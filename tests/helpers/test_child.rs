@@ -71,6 +71,103 @@ async fn main() {
             let bytes = [0xD6u8, 0xD0, 0xCE, 0xC4, b'\n'];
             std::io::stdout().write_all(&bytes).expect("write gbk");
         }
+        "binary-stdout" => {
+            // Invalid UTF-8 and an embedded NUL, plus newline
+            let bytes = [0xFFu8, 0x00, 0xFE, 0x80, b'\n'];
+            std::io::stdout().write_all(&bytes).expect("write binary");
+        }
+        "env-dump" => {
+            let mut vars: Vec<_> = std::env::vars_os()
+                .map(|(key, value)| {
+                    format!("{}={}", key.to_string_lossy(), value.to_string_lossy())
+                })
+                .collect();
+            vars.sort();
+            for var in vars {
+                println!("{var}");
+            }
+        }
+        "proc-status" => {
+            // Credential, capability and sandbox state as the kernel sees it.
+            let status = std::fs::read_to_string("/proc/self/status").expect("proc status");
+            for line in status.lines() {
+                if [
+                    "Uid:",
+                    "Gid:",
+                    "Groups:",
+                    "CapEff:",
+                    "CapAmb:",
+                    "NoNewPrivs:",
+                    "Seccomp:",
+                ]
+                .iter()
+                .any(|key| line.starts_with(key))
+                {
+                    println!("{line}");
+                }
+            }
+        }
+        "session-ids" => {
+            // Fields 1, 5 and 6 of /proc/self/stat; the command name may
+            // contain spaces, so split after its closing parenthesis.
+            let stat = std::fs::read_to_string("/proc/self/stat").expect("proc stat");
+            let (pid, rest) = stat.split_once(" (").expect("pid");
+            let fields: Vec<_> = rest.rsplit_once(") ").expect("comm").1.split(' ').collect();
+            println!("pid:{pid} pgid:{} sid:{}", fields[2], fields[3]);
+        }
+        "inspect-fds" => {
+            // The kind of each descriptor given as an argument, then the
+            // socket-activation variables as sd_listen_fds sees them.
+            #[cfg(unix)]
+            for fd in args {
+                use std::os::{fd::FromRawFd, unix::fs::FileTypeExt};
+                let fd: i32 = fd.parse().expect("fd");
+                // SAFETY: the file is forgotten below, so the fd stays open.
+                let file = unsafe { std::fs::File::from_raw_fd(fd) };
+                let kind = match file.metadata() {
+                    Ok(meta) if meta.file_type().is_socket() => "socket",
+                    Ok(_) => "other",
+                    Err(_) => "closed",
+                };
+                std::mem::forget(file);
+                println!("fd{fd}:{kind}");
+            }
+            let listen_fds = std::env::var("LISTEN_FDS").unwrap_or_default();
+            let pid_matches =
+                std::env::var("LISTEN_PID").ok() == Some(std::process::id().to_string());
+            println!("listen-fds:{listen_fds} pid-matches:{pid_matches}");
+        }
+        "tty-check" => {
+            use std::io::IsTerminal;
+            println!("stdout-tty:{}", std::io::stdout().is_terminal());
+            eprintln!("stderr-tty:{}", std::io::stderr().is_terminal());
+        }
+        "write-files" => {
+            // Tries to create each path and reports the raw OS error.
+            for path in args {
+                match std::fs::write(&path, b"probe") {
+                    Ok(()) => println!("{path}:ok"),
+                    Err(error) => println!("{path}:errno-{}", error.raw_os_error().unwrap_or(-1)),
+                }
+            }
+        }
+        "hold-manager-lock" => {
+            // Holds the lock without ever polling for a takeover request.
+            #[cfg(feature = "process")]
+            {
+                let path = args.next().expect("lock path");
+                let _lock = nyanpasu_utils::process::ManagerLock::acquire(&path)
+                    .await
+                    .expect("acquire manager lock");
+                println!("ready");
+                sleep_forever().await;
+            }
+            #[cfg(not(feature = "process"))]
+            {
+                eprintln!("hold-manager-lock needs the process feature");
+                std::process::exit(2);
+            }
+        }
         "echo-stdin" => {
             let mut line = String::new();
             std::io::stdin().read_line(&mut line).expect("read line");
@@ -0,0 +1,133 @@
+#![cfg(all(feature = "process", target_os = "linux"))]
+
+use nyanpasu_utils::process::{Command, ProcessError};
+
+fn child() -> &'static str {
+    env!("CARGO_BIN_EXE_nyanpasu-test-child")
+}
+
+fn is_root() -> bool {
+    nix::unistd::Uid::effective().is_root()
+}
+
+/// The first (real) id from a `/proc/self/status` line such as `Uid:\t0\t0\t0\t0`.
+fn status_field(stdout: &str, key: &str) -> String {
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix(key))
+        .unwrap_or_else(|| panic!("{key} missing from {stdout:?}"))
+        .trim()
+        .to_owned()
+}
+
+#[tokio::test]
+async fn uid_gid_and_groups_are_applied() {
+    if !is_root() {
+        eprintln!("skipping: changing credentials needs root");
+        return;
+    }
+    let out = Command::new(child())
+        .args(["proc-status"])
+        .uid(65534)
+        .gid(65534)
+        .groups([65534])
+        .output()
+        .await
+        .unwrap();
+    assert!(out.success(), "{out:?}");
+    assert!(status_field(&out.stdout, "Uid:").starts_with("65534"));
+    assert!(status_field(&out.stdout, "Gid:").starts_with("65534"));
+    assert_eq!(status_field(&out.stdout, "Groups:"), "65534");
+}
+
+#[tokio::test]
+async fn root_dropping_uid_clears_supplementary_groups() {
+    if !is_root() {
+        eprintln!("skipping: changing credentials needs root");
+        return;
+    }
+    let out = Command::new(child())
+        .args(["proc-status"])
+        .uid(65534)
+        .gid(65534)
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(status_field(&out.stdout, "Groups:"), "");
+}
+
+#[tokio::test]
+async fn naming_the_managers_own_user_needs_no_privileges() {
+    let uid = nix::unistd::Uid::current();
+    let Some(user) = nix::unistd::User::from_uid(uid).unwrap() else {
+        eprintln!("skipping: the current uid has no passwd entry");
+        return;
+    };
+    let out = Command::new(child())
+        .args(["proc-status"])
+        .user(user.name)
+        .output()
+        .await
+        .unwrap();
+    assert!(out.success(), "{out:?}");
+    assert!(status_field(&out.stdout, "Uid:").starts_with(&uid.to_string()));
+}
+
+#[tokio::test]
+async fn unknown_user_fails_before_spawn() {
+    let error = Command::new(child())
+        .args(["exit-with", "0"])
+        .user("nyanpasu-no-such-user")
+        .spawn()
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(error, ProcessError::UnknownUser { .. }));
+}
+
+#[tokio::test]
+async fn ambient_caps_survive_dropping_root() {
+    use nyanpasu_utils::os::caps::Capability;
+
+    if !is_root() {
+        eprintln!("skipping: changing credentials needs root");
+        return;
+    }
+    let out = Command::new(child())
+        .args(["proc-status"])
+        .uid(65534)
+        .gid(65534)
+        .ambient_caps([Capability::NetBindService, Capability::NetAdmin])
+        .output()
+        .await
+        .unwrap();
+    assert!(out.success(), "{out:?}");
+    assert!(status_field(&out.stdout, "Uid:").starts_with("65534"));
+    // Bits 10 and 12.
+    assert_eq!(status_field(&out.stdout, "CapAmb:"), "0000000000001400");
+    assert_eq!(status_field(&out.stdout, "CapEff:"), "0000000000001400");
+}
+
+#[tokio::test]
+async fn ambient_caps_outside_permitted_set_are_named() {
+    use nyanpasu_utils::os::caps::{self, Capability};
+
+    let state = caps::current().unwrap();
+    if state.permitted.contains(Capability::NetRaw) {
+        eprintln!("skipping: manager already holds CAP_NET_RAW");
+        return;
+    }
+    let error = Command::new(child())
+        .args(["exit-with", "0"])
+        .ambient_caps([Capability::NetRaw])
+        .spawn()
+        .await
+        .err()
+        .unwrap();
+    match error {
+        ProcessError::MissingCapabilities { missing } => {
+            assert_eq!(missing, [Capability::NetRaw]);
+        }
+        other => panic!("unexpected error: {other}"),
+    }
+}
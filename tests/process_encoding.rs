@@ -23,3 +23,39 @@ async fn gbk_stdout_decodes_correctly() {
     }
     assert_eq!(decoded.unwrap().trim(), "中文");
 }
+
+#[cfg(unix)]
+#[tokio::test]
+async fn raw_output_delivers_undecoded_bytes() {
+    let (_handle, mut rx) = Command::new(child())
+        .args(["binary-stdout"])
+        .raw_output(true)
+        .spawn()
+        .await
+        .unwrap();
+    let mut raw = Vec::new();
+    while let Some(e) = rx.recv().await {
+        match e {
+            ProcessEvent::StdoutBytes(chunk) => raw.extend_from_slice(&chunk),
+            ProcessEvent::Stdout(line) => panic!("raw mode decoded stdout: {line:?}"),
+            ProcessEvent::Terminated(_) => break,
+            _ => {}
+        }
+    }
+    // Chunks concatenate to the exact stream, terminator included.
+    assert_eq!(raw, [0xFF, 0x00, 0xFE, 0x80, b'\n']);
+}
+
+#[cfg(windows)]
+#[tokio::test]
+async fn raw_output_streaming_is_rejected() {
+    let result = Command::new(child())
+        .args(["binary-stdout"])
+        .raw_output(true)
+        .spawn()
+        .await;
+    assert!(matches!(
+        result,
+        Err(nyanpasu_utils::process::ProcessError::RawOutputUnsupported)
+    ));
+}
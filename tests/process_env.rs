@@ -0,0 +1,58 @@
+#![cfg(feature = "process")]
+
+use nyanpasu_utils::process::Command;
+
+fn child() -> &'static str {
+    env!("CARGO_BIN_EXE_nyanpasu-test-child")
+}
+
+async fn env_dump(cmd: Command) -> Vec<String> {
+    let out = cmd.args(["env-dump"]).output().await.unwrap();
+    assert!(out.success());
+    out.stdout.lines().map(str::to_owned).collect()
+}
+
+fn keys(vars: &[String]) -> Vec<&str> {
+    vars.iter()
+        .map(|var| var.split_once('=').map_or(var.as_str(), |(key, _)| key))
+        .collect()
+}
+
+#[tokio::test]
+async fn env_clear_leaves_only_explicit_variables() {
+    let vars = env_dump(
+        Command::new(child())
+            .env("DROPPED", "x")
+            .env_clear()
+            .envs([("A", "1"), ("B", "2")]),
+    )
+    .await;
+    assert_eq!(vars, ["A=1", "B=2"]);
+}
+
+#[tokio::test]
+async fn env_remove_hides_inherited_and_explicit_variables() {
+    let vars = env_dump(
+        Command::new(child())
+            .env("EXPLICIT", "1")
+            .env_remove("EXPLICIT")
+            .env_remove("PATH"),
+    )
+    .await;
+    let keys = keys(&vars);
+    assert!(!keys.contains(&"EXPLICIT"), "{keys:?}");
+    assert!(!keys.contains(&"PATH"), "{keys:?}");
+    assert!(!keys.is_empty(), "the rest of the environment is inherited");
+}
+
+#[tokio::test]
+async fn inherit_env_only_filters_the_manager_environment() {
+    let path = std::env::var("PATH").expect("tests run with PATH set");
+    let vars = env_dump(
+        Command::new(child())
+            .inherit_env_only(["PATH", "NYANPASU_DEFINITELY_UNSET"])
+            .env("EXTRA", "1"),
+    )
+    .await;
+    assert_eq!(vars, ["EXTRA=1".to_owned(), format!("PATH={path}")]);
+}
@@ -0,0 +1,72 @@
+#![cfg(all(feature = "process", unix))]
+
+use std::{net::TcpListener, os::fd::OwnedFd};
+
+use nyanpasu_utils::process::{Command, ProcessError};
+
+fn child() -> &'static str {
+    env!("CARGO_BIN_EXE_nyanpasu-test-child")
+}
+
+fn listener() -> OwnedFd {
+    TcpListener::bind("127.0.0.1:0").unwrap().into()
+}
+
+#[tokio::test]
+async fn passed_fd_appears_at_its_target() {
+    let out = Command::new(child())
+        .args(["inspect-fds", "7", "8"])
+        .env_remove("LISTEN_FDS")
+        .pass_fd(listener(), 7)
+        .output()
+        .await
+        .unwrap();
+    assert!(out.success(), "{out:?}");
+    assert_eq!(
+        out.stdout,
+        "fd7:socket\nfd8:closed\nlisten-fds: pid-matches:false\n"
+    );
+}
+
+#[tokio::test]
+async fn listen_fds_follow_socket_activation() {
+    let out = Command::new(child())
+        .args(["inspect-fds", "3", "4"])
+        .env("KEPT", "1")
+        .listen_fds([listener(), listener()])
+        .output()
+        .await
+        .unwrap();
+    assert!(out.success(), "{out:?}");
+    assert_eq!(
+        out.stdout,
+        "fd3:socket\nfd4:socket\nlisten-fds:2 pid-matches:true\n"
+    );
+
+    let out = Command::new(child())
+        .args(["env-dump"])
+        .env_clear()
+        .env("KEPT", "1")
+        .listen_fds([listener()])
+        .output()
+        .await
+        .unwrap();
+    let vars: Vec<_> = out.stdout.lines().collect();
+    assert_eq!(vars.len(), 3, "{vars:?}");
+    assert!(vars.contains(&"KEPT=1"));
+    assert!(vars.contains(&"LISTEN_FDS=1"));
+}
+
+#[tokio::test]
+async fn stdio_targets_are_rejected() {
+    let error = Command::new(child())
+        .args(["inspect-fds"])
+        .pass_fd(listener(), 1)
+        .output()
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&error, ProcessError::Io(e) if e.kind() == std::io::ErrorKind::InvalidInput),
+        "{error:?}"
+    );
+}
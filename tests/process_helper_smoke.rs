@@ -36,3 +36,70 @@ fn gbk_stdout_emits_expected_bytes() {
         .unwrap();
     assert_eq!(&out.stdout[..4], &[0xD6, 0xD0, 0xCE, 0xC4]);
 }
+
+#[test]
+fn binary_stdout_emits_expected_bytes() {
+    let out = std::process::Command::new(child())
+        .args(["binary-stdout"])
+        .output()
+        .unwrap();
+    assert_eq!(&out.stdout[..4], &[0xFF, 0x00, 0xFE, 0x80]);
+}
+
+#[test]
+fn tty_check_reports_piped_streams() {
+    let out = std::process::Command::new(child())
+        .args(["tty-check"])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "stdout-tty:false\n");
+    assert_eq!(String::from_utf8(out.stderr).unwrap(), "stderr-tty:false\n");
+}
+
+#[test]
+fn env_dump_lists_the_child_environment() {
+    let out = std::process::Command::new(child())
+        .args(["env-dump"])
+        .env_clear()
+        .env("SMOKE_VAR", "1")
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "SMOKE_VAR=1\n");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn proc_status_reports_credentials() {
+    let out = std::process::Command::new(child())
+        .args(["proc-status"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.lines().any(|line| line.starts_with("Uid:")));
+    assert!(stdout.lines().any(|line| line.starts_with("CapAmb:")));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn session_ids_reports_own_pid() {
+    let child = std::process::Command::new(child())
+        .args(["session-ids"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(child.stdout).unwrap();
+    assert!(stdout.starts_with("pid:") && stdout.contains(" pgid:") && stdout.contains(" sid:"));
+}
+
+#[cfg(unix)]
+#[test]
+fn inspect_fds_reports_closed_descriptors() {
+    let out = std::process::Command::new(child())
+        .args(["inspect-fds", "200"])
+        .env_remove("LISTEN_FDS")
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "fd200:closed\nlisten-fds: pid-matches:false\n"
+    );
+}
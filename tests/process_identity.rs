@@ -0,0 +1,51 @@
+#![cfg(feature = "process")]
+
+use std::time::Duration;
+
+use nyanpasu_utils::process::{Command, ProcessEvent, ProcessIdentity};
+
+fn child() -> &'static str {
+    env!("CARGO_BIN_EXE_nyanpasu-test-child")
+}
+
+#[tokio::test]
+async fn identity_follows_the_child_until_it_exits() {
+    let (handle, mut rx) = Command::new(child())
+        .args(["sleep-forever"])
+        .spawn()
+        .await
+        .unwrap();
+    loop {
+        match rx.recv().await.unwrap() {
+            ProcessEvent::Stdout(line) if line.contains("ready") => break,
+            ProcessEvent::Terminated(_) => panic!("exited early"),
+            _ => {}
+        }
+    }
+    let identity = ProcessIdentity::of(handle.pid()).unwrap().unwrap();
+    assert_eq!(identity.pid(), handle.pid());
+    assert!(
+        identity.executable().starts_with("nyanpasu-test-child"),
+        "{identity:?}"
+    );
+    assert!(identity.matches(&ProcessIdentity::of(handle.pid()).unwrap().unwrap()));
+    assert!(identity.is_same_process_as(&handle).unwrap());
+
+    handle.kill().await.unwrap();
+    tokio::time::timeout(Duration::from_secs(10), handle.wait())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!identity.is_same_process_as(&handle).unwrap());
+}
+
+#[cfg(unix)]
+#[test]
+fn own_identity_differs_from_another_pid() {
+    let own = ProcessIdentity::of(std::process::id()).unwrap().unwrap();
+    assert!(own.matches(&own.clone()));
+    let parent = ProcessIdentity::of(std::os::unix::process::parent_id());
+    if let Ok(Some(parent)) = parent {
+        assert!(!own.matches(&parent));
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::time::Duration;
 
-use nyanpasu_utils::process::{Command, ProcessEvent};
+use nyanpasu_utils::process::{Command, ExitReason, ProcessEvent};
 
 fn child() -> &'static str {
     env!("CARGO_BIN_EXE_nyanpasu-test-child")
@@ -34,6 +34,7 @@ async fn kill_terminates_and_wait_returns() {
     handle.kill().await.unwrap();
     let payload = handle.wait().await.unwrap();
     assert_ne!(payload.code, Some(0)); // hard kill is never a clean exit
+    assert_eq!(payload.reason, Some(ExitReason::KilledByHandle));
     // killing again is idempotent-Ok
     handle.kill().await.unwrap();
 }
@@ -55,7 +56,9 @@ async fn graceful_kill_delivers_sigterm_first() {
     }
     handle.graceful_kill().await.unwrap();
     // trap-term exits 0 on SIGTERM -> proves the graceful tier was delivered
-    assert_eq!(handle.wait().await.unwrap().code, Some(0));
+    let payload = handle.wait().await.unwrap();
+    assert_eq!(payload.code, Some(0));
+    assert_eq!(payload.reason, Some(ExitReason::GracefullyStopped));
 }
 
 #[cfg(windows)]
@@ -98,3 +101,33 @@ async fn whole_tree_is_reaped() {
     tokio::time::sleep(Duration::from_millis(500)).await;
     assert!(!pid_alive(grandchild_pid), "grandchild survived tree kill");
 }
+
+#[cfg(unix)]
+#[tokio::test]
+async fn validated_kill_only_kills_matching_processes() {
+    use nyanpasu_utils::os::{KillOutcome, ProcessMatcher, kill_pid_validated};
+
+    let mut child = std::process::Command::new(child())
+        .arg("sleep-forever")
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let pid = child.id();
+    let outcome = kill_pid_validated(pid, &ProcessMatcher::new().name("not-nyanpasu-test-child"))
+        .await
+        .unwrap();
+    assert!(
+        matches!(outcome, KillOutcome::Mismatched { .. }),
+        "{outcome:?}"
+    );
+
+    let KillOutcome::Killed(killed) =
+        kill_pid_validated(pid, &ProcessMatcher::new().name("nyanpasu-test-child"))
+            .await
+            .unwrap()
+    else {
+        panic!("the test child was not killed");
+    };
+    assert_eq!(killed.last().map(|process| process.pid), Some(pid));
+    child.wait().unwrap();
+}
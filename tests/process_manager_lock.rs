@@ -0,0 +1,113 @@
+#![cfg(feature = "process")]
+
+use std::time::Duration;
+
+use nyanpasu_utils::process::{ManagerLock, ManagerLockError};
+
+#[tokio::test]
+async fn contended_lock_reports_the_holder() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("manager.lock");
+    let lock = ManagerLock::acquire(&path).await.unwrap();
+    let error = ManagerLock::acquire(&path).await.unwrap_err();
+    let ManagerLockError::Held {
+        holder: Some(holder),
+    } = &error
+    else {
+        panic!("unexpected error: {error:?}");
+    };
+    assert_eq!(holder.pid(), std::process::id());
+    assert_eq!(
+        error.to_string(),
+        format!("another instance (pid {}) is running", std::process::id())
+    );
+
+    drop(lock);
+    assert!(ManagerLock::holder(&path).await.unwrap().is_none());
+    ManagerLock::acquire(&path).await.unwrap();
+}
+
+#[tokio::test]
+async fn takeover_waits_for_the_holder_to_shut_down() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("manager.lock");
+    let lock = ManagerLock::acquire(&path).await.unwrap();
+    let holder = tokio::spawn(async move {
+        lock.takeover_requested().await;
+        drop(lock);
+    });
+
+    let successor = ManagerLock::take_over(&path, Duration::from_secs(10))
+        .await
+        .unwrap();
+    holder.await.unwrap();
+    assert_eq!(
+        ManagerLock::holder(successor.path())
+            .await
+            .unwrap()
+            .map(|holder| holder.pid()),
+        Some(std::process::id())
+    );
+    // The answered request does not reach the new holder.
+    assert!(
+        tokio::time::timeout(Duration::from_millis(300), successor.takeover_requested())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn takeover_times_out_when_the_holder_stays() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("manager.lock");
+    let lock = ManagerLock::acquire(&path).await.unwrap();
+    let error = ManagerLock::take_over(&path, Duration::from_millis(300))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            error,
+            ManagerLockError::TakeoverTimedOut { holder: Some(_) }
+        ),
+        "{error:?}"
+    );
+    // The abandoned request is withdrawn, so the holder keeps running.
+    assert!(
+        tokio::time::timeout(Duration::from_millis(300), lock.takeover_requested())
+            .await
+            .is_err()
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn takeover_terminates_a_holder_that_never_polls() {
+    use std::io::BufRead;
+    use std::os::unix::process::ExitStatusExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("manager.lock");
+    let mut holder = std::process::Command::new(env!("CARGO_BIN_EXE_nyanpasu-test-child"))
+        .arg("hold-manager-lock")
+        .arg(&path)
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut ready = String::new();
+    std::io::BufReader::new(holder.stdout.take().unwrap())
+        .read_line(&mut ready)
+        .unwrap();
+    assert_eq!(ready.trim(), "ready");
+
+    let successor = ManagerLock::take_over(&path, Duration::from_millis(300))
+        .await
+        .unwrap();
+    assert_eq!(holder.wait().unwrap().signal(), Some(15));
+    assert_eq!(
+        ManagerLock::holder(successor.path())
+            .await
+            .unwrap()
+            .map(|holder| holder.pid()),
+        Some(std::process::id())
+    );
+}
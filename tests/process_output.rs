@@ -3,7 +3,7 @@
 
 use std::time::Duration;
 
-use nyanpasu_utils::process::{Command, ProcessError};
+use nyanpasu_utils::process::{Command, ExitReason, OutputLimits, ProcessError, ProcessEvent};
 
 fn child() -> &'static str {
     env!("CARGO_BIN_EXE_nyanpasu-test-child")
@@ -21,6 +21,91 @@ async fn output_captures_streams_and_code() {
     assert!(out.stderr.contains("stderr-marker"));
 }
 
+#[tokio::test]
+async fn output_bytes_preserves_binary_stdout() {
+    let out = Command::new(child())
+        .args(["binary-stdout"])
+        .output_bytes()
+        .await
+        .unwrap();
+    assert!(out.success());
+    assert!(out.stdout.starts_with(&[0xFF, 0x00, 0xFE, 0x80]));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn output_reports_signal_deaths() {
+    let out = Command::new("sh")
+        .args(["-c", "kill -TERM $$"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(out.code, None);
+    assert_eq!(
+        out.reason,
+        Some(ExitReason::Signaled {
+            signal: 15,
+            core_dumped: false
+        })
+    );
+}
+
+#[tokio::test]
+async fn output_with_keeps_head_and_tail_of_large_stdout() {
+    let out = Command::new(child())
+        .args(["spam-stdout", "1000"])
+        .output_with(OutputLimits {
+            max_stdout: 256,
+            ..OutputLimits::default()
+        })
+        .await
+        .unwrap();
+    assert!(out.success());
+    assert!(out.stdout.is_truncated());
+    assert!(out.stdout.head.starts_with("line-0\n"));
+    assert!(out.stdout.tail.ends_with("line-999\n"));
+    assert!(out.stdout.head.len() + out.stdout.tail.len() <= 256);
+    assert!(!out.stderr.is_truncated());
+}
+
+#[tokio::test]
+async fn output_with_tail_only_drops_the_head() {
+    let out = Command::new(child())
+        .args(["spam-stdout", "1000"])
+        .output_with(OutputLimits {
+            max_stdout: 64,
+            tail_only: true,
+            ..OutputLimits::default()
+        })
+        .await
+        .unwrap();
+    assert!(out.stdout.head.is_empty());
+    assert!(out.stdout.tail.ends_with("line-999\n"));
+    assert!(out.stdout.omitted > 0);
+}
+
+#[tokio::test]
+async fn output_with_callback_sees_every_line() {
+    let mut lines = 0;
+    let out = Command::new(child())
+        .args(["spam-stdout", "1000"])
+        .output_with_callback(
+            OutputLimits {
+                max_stdout: 32,
+                ..OutputLimits::default()
+            },
+            |event| {
+                if matches!(event, ProcessEvent::Stdout(_)) {
+                    lines += 1;
+                }
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(lines, 1000);
+    assert!(out.stdout.is_truncated());
+}
+
 #[tokio::test]
 async fn output_nonzero_is_data_not_error() {
     let out = Command::new(child())
@@ -59,6 +59,22 @@ async fn epoch_pid_file_written_and_cleaned_up() {
     assert!(!pid_path.exists());
 }
 
+#[tokio::test]
+async fn failed_spawn_leaves_no_spawn_intent() {
+    let dir = tempfile::tempdir().unwrap();
+    let pid_path = dir.path().join("core-2.pid");
+    let runtime_path = dir.path().join("config-2.yaml");
+    std::fs::write(&runtime_path, "mixed-port: 0\n").unwrap();
+    let missing = dir.path().join("missing-core");
+    let result = Command::new(&missing)
+        .epoch_pid_file(EpochPidFile::new(&pid_path, 2, &runtime_path))
+        .spawn()
+        .await;
+    assert!(result.is_err());
+    assert!(!dir.path().join("core-2.intent").exists());
+    assert!(!pid_path.exists());
+}
+
 #[tokio::test]
 async fn residual_process_is_killed_before_spawn() {
     let dir = tempfile::tempdir().unwrap();
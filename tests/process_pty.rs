@@ -0,0 +1,89 @@
+#![cfg(all(feature = "process", unix))]
+
+use std::time::Duration;
+
+use nyanpasu_utils::process::{Command, ProcessError, ProcessEvent, PtySize};
+
+fn child() -> &'static str {
+    env!("CARGO_BIN_EXE_nyanpasu-test-child")
+}
+
+async fn stdout_lines(mut rx: tokio::sync::mpsc::Receiver<ProcessEvent>) -> Vec<String> {
+    let mut lines = Vec::new();
+    while let Some(event) = rx.recv().await {
+        if let ProcessEvent::Stdout(line) = event {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+#[tokio::test]
+async fn pty_child_sees_a_terminal_on_both_streams() {
+    let (handle, rx) = Command::new(child())
+        .args(["tty-check"])
+        .pty(PtySize::default())
+        .spawn()
+        .await
+        .unwrap();
+    let lines = tokio::time::timeout(Duration::from_secs(10), stdout_lines(rx))
+        .await
+        .expect("pty output never ended");
+    assert!(lines.contains(&"stdout-tty:true".to_owned()), "{lines:?}");
+    assert!(lines.contains(&"stderr-tty:true".to_owned()), "{lines:?}");
+    assert_eq!(handle.wait().await.unwrap().code, Some(0));
+}
+
+#[tokio::test]
+async fn pty_stdin_roundtrip_and_resize() {
+    let (handle, rx) = Command::new(child())
+        .args(["echo-stdin"])
+        .pty(PtySize::default())
+        .pipe_stdin(true)
+        .spawn()
+        .await
+        .unwrap();
+    handle
+        .resize_pty(PtySize {
+            rows: 50,
+            cols: 132,
+        })
+        .unwrap();
+    handle.write_stdin(b"ping\n").await.unwrap();
+    let lines = tokio::time::timeout(Duration::from_secs(10), stdout_lines(rx))
+        .await
+        .expect("pty output never ended");
+    // The terminal echoes input back before the child answers.
+    assert!(lines.contains(&"echo:ping".to_owned()), "{lines:?}");
+}
+
+#[tokio::test]
+async fn pty_child_is_killed_with_its_group() {
+    let (handle, rx) = Command::new(child())
+        .args(["sleep-forever"])
+        .pty(PtySize::default())
+        .spawn()
+        .await
+        .unwrap();
+    tokio::time::timeout(Duration::from_secs(10), handle.kill())
+        .await
+        .expect("kill hung")
+        .unwrap();
+    tokio::time::timeout(Duration::from_secs(10), stdout_lines(rx))
+        .await
+        .expect("pty output never ended after kill");
+}
+
+#[tokio::test]
+async fn resize_without_pty_is_error() {
+    let (handle, _rx) = Command::new(child())
+        .args(["sleep-forever"])
+        .spawn()
+        .await
+        .unwrap();
+    assert!(matches!(
+        handle.resize_pty(PtySize::default()),
+        Err(ProcessError::PtyUnavailable)
+    ));
+    handle.kill().await.unwrap();
+}
@@ -0,0 +1,138 @@
+#![cfg(feature = "process")]
+
+use nyanpasu_utils::process::{Command, ManagerLock, OrphanReapOutcome, ProcessEvent, RuntimeDir};
+
+fn child() -> &'static str {
+    env!("CARGO_BIN_EXE_nyanpasu-test-child")
+}
+
+#[tokio::test]
+async fn next_epoch_is_monotonic_across_sweeps_and_reopens() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("config-7.yaml"), "mixed-port: 0\n").unwrap();
+    let runtime = RuntimeDir::open(dir.path()).await.unwrap();
+    assert_eq!(runtime.next_epoch().await.unwrap(), 8);
+    assert_eq!(runtime.next_epoch().await.unwrap(), 9);
+
+    // The orphaned config is gone, but the counter remembers.
+    let manager = ManagerLock::acquire(dir.path().join("manager.lock"))
+        .await
+        .unwrap();
+    runtime.sweep(&manager).await.unwrap();
+    assert!(
+        runtime
+            .artifacts()
+            .await
+            .unwrap()
+            .runtime_configs
+            .is_empty()
+    );
+    let reopened = RuntimeDir::open(dir.path()).await.unwrap();
+    assert_eq!(reopened.next_epoch().await.unwrap(), 10);
+}
+
+#[tokio::test]
+async fn sweep_reaps_orphans_and_removes_leftovers() {
+    let dir = tempfile::tempdir().unwrap();
+    let runtime = RuntimeDir::open(dir.path()).await.unwrap();
+    let epoch = runtime.next_epoch().await.unwrap();
+    let pid_file = runtime.epoch_pid_file(epoch);
+    std::fs::write(pid_file.runtime_config(), "mixed-port: 0\n").unwrap();
+    let (handle, mut events) = Command::new(child())
+        .args(["sleep-forever"])
+        .epoch_pid_file(pid_file)
+        .spawn()
+        .await
+        .unwrap();
+    loop {
+        match events.recv().await.unwrap() {
+            ProcessEvent::Stdout(line) if line.contains("ready") => break,
+            ProcessEvent::Terminated(_) => panic!("exited early"),
+            _ => {}
+        }
+    }
+    let stale_config = dir.path().join("config-1000.yaml");
+    let stale_staging = dir.path().join("core-1000.pid.tmp-4294967295-0");
+    std::fs::write(&stale_config, "").unwrap();
+    std::fs::write(&stale_staging, "").unwrap();
+
+    let manager = ManagerLock::acquire(dir.path().join("manager.lock"))
+        .await
+        .unwrap();
+    let report = runtime.sweep(&manager).await.unwrap();
+    assert!(matches!(
+        report.reaped.get(&epoch),
+        Some(Ok(OrphanReapOutcome::Killed))
+    ));
+    assert!(report.removed_configs.contains(&stale_config));
+    assert_eq!(report.removed_staging_files, [stale_staging]);
+    let payload = handle.wait().await.unwrap();
+    assert_ne!(payload.code, Some(0));
+    assert_eq!(
+        runtime.artifacts().await.unwrap(),
+        Default::default(),
+        "sweep left artifacts behind"
+    );
+}
+
+#[tokio::test]
+async fn epoch_spawn_tags_the_child_and_retires_its_intent() {
+    let dir = tempfile::tempdir().unwrap();
+    let runtime = RuntimeDir::open(dir.path()).await.unwrap();
+    let pid_file = runtime.epoch_pid_file(1);
+    std::fs::write(pid_file.runtime_config(), "mixed-port: 0\n").unwrap();
+    let out = Command::new(child())
+        .args(["env-dump"])
+        .epoch_pid_file(pid_file)
+        .output()
+        .await
+        .unwrap();
+    assert!(
+        out.stdout
+            .lines()
+            .any(|var| var.starts_with("NYANPASU_SPAWN_INTENT=")),
+        "{out:?}"
+    );
+    assert!(runtime.artifacts().await.unwrap().intent_files.is_empty());
+}
+
+#[tokio::test]
+async fn sweep_kills_children_tagged_by_a_leftover_intent() {
+    let dir = tempfile::tempdir().unwrap();
+    let runtime = RuntimeDir::open(dir.path()).await.unwrap();
+    // What a manager killed right after spawn leaves behind: an intent and a
+    // tagged child, but no pid record.
+    let tag = "00c0ffee";
+    let (handle, mut events) = Command::new(child())
+        .args(["sleep-forever"])
+        .env("NYANPASU_SPAWN_INTENT", tag)
+        .spawn()
+        .await
+        .unwrap();
+    loop {
+        match events.recv().await.unwrap() {
+            ProcessEvent::Stdout(line) if line.contains("ready") => break,
+            ProcessEvent::Terminated(_) => panic!("exited early"),
+            _ => {}
+        }
+    }
+    std::fs::write(
+        dir.path().join("core-5.intent"),
+        format!(
+            "version=1\nepoch=5\nexecutable=78\nmanager-pid={}\nmanager-start-token=0\ntag={tag}\n",
+            std::process::id()
+        ),
+    )
+    .unwrap();
+
+    let manager = ManagerLock::acquire(dir.path().join("manager.lock"))
+        .await
+        .unwrap();
+    let report = runtime.sweep(&manager).await.unwrap();
+    assert!(matches!(
+        report.recovered_intents.get(&5),
+        Some(Ok(OrphanReapOutcome::Killed))
+    ));
+    assert_ne!(handle.wait().await.unwrap().code, Some(0));
+    assert!(runtime.artifacts().await.unwrap().intent_files.is_empty());
+}
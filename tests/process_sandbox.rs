@@ -0,0 +1,106 @@
+#![cfg(all(feature = "process", target_os = "linux"))]
+
+use nyanpasu_utils::process::{Command, MountIsolation, ProcessError, Sandbox, SeccompPreset};
+
+fn child() -> &'static str {
+    env!("CARGO_BIN_EXE_nyanpasu-test-child")
+}
+
+fn has_sys_admin() -> bool {
+    let state = nyanpasu_utils::os::caps::current().unwrap();
+    state.effective.bits() & (1 << 21) != 0
+}
+
+fn status_field(stdout: &str, key: &str) -> String {
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix(key))
+        .unwrap_or_else(|| panic!("{key} missing from {stdout:?}"))
+        .trim()
+        .to_owned()
+}
+
+#[tokio::test]
+async fn no_new_privs_is_set() {
+    let out = Command::new(child())
+        .args(["proc-status"])
+        .sandbox(Sandbox::new().no_new_privs(true))
+        .output()
+        .await
+        .unwrap();
+    assert!(out.success(), "{out:?}");
+    assert_eq!(status_field(&out.stdout, "NoNewPrivs:"), "1");
+}
+
+#[tokio::test]
+async fn seccomp_preset_installs_a_filter() {
+    let out = Command::new(child())
+        .args(["proc-status"])
+        .sandbox(Sandbox::new().seccomp(SeccompPreset::Permissive))
+        .output()
+        .await
+        .unwrap();
+    assert!(out.success(), "{out:?}");
+    // Mode 2 is SECCOMP_MODE_FILTER.
+    assert_eq!(status_field(&out.stdout, "Seccomp:"), "2");
+    assert_eq!(status_field(&out.stdout, "NoNewPrivs:"), "1");
+}
+
+#[tokio::test]
+async fn mount_namespace_fails_closed_without_sys_admin() {
+    if has_sys_admin() {
+        eprintln!("skipping: the test runner has CAP_SYS_ADMIN");
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let error = Command::new(child())
+        .args(["proc-status"])
+        .sandbox(Sandbox::new().mount_namespace(MountIsolation::for_core(child(), dir.path())))
+        .output()
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            error,
+            ProcessError::SandboxUnavailable {
+                feature: "mount namespace",
+                ..
+            }
+        ),
+        "{error:?}"
+    );
+}
+
+#[tokio::test]
+async fn mount_namespace_runs_the_child() {
+    if !has_sys_admin() {
+        eprintln!("skipping: mount namespaces need CAP_SYS_ADMIN");
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    let inside_file = dir.path().join("probe");
+    let outside_file = outside.path().join("probe");
+    let out = Command::new(child())
+        .arg("write-files")
+        .arg(&inside_file)
+        .arg(&outside_file)
+        .sandbox(Sandbox::new().mount_namespace(MountIsolation::for_core(child(), dir.path())))
+        .output()
+        .await
+        .unwrap();
+    assert!(out.success(), "{out:?}");
+    assert!(
+        out.stdout
+            .contains(&format!("{}:ok", inside_file.display())),
+        "{out:?}"
+    );
+    // Everything outside the app dir is read-only: 30 is EROFS.
+    assert!(
+        out.stdout
+            .contains(&format!("{}:errno-30", outside_file.display())),
+        "{out:?}"
+    );
+    // The read-only remount stays inside the child's namespace.
+    std::fs::write(outside.path().join("host"), b"ok").unwrap();
+}
@@ -0,0 +1,68 @@
+#![cfg(all(feature = "process", target_os = "linux"))]
+
+use std::time::Duration;
+
+use nyanpasu_utils::process::{Command, ProcessEvent};
+
+fn child() -> &'static str {
+    env!("CARGO_BIN_EXE_nyanpasu-test-child")
+}
+
+/// `(pid, pgid, sid)` from the `session-ids` helper line.
+fn session_ids(stdout: &str) -> (u32, u32, u32) {
+    let ids: Vec<u32> = stdout
+        .trim()
+        .split(' ')
+        .map(|field| field.split_once(':').unwrap().1.parse().unwrap())
+        .collect();
+    (ids[0], ids[1], ids[2])
+}
+
+#[tokio::test]
+async fn detached_child_leads_its_own_session_and_group() {
+    let out = Command::new(child())
+        .args(["session-ids"])
+        .detach_session(true)
+        .output()
+        .await
+        .unwrap();
+    assert!(out.success(), "{out:?}");
+    let (pid, pgid, sid) = session_ids(&out.stdout);
+    assert_eq!(sid, pid);
+    assert_eq!(pgid, pid);
+}
+
+#[tokio::test]
+async fn child_shares_the_manager_session_by_default() {
+    let out = Command::new(child())
+        .args(["session-ids"])
+        .output()
+        .await
+        .unwrap();
+    assert!(out.success(), "{out:?}");
+    let (_, _, sid) = session_ids(&out.stdout);
+    assert_eq!(sid, nix::unistd::getsid(None).unwrap().as_raw() as u32);
+}
+
+#[tokio::test]
+async fn detached_child_keeps_graceful_shutdown() {
+    let (handle, mut rx) = Command::new(child())
+        .args(["trap-term"])
+        .detach_session(true)
+        .spawn()
+        .await
+        .unwrap();
+    loop {
+        match rx.recv().await.unwrap() {
+            ProcessEvent::Stdout(line) if line.contains("ready") => break,
+            ProcessEvent::Terminated(_) => panic!("exited early"),
+            _ => {}
+        }
+    }
+    handle.graceful_kill().await.unwrap();
+    let payload = tokio::time::timeout(Duration::from_secs(10), handle.wait())
+        .await
+        .expect("child survived graceful kill")
+        .unwrap();
+    assert_eq!(payload.code, Some(0));
+}
@@ -2,7 +2,7 @@
 
 use std::time::Duration;
 
-use nyanpasu_utils::process::{Command, ProcessEvent};
+use nyanpasu_utils::process::{Command, ExitReason, ProcessEvent};
 
 fn child() -> &'static str {
     env!("CARGO_BIN_EXE_nyanpasu-test-child")
@@ -61,7 +61,22 @@ async fn nonzero_exit_code_is_reported() {
         .unwrap();
     let evs = collect_all(rx).await;
     assert!(matches!(evs.last().unwrap(), ProcessEvent::Terminated(p) if p.code == Some(3)));
-    assert_eq!(handle.wait().await.unwrap().code, Some(3));
+    let payload = handle.wait().await.unwrap();
+    assert_eq!(payload.code, Some(3));
+    assert_eq!(payload.reason, Some(ExitReason::Exited(3)));
+}
+
+#[tokio::test]
+async fn terminated_payload_reports_wall_clock_runtime() {
+    let (handle, rx) = Command::new(child())
+        .args(["sleep-then-exit", "300", "0"])
+        .spawn()
+        .await
+        .unwrap();
+    collect_all(rx).await;
+    let payload = handle.wait().await.unwrap();
+    assert_eq!(payload.reason, Some(ExitReason::Exited(0)));
+    assert!(payload.runtime >= Duration::from_millis(300), "{payload:?}");
 }
 
 #[tokio::test]
@@ -80,6 +95,36 @@ async fn spam_10k_lines_no_loss_with_default_capacity() {
     assert!(matches!(evs.last().unwrap(), ProcessEvent::Terminated(_)));
 }
 
+#[tokio::test]
+async fn overflowing_output_ring_reports_every_dropped_line() {
+    use nyanpasu_utils::process::OutputOverflow;
+
+    let (handle, rx) = Command::new(child())
+        .args(["spam-stdout", "1000"])
+        .event_channel_capacity(1)
+        .output_buffer_size(64)
+        .output_overflow(OutputOverflow::DropOldest)
+        .spawn()
+        .await
+        .unwrap();
+    handle.wait().await.unwrap();
+    let evs = collect_all(rx).await;
+    let delivered = evs
+        .iter()
+        .filter(|e| matches!(e, ProcessEvent::Stdout(_)))
+        .count();
+    let dropped: usize = evs
+        .iter()
+        .filter_map(|e| match e {
+            ProcessEvent::OutputDropped { count } => Some(*count),
+            _ => None,
+        })
+        .sum();
+    assert!(dropped > 0, "a 64-byte ring cannot hold 1000 lines");
+    assert_eq!(delivered + dropped, 1000);
+    assert!(matches!(evs.last().unwrap(), ProcessEvent::Terminated(_)));
+}
+
 #[tokio::test]
 async fn spawn_missing_program_is_error() {
     let err = Command::new("definitely-not-a-real-binary-42")
@@ -134,7 +179,10 @@ async fn spawn_timeout_kills_the_whole_tree() {
         }
 
         assert!(matches!(events.last(), Some(ProcessEvent::Terminated(_))));
-        handle.wait().await.unwrap();
+        assert_eq!(
+            handle.wait().await.unwrap().reason,
+            Some(ExitReason::TimedOut)
+        );
 
         let grandchild_pid = grandchild_pid.expect("grandchild pid event");
         let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
@@ -96,3 +96,135 @@ async fn write_stdin_after_termination_is_unavailable() {
         Err(ProcessError::StdinUnavailable)
     ));
 }
+
+async fn first_stdout_line(mut rx: tokio::sync::mpsc::Receiver<ProcessEvent>) -> Option<String> {
+    while let Some(event) = rx.recv().await {
+        if let ProcessEvent::Stdout(line) = event {
+            return Some(line);
+        }
+    }
+    None
+}
+
+#[tokio::test]
+async fn close_stdin_delivers_eof() {
+    let (handle, rx) = Command::new(child())
+        .args(["echo-stdin"])
+        .pipe_stdin(true)
+        .spawn()
+        .await
+        .unwrap();
+    handle.close_stdin().await.unwrap();
+    // read_line returns an empty line at EOF instead of blocking forever.
+    let line = tokio::time::timeout(Duration::from_secs(10), first_stdout_line(rx))
+        .await
+        .expect("child never saw EOF");
+    assert_eq!(line.unwrap().trim(), "echo:");
+    assert_eq!(handle.wait().await.unwrap().code, Some(0));
+    assert!(matches!(
+        handle.write_stdin(b"late").await,
+        Err(ProcessError::StdinUnavailable)
+    ));
+}
+
+#[tokio::test]
+async fn close_stdin_flushes_accepted_writes_first() {
+    let (handle, rx) = Command::new(child())
+        .args(["echo-stdin"])
+        .pipe_stdin(true)
+        .spawn()
+        .await
+        .unwrap();
+    handle.write_stdin(b"queued\n").await.unwrap();
+    handle.close_stdin().await.unwrap();
+    assert_eq!(first_stdout_line(rx).await.unwrap().trim(), "echo:queued");
+}
+
+#[tokio::test]
+async fn stdin_writer_streams_and_shuts_down() {
+    use tokio::io::AsyncWriteExt;
+
+    let (handle, rx) = Command::new(child())
+        .args(["echo-stdin"])
+        .pipe_stdin(true)
+        .spawn()
+        .await
+        .unwrap();
+    let mut writer = handle.stdin_writer();
+    let mut input: &[u8] = b"via-writer\n";
+    tokio::io::copy(&mut input, &mut writer).await.unwrap();
+    writer.shutdown().await.unwrap();
+    assert_eq!(
+        first_stdout_line(rx).await.unwrap().trim(),
+        "echo:via-writer"
+    );
+}
+
+#[tokio::test]
+async fn stdin_from_bytes_feeds_one_shot_output() {
+    let out = Command::new(child())
+        .args(["echo-stdin"])
+        .stdin_from(b"from-bytes\n".as_slice())
+        .output()
+        .await
+        .unwrap();
+    assert!(out.success());
+    assert_eq!(out.stdout.trim(), "echo:from-bytes");
+}
+
+#[tokio::test]
+async fn stdin_from_file_and_reader() {
+    use nyanpasu_utils::process::StdinSource;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("input.txt");
+    std::fs::write(&path, "from-file\n").unwrap();
+    let out = Command::new(child())
+        .args(["echo-stdin"])
+        .stdin_from(path.as_path())
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(out.stdout.trim(), "echo:from-file");
+
+    let out = Command::new(child())
+        .args(["echo-stdin"])
+        .stdin_from(StdinSource::reader(&b"from-reader\n"[..]))
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(out.stdout, "echo:from-reader\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn stdin_from_captures_are_byte_exact() {
+    let out = Command::new(child())
+        .args(["binary-stdout"])
+        .stdin_from(b"".as_slice())
+        .output_bytes()
+        .await
+        .unwrap();
+    assert_eq!(out.stdout, [0xFF, 0x00, 0xFE, 0x80, b'\n']);
+
+    // Invalid UTF-8 is replaced rather than failing the capture.
+    let out = Command::new(child())
+        .args(["binary-stdout"])
+        .stdin_from(b"".as_slice())
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(out.stdout, "\u{fffd}\0\u{fffd}\u{fffd}\n");
+}
+
+#[tokio::test]
+async fn stdin_from_missing_file_fails_spawn() {
+    let err = Command::new(child())
+        .args(["echo-stdin"])
+        .stdin_from(std::path::Path::new("/definitely/not/here.txt"))
+        .spawn()
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, ProcessError::Io(_)));
+}